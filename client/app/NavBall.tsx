@@ -19,7 +19,7 @@ function NavBall() {
   const logout = async () => {
     try {
       await axios.post(
-        `${process.env.NEXT_PUBLIC_API_URL}/api/auth/logout`,
+        `${process.env.NEXT_PUBLIC_API_URL}/api/v1/auth/logout`,
         {},
         {
           withCredentials: true,
@@ -68,13 +68,13 @@ const CompleteLogin: React.FC<CompleteLoginProps> = ({ authData }) => {
     try {
       // Send the credential to your backend to finalize the registration
       const response = await axios.post(
-        "http://localhost:8080/api/auth/login_complete",
+        "http://localhost:8080/api/v1/auth/login/complete",
         credential,
         {
           withCredentials: true,
         }
       );
-      createSession(response.data.userId, response.data.username);
+      createSession(response.data.user_id, response.data.username);
       alert("Login successful!");
     } catch (err) {
       console.error("Error loggin in:", err);
@@ -28,7 +28,7 @@ const StartLogin: React.FC<StartLoginProps> = ({ onStart }) => {
 
     try {
       const response = await axios.post<StartLoginResponse>(
-        "http://localhost:8080/api/auth/login",
+        "http://localhost:8080/api/v1/auth/login",
         {
           username,
         },
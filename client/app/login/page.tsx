@@ -35,7 +35,7 @@ export default function LoginPage() {
       const response = await axios.post<{
         publicKey: PublicKeyCredentialCreationOptionsJSON;
       }>(
-        `${process.env.NEXT_PUBLIC_API_URL}/api/auth/login`,
+        `${process.env.NEXT_PUBLIC_API_URL}/api/v1/auth/login`,
         { username },
         { withCredentials: true }
       );
@@ -97,13 +97,13 @@ export default function LoginPage() {
         try {
           // Send the credential to the backend to complete the login
           const response = await axios.post(
-            `${process.env.NEXT_PUBLIC_API_URL}/api/auth/login_complete`,
+            `${process.env.NEXT_PUBLIC_API_URL}/api/v1/auth/login/complete`,
             credential,
             {
               withCredentials: true,
             }
           );
-          createSession(response.data.userId, response.data.username);
+          createSession(response.data.user_id, response.data.username);
           alert("Login successful!");
           router.push("/");
         } catch (err) {
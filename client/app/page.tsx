@@ -14,7 +14,7 @@ const Home = async () => {
   let polls: Poll[] = [];
   try {
     const response = await axios.get(
-      `${process.env.NEXT_PUBLIC_API_URL}/api/polls/`
+      `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls`
     );
     polls = response.data;
   } catch (error) {
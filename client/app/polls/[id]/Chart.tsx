@@ -23,7 +23,7 @@ function Chart() {
     const fetchData = async () => {
       try {
         const response = await axios.get(
-          `${process.env.NEXT_PUBLIC_API_URL}/api/polls/` + urlId
+          `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls/${urlId}`
         );
         if (response.data && response.data.options) {
           extractPercentageArray(response.data.options);
@@ -5,32 +5,48 @@ interface PollOption {
   poll_id: string;
   option_text: string;
   votes_count: number | null;
+  weighted_votes: number | null;
+}
+interface ResultStats {
+  total_votes: number;
+  total_weight: number | null;
+  leaders: string[];
+  is_tie: boolean;
+  winner: string | null;
+  runners_up: string[];
+  margin: number | null;
 }
 interface PollResults {
   poll: string;
-  percentage: [string, number][];
   total_votes: number;
-  winner: string;
-  runnerUp: string;
+  stats: ResultStats;
   options: PollOption[];
 }
 function Live({ pollId }: { pollId: string }) {
   const [pollResults, setPollResults] = useState<PollResults | null>(null);
+  const [hiddenUntil, setHiddenUntil] = useState<string | null>(null);
   useEffect(() => {
     if (!pollId) return;
 
     const eventSource = new EventSource(
-      `${process.env.NEXT_PUBLIC_API_URL}/api/polls/${pollId}/results`
+      `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls/${pollId}/results`,
+      { withCredentials: true }
     );
 
     const handleMessage = (event: MessageEvent) => {
       const updatedData = JSON.parse(event.data);
       //   console.log(updatedData);
+      setHiddenUntil(null);
       setPollResults(updatedData);
       console.log("asdadasdas", updatedData?.total_votes);
     };
 
     eventSource.onmessage = handleMessage;
+    eventSource.addEventListener("hidden", (event) => {
+      const { results_visibility } = JSON.parse((event as MessageEvent).data);
+      setPollResults(null);
+      setHiddenUntil(results_visibility);
+    });
     eventSource.onerror = (error) => {
       console.error("Error with SSE:", error);
       eventSource.close();
@@ -46,11 +62,29 @@ function Live({ pollId }: { pollId: string }) {
           Live Results
         </h2>
         <div className="max-w-2xl mx-auto">
+          {hiddenUntil && (
+            <p className="text-gray-400 text-center">
+              {hiddenUntil === "after_vote"
+                ? "Results are shown once you have voted."
+                : hiddenUntil === "after_close"
+                ? "Results are shown once the poll closes."
+                : "Results are only visible to the poll owner."}
+            </p>
+          )}
           {pollResults?.options
-            .sort((a, b) => (b.votes_count ?? 0) - (a.votes_count ?? 0))
+            .sort((a, b) =>
+              pollResults.stats.total_weight !== null
+                ? (b.weighted_votes ?? 0) - (a.weighted_votes ?? 0)
+                : (b.votes_count ?? 0) - (a.votes_count ?? 0)
+            )
             .map((option) => {
+              const totalWeight = pollResults.stats.total_weight;
               const votePercentage =
-                pollResults.total_votes > 0
+                totalWeight !== null
+                  ? totalWeight > 0
+                    ? ((option.weighted_votes ?? 0) / totalWeight) * 100
+                    : 0
+                  : pollResults.total_votes > 0
                   ? ((option.votes_count ?? 0) / pollResults.total_votes) * 100
                   : 0;
               console.log(votePercentage);
@@ -67,6 +101,11 @@ function Live({ pollId }: { pollId: string }) {
                       <span className="text-sm font-semibold bg-gray-800 px-3 py-1 rounded-full">
                         {option.votes_count ?? 0} votes
                       </span>
+                      {totalWeight !== null && (
+                        <span className="text-sm font-semibold bg-gray-800 px-3 py-1 rounded-full">
+                          weight {option.weighted_votes ?? 0}
+                        </span>
+                      )}
                     </div>
                   </div>
                   <div className="h-3 bg-gray-800/50 border rounded-full overflow-hidden backdrop-blur-sm">
@@ -62,7 +62,7 @@ function Vote({
       setLoading(true);
       try {
         const response = await axios.post(
-          `${process.env.NEXT_PUBLIC_API_URL}/api/polls/${pollId}/vote`,
+          `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls/${pollId}/votes`,
           {
             option_id: voted,
           },
@@ -73,7 +73,7 @@ function Vote({
             withCredentials: true,
           }
         );
-        if (response.status !== 200) {
+        if (response.status !== 204) {
           throw new Error("Failed to vote");
         } else {
           alert("Voted successfully");
@@ -32,7 +32,7 @@ const Page = ({ params }: { params: Params }) => {
         const { id } = await params;
         setId(id);
         const { data } = await axios.get(
-          `${process.env.NEXT_PUBLIC_API_URL}/api/polls/${id}`
+          `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls/${id}`
         );
         setPollData(data);
       } catch (error) {
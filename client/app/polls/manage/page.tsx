@@ -21,7 +21,7 @@ function Page() {
     if (!userId) router.push("/login");
     const fetchPolls = async () => {
       const response = await axios.get(
-        `${process.env.NEXT_PUBLIC_API_URL}/api/polls/?creator=` + userId,
+        `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls?creator=` + userId,
         {
           withCredentials: true,
         }
@@ -76,9 +76,8 @@ const PollItem = ({ poll }: { poll: Poll }) => {
   const [isActive, setIsActive] = useState(poll.is_active);
   const resetVotes = async () => {
     try {
-      await axios.post(
-        `${process.env.NEXT_PUBLIC_API_URL}/api/polls/${poll.id}/reset`,
-        {},
+      await axios.delete(
+        `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls/${poll.id}/votes`,
         { withCredentials: true }
       );
       alert("Votes reset successfully");
@@ -91,9 +90,9 @@ const PollItem = ({ poll }: { poll: Poll }) => {
 
   const closePoll = async () => {
     try {
-      await axios.post(
-        `${process.env.NEXT_PUBLIC_API_URL}/api/polls/${poll.id}/close`,
-        {},
+      await axios.patch(
+        `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls/${poll.id}`,
+        { is_active: false },
         { withCredentials: true }
       );
       alert("Poll closed successfully");
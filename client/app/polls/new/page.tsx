@@ -57,7 +57,7 @@ const NewPollPage = () => {
     });
     try {
       const response = await axios.post(
-        `${process.env.NEXT_PUBLIC_API_URL}/api/polls/create`,
+        `${process.env.NEXT_PUBLIC_API_URL}/api/v1/polls`,
         {
           poll_name: pollName,
           poll_description: pollDescription,
@@ -0,0 +1,13 @@
+-- Keep options in the order the poll owner entered them
+ALTER TABLE poll_options ADD COLUMN position INT NOT NULL DEFAULT 0;
+
+-- Existing polls have no recorded order, number their options by id so the order is at least stable
+UPDATE poll_options
+SET position = numbered.position
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY poll_id ORDER BY id) - 1 AS position
+    FROM poll_options
+) AS numbered
+WHERE poll_options.id = numbered.id;
+
+CREATE INDEX idx_poll_options_position ON poll_options(poll_id, position);
@@ -0,0 +1,6 @@
+-- Optional presentation data for each option
+ALTER TABLE poll_options
+    ADD COLUMN description TEXT,
+    ADD COLUMN image_url TEXT,
+    ADD COLUMN image_asset_id UUID,
+    ADD COLUMN color TEXT;
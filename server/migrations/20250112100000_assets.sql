@@ -0,0 +1,20 @@
+-- Uploaded media, deduplicated by the SHA-256 of the original bytes
+CREATE TABLE assets (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content_hash TEXT NOT NULL UNIQUE,
+    content_type TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    width INT NOT NULL,
+    height INT NOT NULL,
+    storage_key TEXT NOT NULL,
+    thumbnail_key TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_assets_user_id ON assets(user_id);
+
+-- Option images can now point at an uploaded asset
+ALTER TABLE poll_options
+    ADD CONSTRAINT poll_options_image_asset_id_fkey
+    FOREIGN KEY (image_asset_id) REFERENCES assets(id) ON DELETE SET NULL;
@@ -0,0 +1,2 @@
+-- Secret ballot polls never reveal who voted for what, including in exports
+ALTER TABLE polls ADD COLUMN secret_ballot BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,8 @@
+-- Recurring polls: the latest instance of a series carries the rule and the time the
+-- scheduler should create its successor
+ALTER TABLE polls
+    ADD COLUMN recurrence TEXT,
+    ADD COLUMN next_run_at TIMESTAMPTZ,
+    ADD COLUMN previous_poll_id UUID REFERENCES polls(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_polls_next_run_at ON polls(next_run_at) WHERE recurrence IS NOT NULL;
@@ -0,0 +1,18 @@
+-- Append-only log of every vote cast and removed, used to chart results over time
+CREATE TABLE vote_events (
+    id BIGSERIAL PRIMARY KEY,
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    poll_option_id UUID NOT NULL REFERENCES poll_options(id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    action TEXT NOT NULL CHECK (action IN ('cast', 'removed', 'reset')),
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_vote_events_poll_id ON vote_events(poll_id, occurred_at);
+
+-- Votes cast before the log existed
+INSERT INTO vote_events (poll_id, poll_option_id, user_id, action, occurred_at)
+SELECT poll_options.poll_id, votes.poll_option_id, votes.user_id, 'cast',
+    COALESCE(votes.voted_at, CURRENT_TIMESTAMP)
+FROM votes
+JOIN poll_options ON votes.poll_option_id = poll_options.id;
@@ -0,0 +1,4 @@
+-- Who may see tallies: everyone, voters once they voted, everyone once closed, or the owner
+ALTER TABLE polls
+    ADD COLUMN results_visibility TEXT NOT NULL DEFAULT 'always'
+    CHECK (results_visibility IN ('always', 'after_vote', 'after_close', 'owner_only'));
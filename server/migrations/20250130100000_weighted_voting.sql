@@ -0,0 +1,29 @@
+-- Weighted polls decide by the summed weight of each option's voters instead of headcount
+ALTER TABLE polls ADD COLUMN weighted BOOLEAN NOT NULL DEFAULT FALSE;
+-- Weight of voters with neither a personal weight nor a role
+ALTER TABLE polls ADD COLUMN default_weight DOUBLE PRECISION NOT NULL DEFAULT 1
+    CHECK (default_weight >= 0);
+
+-- Named groups of voters (e.g. shareholders, team leads) and the weight they carry in a poll
+CREATE TABLE poll_roles (
+    id UUID PRIMARY KEY,
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    weight DOUBLE PRECISION NOT NULL CHECK (weight >= 0),
+    UNIQUE(poll_id, name)
+);
+
+-- Users the owner assigned a role or a personal weight, which takes precedence over the role's
+CREATE TABLE poll_participants (
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role_id UUID REFERENCES poll_roles(id) ON DELETE SET NULL,
+    weight DOUBLE PRECISION CHECK (weight >= 0),
+    PRIMARY KEY (poll_id, user_id)
+);
+
+-- Weight of the voter when the vote was cast; later changes do not alter past votes
+ALTER TABLE votes ADD COLUMN weight DOUBLE PRECISION NOT NULL DEFAULT 1;
+
+ALTER TABLE poll_options ADD COLUMN weighted_votes DOUBLE PRECISION NOT NULL DEFAULT 0;
+UPDATE poll_options SET weighted_votes = COALESCE(votes_count, 0);
@@ -0,0 +1,15 @@
+-- Only the poll's participants may vote; they then form the electorate quorums refer to
+ALTER TABLE polls ADD COLUMN participants_only BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Minimum number of voters for the result to count
+ALTER TABLE polls ADD COLUMN quorum_votes INT CHECK (quorum_votes > 0);
+-- Minimum share of the electorate, in percent, that has to vote
+ALTER TABLE polls ADD COLUMN quorum_percent DOUBLE PRECISION
+    CHECK (quorum_percent > 0 AND quorum_percent <= 100);
+
+-- Share of the votes the leading option needs to be decided
+ALTER TABLE polls ADD COLUMN pass_threshold TEXT NOT NULL DEFAULT 'plurality'
+    CHECK (pass_threshold IN ('plurality', 'majority', 'two_thirds', 'unanimous'));
+
+-- Close the poll as soon as its outcome can no longer change
+ALTER TABLE polls ADD COLUMN auto_close BOOLEAN NOT NULL DEFAULT FALSE;
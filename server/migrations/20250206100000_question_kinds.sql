@@ -0,0 +1,29 @@
+-- What a poll asks for: a pick among its options, a rating, a number or a short text
+ALTER TABLE polls ADD COLUMN kind TEXT NOT NULL DEFAULT 'choice'
+    CHECK (kind IN ('choice', 'scale', 'number', 'text'));
+-- Ratings run from 1 to scale_max
+ALTER TABLE polls ADD COLUMN scale_max INT CHECK (scale_max >= 2);
+ALTER TABLE polls ADD COLUMN number_min DOUBLE PRECISION;
+ALTER TABLE polls ADD COLUMN number_max DOUBLE PRECISION;
+ALTER TABLE polls ADD COLUMN text_max_length INT CHECK (text_max_length > 0);
+
+-- Ballots of scale and number polls
+CREATE TABLE numeric_answers (
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    value DOUBLE PRECISION NOT NULL,
+    answered_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (poll_id, user_id)
+);
+
+-- Ballots of text polls
+CREATE TABLE text_answers (
+    id UUID PRIMARY KEY,
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    answer TEXT NOT NULL,
+    answered_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (poll_id, user_id)
+);
+
+CREATE INDEX idx_text_answers_poll_id ON text_answers(poll_id, answered_at);
@@ -0,0 +1,26 @@
+-- Several questions answered as one ballot; each question is a poll of its own
+CREATE TABLE surveys (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE survey_questions (
+    survey_id UUID NOT NULL REFERENCES surveys(id) ON DELETE CASCADE,
+    poll_id UUID NOT NULL UNIQUE REFERENCES polls(id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    -- A response has to answer this question
+    required BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (survey_id, poll_id)
+);
+
+-- One row per submitted ballot; the answers themselves live with each question's poll
+CREATE TABLE survey_responses (
+    survey_id UUID NOT NULL REFERENCES surveys(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    submitted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (survey_id, user_id)
+);
@@ -0,0 +1,7 @@
+-- Ask a question only when the answer to an earlier question of the survey matches
+ALTER TABLE survey_questions ADD COLUMN condition_poll_id UUID REFERENCES polls(id) ON DELETE CASCADE;
+-- Matches a choice answer picking any of these options
+ALTER TABLE survey_questions ADD COLUMN condition_option_ids UUID[] NOT NULL DEFAULT '{}';
+-- Matches a scale or number answer within this range
+ALTER TABLE survey_questions ADD COLUMN condition_min DOUBLE PRECISION;
+ALTER TABLE survey_questions ADD COLUMN condition_max DOUBLE PRECISION;
@@ -0,0 +1,18 @@
+-- A presenter walking an audience through an ordered set of polls
+CREATE TABLE live_sessions (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    -- The poll the audience is shown; none before the presenter starts
+    current_poll_id UUID REFERENCES polls(id) ON DELETE SET NULL,
+    -- Whether the audience may see the live poll's results
+    results_revealed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE live_session_polls (
+    session_id UUID NOT NULL REFERENCES live_sessions(id) ON DELETE CASCADE,
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    PRIMARY KEY (session_id, poll_id)
+);
@@ -0,0 +1,12 @@
+-- When the poll was last closed; join codes stop working a while after
+ALTER TABLE polls ADD COLUMN closed_at TIMESTAMPTZ;
+UPDATE polls SET closed_at = CURRENT_TIMESTAMP WHERE NOT is_active;
+
+-- Short codes attendees type to reach a poll or a live session
+CREATE TABLE join_codes (
+    code TEXT PRIMARY KEY,
+    poll_id UUID UNIQUE REFERENCES polls(id) ON DELETE CASCADE,
+    session_id UUID UNIQUE REFERENCES live_sessions(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((poll_id IS NULL) <> (session_id IS NULL))
+);
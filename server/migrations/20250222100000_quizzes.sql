@@ -0,0 +1,12 @@
+-- Quiz polls score their voters: a correct vote earns quiz_points, plus up to
+-- quiz_bonus_points for answering within quiz_bonus_seconds of the poll going live
+ALTER TABLE polls ADD COLUMN quiz BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE polls ADD COLUMN quiz_points INT NOT NULL DEFAULT 100 CHECK (quiz_points >= 0);
+ALTER TABLE polls ADD COLUMN quiz_bonus_points INT CHECK (quiz_bonus_points > 0);
+ALTER TABLE polls ADD COLUMN quiz_bonus_seconds INT CHECK (quiz_bonus_seconds > 0);
+-- When the clock for the time bonus started; polls never shown live count from created_at
+ALTER TABLE polls ADD COLUMN quiz_started_at TIMESTAMPTZ;
+-- Voters only learn the correct options once the owner reveals them
+ALTER TABLE polls ADD COLUMN quiz_revealed BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE poll_options ADD COLUMN is_correct BOOLEAN NOT NULL DEFAULT FALSE;
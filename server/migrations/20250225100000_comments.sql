@@ -0,0 +1,34 @@
+-- Discussion on a poll; replies point at the comment they answer
+CREATE TABLE comments (
+    id UUID PRIMARY KEY,
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES comments(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    edited_at TIMESTAMPTZ,
+    -- A deleted comment that still has replies keeps its place in the thread, without a body
+    deleted_at TIMESTAMPTZ,
+    -- Moderation by the poll's owner
+    hidden BOOLEAN NOT NULL DEFAULT FALSE,
+    pinned BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX idx_comments_poll_id ON comments(poll_id, parent_id, created_at);
+CREATE INDEX idx_comments_parent_id ON comments(parent_id);
+
+-- Number of the poll's latest comment event. Events take their number under the lock of
+-- this row, so they commit in order and a reader never skips one that commits late.
+ALTER TABLE polls ADD COLUMN last_comment_event BIGINT NOT NULL DEFAULT 0;
+
+-- Append-only log of comment changes, replayed to the poll's live results stream
+CREATE TABLE comment_events (
+    -- Counts up from 1 within the poll
+    id BIGINT NOT NULL,
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    comment_id UUID NOT NULL,
+    action TEXT NOT NULL
+        CHECK (action IN ('created', 'edited', 'deleted', 'hidden', 'unhidden', 'pinned', 'unpinned')),
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (poll_id, id)
+);
@@ -0,0 +1,12 @@
+-- Emoji sent by a poll's audience while it is live; only counted, per time window
+CREATE TABLE reactions (
+    id BIGSERIAL PRIMARY KEY,
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    emoji TEXT NOT NULL,
+    reacted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_reactions_poll_id ON reactions(poll_id, reacted_at);
+-- Per-user rate limits count a user's recent reactions on a poll
+CREATE INDEX idx_reactions_user_id ON reactions(user_id, poll_id, reacted_at);
@@ -0,0 +1,21 @@
+-- Questions the audience of a live session asks its presenter
+CREATE TABLE audience_questions (
+    id UUID PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES live_sessions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Moderation by the presenter
+    answered_at TIMESTAMPTZ,
+    hidden BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX idx_audience_questions_session_id ON audience_questions(session_id);
+
+-- One upvote per user and question
+CREATE TABLE audience_question_votes (
+    question_id UUID NOT NULL REFERENCES audience_questions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    voted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (question_id, user_id)
+);
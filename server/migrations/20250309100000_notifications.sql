@@ -0,0 +1,32 @@
+-- Where a user wants to hear about polls, about what, and how often. Users without an
+-- email get no notifications.
+CREATE TABLE notification_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    email TEXT,
+    invites BOOLEAN NOT NULL DEFAULT TRUE,
+    closing_reminders BOOLEAN NOT NULL DEFAULT TRUE,
+    results_ready BOOLEAN NOT NULL DEFAULT TRUE,
+    -- How often pending notifications are batched into one message
+    digest TEXT NOT NULL DEFAULT 'hourly' CHECK (digest IN ('immediate', 'hourly', 'daily')),
+    last_digest_at TIMESTAMPTZ
+);
+
+-- Notifications waiting for the user's next digest; a user hears about each thing once
+CREATE TABLE notifications (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('invite', 'closing_reminder', 'results_ready')),
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMPTZ,
+    -- Failed sends are retried with backoff and given up after too many attempts, or at
+    -- once when the mail server rejects the message for good
+    attempts INT NOT NULL DEFAULT 0,
+    retry_at TIMESTAMPTZ,
+    last_error TEXT,
+    failed_at TIMESTAMPTZ,
+    UNIQUE (user_id, kind, poll_id)
+);
+
+CREATE INDEX idx_notifications_pending ON notifications(user_id)
+    WHERE sent_at IS NULL AND failed_at IS NULL;
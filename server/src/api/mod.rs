@@ -0,0 +1,2 @@
+pub mod openapi;
+pub mod request_id;
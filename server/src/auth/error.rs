@@ -1,8 +1,13 @@
 use actix_session::{SessionGetError, SessionInsertError};
 
-use actix_web::http::StatusCode;
+use actix_web::{http::StatusCode, HttpResponse};
+use log::error;
+use serde::Serialize;
 use thiserror::Error;
-use webauthn_rs::prelude::WebauthnError;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::{Uuid, WebauthnError};
+
+use crate::{api::request_id, media::storage::StorageError};
 
 // pub(crate) mod auth;
 // pub(crate) mod index;
@@ -33,13 +38,13 @@ pub enum Error {
     #[error("User has no credentials")]
     UserHasNoCredentials,
     #[error("Database error")]
-    DatabaseError(#[from] sqlx::Error),
-    #[error("Invalid poll options")]
-    InvalidPollOptions,
+    Database(#[from] sqlx::Error),
     #[error("Poll already closed")]
     PollClosed,
     #[error("Poll not found")]
     PollNotFound,
+    #[error("Poll option not found")]
+    OptionNotFound,
     #[error("User not authorized")]
     Unauthorized,
     #[error("User already voted")]
@@ -48,6 +53,182 @@ pub enum Error {
     VoteNotFound,
     #[error("User already exists")]
     UserExists,
+    #[error("Malformed request: {0}")]
+    InvalidRequest(String),
+    #[error("Validation failed")]
+    Validation(Vec<FieldError>),
+    #[error("Upload exceeds the limit of {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("Unsupported media type")]
+    UnsupportedMediaType,
+    #[error("Asset not found")]
+    AssetNotFound,
+    #[error("Storage error")]
+    Storage(#[from] StorageError),
+    #[error("Template not found")]
+    TemplateNotFound,
+    #[error("Export failed")]
+    Export(String),
+    #[error("Results of this poll are not visible to you yet")]
+    ResultsHidden,
+    #[error("Role not found")]
+    RoleNotFound,
+    #[error("Participant not found")]
+    ParticipantNotFound,
+    #[error("Only the poll's participants may vote")]
+    NotEligible,
+    #[error("Survey not found")]
+    SurveyNotFound,
+    #[error("This poll is a survey question and is answered through its survey")]
+    SurveyQuestion,
+    #[error("Live session not found")]
+    LiveSessionNotFound,
+    #[error("No poll is live in this session")]
+    NoLivePoll,
+    #[error("Join code not found")]
+    JoinCodeNotFound,
+    #[error("Join code has expired")]
+    JoinCodeExpired,
+    #[error("This poll is not a quiz")]
+    NotAQuiz,
+    #[error("Comment not found")]
+    CommentNotFound,
+    #[error("Too many requests, try again shortly")]
+    RateLimited,
+    #[error("Question not found")]
+    QuestionNotFound,
+    #[error("Webhook not found")]
+    WebhookNotFound,
+}
+
+/**
+A single field-level problem reported alongside [Error::Validation]
+*/
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/**
+JSON body returned for every error response
+*/
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable, machine-readable error code
+    pub code: &'static str,
+    /// Human readable description, safe to show to users
+    pub message: String,
+    /// Id of the request, also sent in the `X-Request-Id` header
+    pub request_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+}
+
+impl Error {
+    /**
+    Stable code for each variant. Clients match on this instead of the message.
+    */
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Unknown(_) => "WEBAUTHN_ERROR",
+            Error::SessionGet(_) | Error::SessionInsert(_) | Error::CorruptSession => {
+                "CORRUPT_SESSION"
+            }
+            Error::BadRequest(_) => "CREDENTIAL_REJECTED",
+            Error::UserNotFound => "USER_NOT_FOUND",
+            Error::UserHasNoCredentials => "USER_HAS_NO_CREDENTIALS",
+            Error::Database(_) => "DATABASE_ERROR",
+            Error::PollClosed => "POLL_CLOSED",
+            Error::PollNotFound => "POLL_NOT_FOUND",
+            Error::OptionNotFound => "OPTION_NOT_FOUND",
+            Error::Unauthorized => "UNAUTHORIZED",
+            Error::AlreadyVoted => "ALREADY_VOTED",
+            Error::VoteNotFound => "VOTE_NOT_FOUND",
+            Error::UserExists => "USER_EXISTS",
+            Error::InvalidRequest(_) => "INVALID_REQUEST",
+            Error::Validation(_) => "VALIDATION_FAILED",
+            Error::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            Error::UnsupportedMediaType => "UNSUPPORTED_MEDIA_TYPE",
+            Error::AssetNotFound => "ASSET_NOT_FOUND",
+            Error::Storage(_) => "STORAGE_ERROR",
+            Error::TemplateNotFound => "TEMPLATE_NOT_FOUND",
+            Error::Export(_) => "EXPORT_FAILED",
+            Error::ResultsHidden => "RESULTS_HIDDEN",
+            Error::RoleNotFound => "ROLE_NOT_FOUND",
+            Error::ParticipantNotFound => "PARTICIPANT_NOT_FOUND",
+            Error::NotEligible => "NOT_ELIGIBLE",
+            Error::SurveyNotFound => "SURVEY_NOT_FOUND",
+            Error::SurveyQuestion => "SURVEY_QUESTION",
+            Error::LiveSessionNotFound => "LIVE_SESSION_NOT_FOUND",
+            Error::NoLivePoll => "NO_LIVE_POLL",
+            Error::JoinCodeNotFound => "JOIN_CODE_NOT_FOUND",
+            Error::JoinCodeExpired => "JOIN_CODE_EXPIRED",
+            Error::NotAQuiz => "NOT_A_QUIZ",
+            Error::CommentNotFound => "COMMENT_NOT_FOUND",
+            Error::RateLimited => "RATE_LIMITED",
+            Error::QuestionNotFound => "QUESTION_NOT_FOUND",
+            Error::WebhookNotFound => "WEBHOOK_NOT_FOUND",
+        }
+    }
+
+    /**
+    Builds the response body. Internal failures are logged here and replaced by a
+    generic message so driver or webauthn details never reach the client.
+    */
+    pub fn body(&self) -> ErrorBody {
+        let request_id = request_id::current();
+        let message = match self {
+            Error::Unknown(e) => {
+                error!("[{:?}] webauthn error: {:?}", request_id, e);
+                "Internal server error".to_string()
+            }
+            Error::Database(e) => {
+                error!("[{:?}] database error: {:?}", request_id, e);
+                "Internal server error".to_string()
+            }
+            Error::Storage(e) => {
+                error!("[{:?}] storage error: {:?}", request_id, e);
+                "Internal server error".to_string()
+            }
+            Error::Export(e) => {
+                error!("[{:?}] export error: {}", request_id, e);
+                "Internal server error".to_string()
+            }
+            Error::SessionGet(e) => {
+                error!("[{:?}] session read error: {:?}", request_id, e);
+                self.to_string()
+            }
+            Error::SessionInsert(e) => {
+                error!("[{:?}] session write error: {:?}", request_id, e);
+                self.to_string()
+            }
+            Error::BadRequest(e) => {
+                error!("[{:?}] credential rejected: {:?}", request_id, e);
+                self.to_string()
+            }
+            _ => self.to_string(),
+        };
+        let details = match self {
+            Error::Validation(details) => details.clone(),
+            _ => Vec::new(),
+        };
+        ErrorBody {
+            code: self.code(),
+            message,
+            request_id,
+            details,
+        }
+    }
 }
 
 impl actix_web::ResponseError for Error {
@@ -60,14 +241,256 @@ impl actix_web::ResponseError for Error {
             Error::BadRequest(_) => StatusCode::BAD_REQUEST,
             Error::UserNotFound => StatusCode::NOT_FOUND,
             Error::UserHasNoCredentials => StatusCode::BAD_REQUEST,
-            Error::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            Error::InvalidPollOptions => StatusCode::BAD_REQUEST,
+            Error::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::PollClosed => StatusCode::BAD_REQUEST,
             Error::PollNotFound => StatusCode::NOT_FOUND,
+            Error::OptionNotFound => StatusCode::NOT_FOUND,
             Error::Unauthorized => StatusCode::UNAUTHORIZED,
             Error::AlreadyVoted => StatusCode::BAD_REQUEST,
             Error::VoteNotFound => StatusCode::NOT_FOUND,
             Error::UserExists => StatusCode::BAD_REQUEST,
+            Error::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::AssetNotFound => StatusCode::NOT_FOUND,
+            Error::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::TemplateNotFound => StatusCode::NOT_FOUND,
+            Error::Export(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::ResultsHidden => StatusCode::FORBIDDEN,
+            Error::RoleNotFound => StatusCode::NOT_FOUND,
+            Error::ParticipantNotFound => StatusCode::NOT_FOUND,
+            Error::NotEligible => StatusCode::FORBIDDEN,
+            Error::SurveyNotFound => StatusCode::NOT_FOUND,
+            Error::SurveyQuestion => StatusCode::BAD_REQUEST,
+            Error::LiveSessionNotFound => StatusCode::NOT_FOUND,
+            Error::NoLivePoll => StatusCode::BAD_REQUEST,
+            Error::JoinCodeNotFound => StatusCode::NOT_FOUND,
+            Error::JoinCodeExpired => StatusCode::GONE,
+            Error::NotAQuiz => StatusCode::BAD_REQUEST,
+            Error::CommentNotFound => StatusCode::NOT_FOUND,
+            Error::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Error::QuestionNotFound => StatusCode::NOT_FOUND,
+            Error::WebhookNotFound => StatusCode::NOT_FOUND,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.body())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    fn cases() -> Vec<(Error, StatusCode, &'static str)> {
+        vec![
+            (
+                Error::Unknown(WebauthnError::Configuration),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "WEBAUTHN_ERROR",
+            ),
+            (
+                Error::CorruptSession,
+                StatusCode::BAD_REQUEST,
+                "CORRUPT_SESSION",
+            ),
+            (
+                Error::BadRequest(WebauthnError::MismatchedChallenge),
+                StatusCode::BAD_REQUEST,
+                "CREDENTIAL_REJECTED",
+            ),
+            (Error::UserNotFound, StatusCode::NOT_FOUND, "USER_NOT_FOUND"),
+            (
+                Error::UserHasNoCredentials,
+                StatusCode::BAD_REQUEST,
+                "USER_HAS_NO_CREDENTIALS",
+            ),
+            (
+                Error::Database(sqlx::Error::PoolTimedOut),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "DATABASE_ERROR",
+            ),
+            (Error::PollClosed, StatusCode::BAD_REQUEST, "POLL_CLOSED"),
+            (Error::PollNotFound, StatusCode::NOT_FOUND, "POLL_NOT_FOUND"),
+            (
+                Error::OptionNotFound,
+                StatusCode::NOT_FOUND,
+                "OPTION_NOT_FOUND",
+            ),
+            (
+                Error::Unauthorized,
+                StatusCode::UNAUTHORIZED,
+                "UNAUTHORIZED",
+            ),
+            (
+                Error::AlreadyVoted,
+                StatusCode::BAD_REQUEST,
+                "ALREADY_VOTED",
+            ),
+            (Error::VoteNotFound, StatusCode::NOT_FOUND, "VOTE_NOT_FOUND"),
+            (Error::UserExists, StatusCode::BAD_REQUEST, "USER_EXISTS"),
+            (
+                Error::InvalidRequest("bad json".to_string()),
+                StatusCode::BAD_REQUEST,
+                "INVALID_REQUEST",
+            ),
+            (
+                Error::Validation(vec![]),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "VALIDATION_FAILED",
+            ),
+            (
+                Error::PayloadTooLarge(1024),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "PAYLOAD_TOO_LARGE",
+            ),
+            (
+                Error::UnsupportedMediaType,
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "UNSUPPORTED_MEDIA_TYPE",
+            ),
+            (
+                Error::AssetNotFound,
+                StatusCode::NOT_FOUND,
+                "ASSET_NOT_FOUND",
+            ),
+            (
+                Error::Storage(StorageError::NotFound),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "STORAGE_ERROR",
+            ),
+            (
+                Error::TemplateNotFound,
+                StatusCode::NOT_FOUND,
+                "TEMPLATE_NOT_FOUND",
+            ),
+            (
+                Error::Export("writer failed".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "EXPORT_FAILED",
+            ),
+            (
+                Error::ResultsHidden,
+                StatusCode::FORBIDDEN,
+                "RESULTS_HIDDEN",
+            ),
+            (Error::RoleNotFound, StatusCode::NOT_FOUND, "ROLE_NOT_FOUND"),
+            (
+                Error::ParticipantNotFound,
+                StatusCode::NOT_FOUND,
+                "PARTICIPANT_NOT_FOUND",
+            ),
+            (Error::NotEligible, StatusCode::FORBIDDEN, "NOT_ELIGIBLE"),
+            (
+                Error::SurveyNotFound,
+                StatusCode::NOT_FOUND,
+                "SURVEY_NOT_FOUND",
+            ),
+            (
+                Error::SurveyQuestion,
+                StatusCode::BAD_REQUEST,
+                "SURVEY_QUESTION",
+            ),
+            (
+                Error::LiveSessionNotFound,
+                StatusCode::NOT_FOUND,
+                "LIVE_SESSION_NOT_FOUND",
+            ),
+            (Error::NoLivePoll, StatusCode::BAD_REQUEST, "NO_LIVE_POLL"),
+            (
+                Error::JoinCodeNotFound,
+                StatusCode::NOT_FOUND,
+                "JOIN_CODE_NOT_FOUND",
+            ),
+            (
+                Error::JoinCodeExpired,
+                StatusCode::GONE,
+                "JOIN_CODE_EXPIRED",
+            ),
+            (Error::NotAQuiz, StatusCode::BAD_REQUEST, "NOT_A_QUIZ"),
+            (
+                Error::CommentNotFound,
+                StatusCode::NOT_FOUND,
+                "COMMENT_NOT_FOUND",
+            ),
+            (
+                Error::RateLimited,
+                StatusCode::TOO_MANY_REQUESTS,
+                "RATE_LIMITED",
+            ),
+            (
+                Error::QuestionNotFound,
+                StatusCode::NOT_FOUND,
+                "QUESTION_NOT_FOUND",
+            ),
+            (
+                Error::WebhookNotFound,
+                StatusCode::NOT_FOUND,
+                "WEBHOOK_NOT_FOUND",
+            ),
+        ]
+    }
+
+    #[test]
+    fn every_variant_has_its_status_and_code() {
+        for (error, status, code) in cases() {
+            assert_eq!(error.status_code(), status, "{:?}", error);
+            assert_eq!(error.code(), code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn codes_are_unique() {
+        let mut codes: Vec<&str> = cases().iter().map(|(error, _, _)| error.code()).collect();
+        codes.sort();
+        let count = codes.len();
+        codes.dedup();
+        assert_eq!(codes.len(), count);
+    }
+
+    #[test]
+    fn internal_errors_are_not_leaked() {
+        for error in [
+            Error::Unknown(WebauthnError::Configuration),
+            Error::Database(sqlx::Error::Protocol(
+                "relation \"polls\" is locked".to_string(),
+            )),
+            Error::Storage(StorageError::Io(std::io::Error::other(
+                "/srv/media is full",
+            ))),
+            Error::Export("xlsx writer: sheet name too long".to_string()),
+        ] {
+            let body = error.body();
+            assert_eq!(body.message, "Internal server error", "{:?}", error);
+        }
+        let body = Error::BadRequest(WebauthnError::MismatchedChallenge).body();
+        assert_eq!(body.message, "Bad request");
+    }
+
+    #[actix_web::test]
+    async fn responses_carry_the_json_body() {
+        let error = Error::Validation(vec![FieldError::new("title", "must not be blank")]);
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "code": "VALIDATION_FAILED",
+                "message": "Validation failed",
+                "request_id": null,
+                "details": [{ "field": "title", "message": "must not be blank" }],
+            })
+        );
+
+        let body = Error::PollNotFound.body();
+        assert!(body.details.is_empty());
+        assert_eq!(body.message, "Poll not found");
+    }
 }
@@ -1,17 +1,40 @@
-use super::error::WebResult;
+use super::error::{ErrorBody, WebResult};
 use super::validate_session::validate_session;
 use actix_session::Session;
 use actix_web::HttpResponse;
 use log::warn;
+use serde::Serialize;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
 
+#[derive(Serialize, ToSchema)]
+pub struct UserResponse {
+    pub user_id: Uuid,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/me",
+    tag = "auth",
+    responses(
+        (status = 200, description = "The logged in user", body = UserResponse),
+        (status = 401, description = "No active session", body = ErrorBody),
+    )
+)]
 pub async fn get_user(session: Session) -> WebResult<HttpResponse> {
     warn!("its coming here. {:?}", session.entries());
     let user_id = validate_session(&session)?;
 
-    Ok(HttpResponse::Ok().json(user_id))
+    Ok(HttpResponse::Ok().json(UserResponse { user_id }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/logout",
+    tag = "auth",
+    responses((status = 204, description = "Session cleared"))
+)]
 pub async fn logout(session: Session) -> WebResult<HttpResponse> {
     session.purge();
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::NoContent().finish())
 }
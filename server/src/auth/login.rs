@@ -1,15 +1,15 @@
-use crate::db::auth::{get_passkey, get_user_id, get_username};
+use crate::db::auth::{get_passkey, get_user_id, get_username, update_credentials};
 
-use super::error::{Error, WebResult};
+use super::error::{Error, ErrorBody, WebResult};
 use super::startup::UserData;
 use actix_session::Session;
 use actix_web::web::{Data, Json};
 use actix_web::HttpResponse;
 use log::{error, info};
-use serde::Deserialize;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use tokio::sync::Mutex;
+use utoipa::ToSchema;
 
 /*
  * Webauthn RS auth handlers.
@@ -19,11 +19,28 @@ use tokio::sync::Mutex;
 // 1. Import the prelude - this contains everything needed for the server to function.
 use webauthn_rs::prelude::*;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RegisterRequest {
     username: String,
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct LoginResponse {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/login",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "WebAuthn request challenge", body = Object),
+        (status = 400, description = "User has no credentials", body = ErrorBody),
+        (status = 404, description = "User not found", body = ErrorBody),
+    )
+)]
 pub async fn start_authentication(
     pool: Data<PgPool>,
     req: Json<RegisterRequest>,
@@ -83,10 +100,19 @@ pub async fn start_authentication(
 // a success. If the browser does not complete this call, or *any* error occurs,
 // this is an authentication failure.
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/login/complete",
+    tag = "auth",
+    request_body(content = Object, description = "WebAuthn public key credential"),
+    responses(
+        (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 400, description = "Credential rejected or session corrupt", body = ErrorBody),
+    )
+)]
 pub async fn finish_authentication(
     auth: Json<PublicKeyCredential>,
     session: Session,
-    webauthn_users: Data<Mutex<UserData>>,
     webauthn: Data<Webauthn>,
     pool: Data<PgPool>,
 ) -> WebResult<HttpResponse> {
@@ -117,14 +143,14 @@ pub async fn finish_authentication(
     //         })
     //     })
     //     .ok_or(Error::UserHasNoCredentials)?;
+    update_credentials(&pool, user_unique_id, &auth_result).await?;
 
     let username = get_username(&pool, user_unique_id).await?;
     session.insert("user_id", user_unique_id).unwrap();
     // println!("{:?}", session.entries());
     info!("Authentication Successful!");
-    let res = json!({
-        "userId": user_unique_id,
-        "username": username
-    });
-    Ok(HttpResponse::Ok().json(res))
-}
\ No newline at end of file
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        user_id: user_unique_id,
+        username,
+    }))
+}
@@ -1,20 +1,31 @@
-use super::error::{Error, WebResult};
+use super::error::{Error, ErrorBody, WebResult};
 use super::startup::UserData;
 use crate::db::auth::{get_user_id, store_passkey, store_username};
 use actix_session::Session;
-use actix_web::web::{self, Data, Json, Path};
+use actix_web::web::{Data, Json};
 use actix_web::HttpResponse;
 use log::{error, info};
 use serde::Deserialize;
 use sqlx::{types::Uuid, PgPool};
 use tokio::sync::Mutex;
+use utoipa::ToSchema;
 use webauthn_rs::prelude::*;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RegisterRequest {
     username: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "WebAuthn creation challenge", body = Object),
+        (status = 400, description = "User already exists", body = ErrorBody),
+    )
+)]
 pub async fn start_register(
     req: Json<RegisterRequest>,
     session: Session,
@@ -26,8 +37,8 @@ pub async fn start_register(
     let username = req.username.clone();
     match get_user_id(&pool, &username).await {
         Ok(_) => return Err(Error::UserExists),
-        Err(e) if matches!(e, sqlx::Error::RowNotFound) => false,
-        Err(e) => return Err(Error::DatabaseError(e)),
+        Err(sqlx::Error::RowNotFound) => false,
+        Err(e) => return Err(Error::Database(e)),
     };
     match webauthn_users.lock().await.name_to_id.get(&username) {
         Some(_) => return Err(Error::UserExists),
@@ -83,6 +94,16 @@ pub async fn start_register(
 // on their device. Now we have the registration options sent to us, and we need
 // to verify these and persist them.
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/register/complete",
+    tag = "auth",
+    request_body(content = Object, description = "WebAuthn registration credential"),
+    responses(
+        (status = 204, description = "User registered"),
+        (status = 400, description = "Credential rejected or session corrupt", body = ErrorBody),
+    )
+)]
 pub async fn finish_register(
     req: Json<RegisterPublicKeyCredential>,
     session: Session,
@@ -114,11 +135,11 @@ pub async fn finish_register(
 
     store_username(&pool, &username, user_unique_id)
         .await
-        .map_err(Error::DatabaseError)?;
+        .map_err(Error::Database)?;
 
     store_passkey(&pool, &sk, user_unique_id)
         .await
-        .map_err(Error::DatabaseError)?;
+        .map_err(Error::Database)?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::NoContent().finish())
 }
@@ -1,10 +1,12 @@
 use super::error::Error;
 use actix_session::Session;
-use actix_web::{error::ErrorBadRequest, HttpResponse};
 use log::warn;
 use webauthn_rs::prelude::*;
 pub fn validate_session(session: &Session) -> Result<Uuid, Error> {
-    warn!("\x1b[32mSession before voting: {:?}\x1b[0m", session.entries());
+    warn!(
+        "\x1b[32mSession before voting: {:?}\x1b[0m",
+        session.entries()
+    );
     let user_id: Option<Uuid> = session.get("user_id").unwrap_or(None);
 
     let id = match user_id {
@@ -16,6 +18,17 @@ pub fn validate_session(session: &Session) -> Result<Uuid, Error> {
         }
         None => Err(Error::Unauthorized),
     }?;
-    warn!("\x1b[32mSession after voting: {:?}\x1b[0m", session.entries());
+    warn!(
+        "\x1b[32mSession after voting: {:?}\x1b[0m",
+        session.entries()
+    );
     Ok(id)
 }
+
+/**
+The logged in user, if any, for routes that also serve anonymous visitors. Unlike
+[validate_session] this neither fails nor renews the session.
+*/
+pub fn session_user(session: &Session) -> Option<Uuid> {
+    session.get("user_id").unwrap_or(None)
+}
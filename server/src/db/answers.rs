@@ -0,0 +1,248 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgExecutor, PgPool, Row};
+use utoipa::ToSchema;
+
+pub async fn insert_numeric_answer<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    user_id: Uuid,
+    value: f64,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO numeric_answers (poll_id, user_id, value) VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .bind(value)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn insert_text_answer<'e, E>(
+    executor: E,
+    answer_id: Uuid,
+    poll_id: Uuid,
+    user_id: Uuid,
+    answer: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO text_answers (id, poll_id, user_id, answer) VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(answer_id)
+    .bind(poll_id)
+    .bind(user_id)
+    .bind(answer)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/**
+Removes the user's answer, whatever its kind. Returns whether there was one.
+*/
+pub async fn delete_user_answer<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
+        r#"
+        WITH removed_numeric AS (
+            DELETE FROM numeric_answers WHERE poll_id = $1 AND user_id = $2 RETURNING 1
+        ), removed_text AS (
+            DELETE FROM text_answers WHERE poll_id = $1 AND user_id = $2 RETURNING 1
+        )
+        SELECT (SELECT COUNT(*) FROM removed_numeric) + (SELECT COUNT(*) FROM removed_text)
+            AS removed
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(row.get::<i64, _>("removed") > 0)
+}
+
+pub async fn delete_answers<'e, E>(executor: E, poll_id: Uuid) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        WITH removed_numeric AS (
+            DELETE FROM numeric_answers WHERE poll_id = $1
+        )
+        DELETE FROM text_answers WHERE poll_id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct NumericStats {
+    pub count: i64,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+pub async fn get_numeric_stats<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+) -> Result<NumericStats, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let stats: NumericStats = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) AS count, AVG(value) AS mean,
+               percentile_cont(0.5) WITHIN GROUP (ORDER BY value) AS median,
+               MIN(value) AS min, MAX(value) AS max
+        FROM numeric_answers
+        WHERE poll_id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(stats)
+}
+
+/**
+How many answers gave each distinct value, lowest value first
+*/
+pub async fn get_value_counts<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+) -> Result<Vec<(f64, i64)>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let rows = sqlx::query(
+        r#"
+        SELECT value, COUNT(*) AS count
+        FROM numeric_answers
+        WHERE poll_id = $1
+        GROUP BY value
+        ORDER BY value
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("value"), row.get("count")))
+        .collect())
+}
+
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct WordCount {
+    pub word: String,
+    /// Occurrences across all answers
+    pub count: i64,
+}
+
+/**
+Most frequent words of the poll's text answers, case-insensitively. Words shorter than
+`min_length` and the `ignored` ones are skipped.
+*/
+pub async fn get_word_counts<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    min_length: i32,
+    ignored: &[&str],
+    limit: i64,
+) -> Result<Vec<WordCount>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let words: Vec<WordCount> = sqlx::query_as(
+        r#"
+        SELECT word, COUNT(*) AS count
+        FROM text_answers,
+            regexp_split_to_table(lower(answer), '[^[:alnum:]'']+') AS word
+        WHERE poll_id = $1 AND char_length(word) >= $2 AND NOT (word = ANY($3))
+        GROUP BY word
+        ORDER BY count DESC, word
+        LIMIT $4
+        "#,
+    )
+    .bind(poll_id)
+    .bind(min_length)
+    .bind(ignored)
+    .bind(limit)
+    .fetch_all(executor)
+    .await?;
+    Ok(words)
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct TextAnswer {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub answer: String,
+    pub answered_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub async fn count_text_answers<'e, E>(executor: E, poll_id: Uuid) -> Result<i64, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS count FROM text_answers WHERE poll_id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(row.get("count"))
+}
+
+/**
+One page of the poll's text answers, newest first
+*/
+pub async fn get_text_answers(
+    pool: &PgPool,
+    poll_id: Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<TextAnswer>, sqlx::Error> {
+    let answers: Vec<TextAnswer> = sqlx::query_as(
+        r#"
+        SELECT text_answers.id, text_answers.user_id, users.username, text_answers.answer,
+               text_answers.answered_at
+        FROM text_answers
+        JOIN users ON text_answers.user_id = users.id
+        WHERE text_answers.poll_id = $1
+        ORDER BY text_answers.answered_at DESC, text_answers.id
+        LIMIT $2 OFFSET $3
+        "#,
+    )
+    .bind(poll_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    Ok(answers)
+}
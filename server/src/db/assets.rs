@@ -0,0 +1,88 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgPool};
+
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct Asset {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub content_hash: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub width: i32,
+    pub height: i32,
+    pub storage_key: String,
+    pub thumbnail_key: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub async fn get_asset(pool: &PgPool, asset_id: Uuid) -> Result<Asset, sqlx::Error> {
+    let asset: Asset = sqlx::query_as(
+        r#"
+        SELECT * FROM assets WHERE id = $1
+        "#,
+    )
+    .bind(asset_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(asset)
+}
+
+pub async fn get_asset_by_hash(
+    pool: &PgPool,
+    content_hash: &str,
+) -> Result<Option<Asset>, sqlx::Error> {
+    let asset: Option<Asset> = sqlx::query_as(
+        r#"
+        SELECT * FROM assets WHERE content_hash = $1
+        "#,
+    )
+    .bind(content_hash)
+    .fetch_optional(pool)
+    .await?;
+    Ok(asset)
+}
+
+pub struct NewAsset<'a> {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub content_hash: &'a str,
+    pub content_type: &'a str,
+    pub size_bytes: i64,
+    pub width: i32,
+    pub height: i32,
+    pub storage_key: &'a str,
+    pub thumbnail_key: &'a str,
+}
+
+/**
+Inserts an asset row. When another upload with the same hash won the race, the
+existing row is returned instead.
+*/
+pub async fn insert_asset(pool: &PgPool, asset: &NewAsset<'_>) -> Result<Asset, sqlx::Error> {
+    let inserted: Option<Asset> = sqlx::query_as(
+        r#"
+        INSERT INTO assets
+            (id, user_id, content_hash, content_type, size_bytes, width, height, storage_key, thumbnail_key)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (content_hash) DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(asset.id)
+    .bind(asset.user_id)
+    .bind(asset.content_hash)
+    .bind(asset.content_type)
+    .bind(asset.size_bytes)
+    .bind(asset.width)
+    .bind(asset.height)
+    .bind(asset.storage_key)
+    .bind(asset.thumbnail_key)
+    .fetch_optional(pool)
+    .await?;
+    match inserted {
+        Some(asset) => Ok(asset),
+        None => get_asset_by_hash(pool, asset.content_hash)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound),
+    }
+}
@@ -0,0 +1,306 @@
+use sqlx::{types::Uuid, PgExecutor, PgPool};
+
+/**
+A question of a live session's audience with its upvotes, as loaded for one reader
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct AudienceQuestion {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub body: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub answered_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub hidden: bool,
+    pub votes: i64,
+    /// Whether the reader upvoted it
+    pub voted: bool,
+}
+
+pub async fn create_question<'e, E>(
+    executor: E,
+    question_id: Uuid,
+    session_id: Uuid,
+    user_id: Uuid,
+    body: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO audience_questions (id, session_id, user_id, body)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(question_id)
+    .bind(session_id)
+    .bind(user_id)
+    .bind(body)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/**
+Loads a question of `session_id` as `viewer` sees it. Hidden questions are only found
+for the presenter and their author.
+*/
+pub async fn get_question(
+    pool: &PgPool,
+    session_id: Uuid,
+    question_id: Uuid,
+    viewer: Option<Uuid>,
+    presenter: bool,
+) -> Result<Option<AudienceQuestion>, sqlx::Error> {
+    let question: Option<AudienceQuestion> = sqlx::query_as(
+        r#"
+        SELECT audience_questions.id, audience_questions.session_id, audience_questions.user_id,
+               users.username, audience_questions.body, audience_questions.created_at,
+               audience_questions.answered_at, audience_questions.hidden,
+               (SELECT COUNT(*) FROM audience_question_votes
+                WHERE question_id = audience_questions.id) AS votes,
+               EXISTS(SELECT 1 FROM audience_question_votes
+                      WHERE question_id = audience_questions.id AND user_id = $3) AS voted
+        FROM audience_questions
+        JOIN users ON audience_questions.user_id = users.id
+        WHERE audience_questions.id = $2 AND audience_questions.session_id = $1
+            AND (NOT audience_questions.hidden OR $4 OR audience_questions.user_id = $3)
+        "#,
+    )
+    .bind(session_id)
+    .bind(question_id)
+    .bind(viewer)
+    .bind(presenter)
+    .fetch_optional(pool)
+    .await?;
+    Ok(question)
+}
+
+/**
+The session's questions as `viewer` sees them: open questions before answered ones, each
+group by most upvotes, then oldest first.
+*/
+pub async fn list_questions<'e, E>(
+    executor: E,
+    session_id: Uuid,
+    viewer: Option<Uuid>,
+    presenter: bool,
+) -> Result<Vec<AudienceQuestion>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let questions: Vec<AudienceQuestion> = sqlx::query_as(
+        r#"
+        SELECT audience_questions.id, audience_questions.session_id, audience_questions.user_id,
+               users.username, audience_questions.body, audience_questions.created_at,
+               audience_questions.answered_at, audience_questions.hidden,
+               (SELECT COUNT(*) FROM audience_question_votes
+                WHERE question_id = audience_questions.id) AS votes,
+               EXISTS(SELECT 1 FROM audience_question_votes
+                      WHERE question_id = audience_questions.id AND user_id = $2) AS voted
+        FROM audience_questions
+        JOIN users ON audience_questions.user_id = users.id
+        WHERE audience_questions.session_id = $1
+            AND (NOT audience_questions.hidden OR $3 OR audience_questions.user_id = $2)
+        ORDER BY audience_questions.answered_at IS NOT NULL, votes DESC,
+            audience_questions.created_at, audience_questions.id
+        "#,
+    )
+    .bind(session_id)
+    .bind(viewer)
+    .bind(presenter)
+    .fetch_all(executor)
+    .await?;
+    Ok(questions)
+}
+
+pub async fn delete_question(pool: &PgPool, question_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        DELETE FROM audience_questions WHERE id = $1
+        "#,
+    )
+    .bind(question_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/**
+Upvotes a question. Returns `false` when the user already upvoted it.
+*/
+pub async fn upvote<'e, E>(
+    executor: E,
+    question_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let result = sqlx::query(
+        r#"
+        INSERT INTO audience_question_votes (question_id, user_id) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(question_id)
+    .bind(user_id)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/**
+Withdraws a user's upvote. Returns `false` when there was none.
+*/
+pub async fn remove_upvote(
+    pool: &PgPool,
+    question_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM audience_question_votes WHERE question_id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(question_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/**
+Marks a question answered, keeping the time it first was, or open again.
+*/
+pub async fn set_answered<'e, E>(
+    executor: E,
+    question_id: Uuid,
+    answered: bool,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        UPDATE audience_questions
+        SET answered_at = CASE WHEN $2 THEN COALESCE(answered_at, CURRENT_TIMESTAMP) END
+        WHERE id = $1
+        "#,
+    )
+    .bind(question_id)
+    .bind(answered)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_hidden<'e, E>(
+    executor: E,
+    question_id: Uuid,
+    hidden: bool,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        UPDATE audience_questions SET hidden = $2 WHERE id = $1
+        "#,
+    )
+    .bind(question_id)
+    .bind(hidden)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{connect, insert_user};
+    use sqlx::{Acquire, PgConnection};
+
+    async fn insert_session(conn: &mut PgConnection, presenter: Uuid) -> Uuid {
+        let session_id = Uuid::new_v4();
+        sqlx::query("INSERT INTO live_sessions (id, user_id, title) VALUES ($1, $2, 'Q&A')")
+            .bind(session_id)
+            .bind(presenter)
+            .execute(conn)
+            .await
+            .unwrap();
+        session_id
+    }
+
+    /// A question asked `minutes` after the session's first one
+    async fn ask(conn: &mut PgConnection, session_id: Uuid, user_id: Uuid, minutes: i32) -> Uuid {
+        let question_id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO audience_questions (id, session_id, user_id, body, created_at)
+            VALUES ($1, $2, $3, 'Why?', '2025-03-03T12:00:00Z'::TIMESTAMPTZ + make_interval(mins => $4))
+            "#,
+        )
+        .bind(question_id)
+        .bind(session_id)
+        .bind(user_id)
+        .bind(minutes)
+        .execute(conn)
+        .await
+        .unwrap();
+        question_id
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the database of DATABASE_URL"]
+    async fn open_questions_come_first_by_upvotes_then_age() {
+        let mut conn = connect().await;
+        let mut tx = conn.begin().await.unwrap();
+        let presenter = insert_user(&mut tx, "presenter").await;
+        let mut audience = vec![];
+        for _ in 0..3 {
+            audience.push(insert_user(&mut tx, "listener").await);
+        }
+        let session = insert_session(&mut tx, presenter).await;
+        let oldest = ask(&mut tx, session, audience[0], 0).await;
+        let popular = ask(&mut tx, session, audience[0], 1).await;
+        let newest = ask(&mut tx, session, audience[1], 2).await;
+        let answered = ask(&mut tx, session, audience[1], 3).await;
+        let hidden = ask(&mut tx, session, audience[2], 4).await;
+        for &user_id in &audience {
+            assert!(upvote(&mut *tx, popular, user_id).await.unwrap());
+            assert!(upvote(&mut *tx, answered, user_id).await.unwrap());
+        }
+        assert!(!upvote(&mut *tx, popular, audience[0]).await.unwrap());
+        assert!(upvote(&mut *tx, newest, audience[0]).await.unwrap());
+        set_answered(&mut *tx, answered, true).await.unwrap();
+        set_hidden(&mut *tx, hidden, true).await.unwrap();
+
+        let ids = |questions: Vec<AudienceQuestion>| -> Vec<Uuid> {
+            questions.into_iter().map(|q| q.id).collect()
+        };
+        let listed = list_questions(&mut *tx, session, Some(audience[0]), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            listed
+                .iter()
+                .map(|q| (q.votes, q.voted))
+                .collect::<Vec<_>>(),
+            [(3, true), (1, true), (0, false), (3, true)]
+        );
+        assert_eq!(ids(listed), [popular, newest, oldest, answered]);
+
+        // Hidden questions still show for the presenter and their author, in their place
+        let presenting = list_questions(&mut *tx, session, Some(presenter), true)
+            .await
+            .unwrap();
+        assert_eq!(ids(presenting), [popular, newest, oldest, hidden, answered]);
+        let author = list_questions(&mut *tx, session, Some(audience[2]), false)
+            .await
+            .unwrap();
+        assert_eq!(ids(author), [popular, newest, oldest, hidden, answered]);
+    }
+}
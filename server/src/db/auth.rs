@@ -92,7 +92,7 @@ pub async fn update_credentials(
         return Err(sqlx::Error::RowNotFound);
     }
     passkeys.iter_mut().for_each(|passkey| {
-        passkey.update_credential(&auth_result);
+        passkey.update_credential(auth_result);
     });
     Ok(())
 }
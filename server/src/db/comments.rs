@@ -0,0 +1,494 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgExecutor, PgPool, Row};
+use utoipa::ToSchema;
+
+/**
+A comment with its author's name. `reply_count` only counts the replies the reader it was
+loaded for may see.
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct Comment {
+    pub id: Uuid,
+    pub poll_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub username: String,
+    pub body: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub edited_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub hidden: bool,
+    pub pinned: bool,
+    pub reply_count: i64,
+}
+
+/**
+What happened to a comment, as logged in `comment_events`
+*/
+#[derive(Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum CommentAction {
+    Created,
+    Edited,
+    Deleted,
+    Hidden,
+    Unhidden,
+    Pinned,
+    Unpinned,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct CommentEvent {
+    pub id: i64,
+    pub comment_id: Uuid,
+    pub action: CommentAction,
+}
+
+pub async fn create_comment<'e, E>(
+    executor: E,
+    comment_id: Uuid,
+    poll_id: Uuid,
+    parent_id: Option<Uuid>,
+    user_id: Uuid,
+    body: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        WITH created AS (
+            INSERT INTO comments (id, poll_id, parent_id, user_id, body)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, poll_id
+        ), numbered AS (
+            UPDATE polls SET last_comment_event = last_comment_event + 1
+            FROM created WHERE polls.id = created.poll_id
+            RETURNING polls.id AS poll_id, polls.last_comment_event AS event_id,
+                created.id AS comment_id
+        )
+        INSERT INTO comment_events (id, poll_id, comment_id, action)
+        SELECT event_id, poll_id, comment_id, 'created' FROM numbered
+        "#,
+    )
+    .bind(comment_id)
+    .bind(poll_id)
+    .bind(parent_id)
+    .bind(user_id)
+    .bind(body)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/**
+Loads a comment of `poll_id` as `viewer` sees it. Hidden comments are only found for
+moderators and their author.
+*/
+pub async fn get_comment(
+    pool: &PgPool,
+    poll_id: Uuid,
+    comment_id: Uuid,
+    viewer: Option<Uuid>,
+    moderator: bool,
+) -> Result<Option<Comment>, sqlx::Error> {
+    let comment: Option<Comment> = sqlx::query_as(
+        r#"
+        SELECT comments.id, comments.poll_id, comments.parent_id, comments.user_id,
+               users.username, comments.body, comments.created_at, comments.edited_at,
+               comments.deleted_at, comments.hidden, comments.pinned,
+               (SELECT COUNT(*) FROM comments replies
+                WHERE replies.parent_id = comments.id
+                    AND (NOT replies.hidden OR $4 OR replies.user_id = $3)) AS reply_count
+        FROM comments
+        JOIN users ON comments.user_id = users.id
+        WHERE comments.id = $2 AND comments.poll_id = $1
+            AND (NOT comments.hidden OR $4 OR comments.user_id = $3)
+        "#,
+    )
+    .bind(poll_id)
+    .bind(comment_id)
+    .bind(viewer)
+    .bind(moderator)
+    .fetch_optional(pool)
+    .await?;
+    Ok(comment)
+}
+
+pub async fn count_comments(
+    pool: &PgPool,
+    poll_id: Uuid,
+    parent_id: Option<Uuid>,
+    viewer: Option<Uuid>,
+    moderator: bool,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS count FROM comments
+        WHERE poll_id = $1 AND parent_id IS NOT DISTINCT FROM $2
+            AND (NOT hidden OR $4 OR user_id = $3)
+        "#,
+    )
+    .bind(poll_id)
+    .bind(parent_id)
+    .bind(viewer)
+    .bind(moderator)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.get("count"))
+}
+
+/**
+One page of the top-level comments of a poll, or of the replies to `parent_id`, as
+`viewer` sees them. Pinned comments come first, then the oldest.
+*/
+pub async fn list_comments(
+    pool: &PgPool,
+    poll_id: Uuid,
+    parent_id: Option<Uuid>,
+    viewer: Option<Uuid>,
+    moderator: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Comment>, sqlx::Error> {
+    let comments: Vec<Comment> = sqlx::query_as(
+        r#"
+        SELECT comments.id, comments.poll_id, comments.parent_id, comments.user_id,
+               users.username, comments.body, comments.created_at, comments.edited_at,
+               comments.deleted_at, comments.hidden, comments.pinned,
+               (SELECT COUNT(*) FROM comments replies
+                WHERE replies.parent_id = comments.id
+                    AND (NOT replies.hidden OR $4 OR replies.user_id = $3)) AS reply_count
+        FROM comments
+        JOIN users ON comments.user_id = users.id
+        WHERE comments.poll_id = $1 AND comments.parent_id IS NOT DISTINCT FROM $2
+            AND (NOT comments.hidden OR $4 OR comments.user_id = $3)
+        ORDER BY comments.pinned DESC, comments.created_at, comments.id
+        LIMIT $5 OFFSET $6
+        "#,
+    )
+    .bind(poll_id)
+    .bind(parent_id)
+    .bind(viewer)
+    .bind(moderator)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    Ok(comments)
+}
+
+pub async fn edit_comment(pool: &PgPool, comment_id: Uuid, body: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        WITH edited AS (
+            UPDATE comments SET body = $2, edited_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            RETURNING id, poll_id
+        ), numbered AS (
+            UPDATE polls SET last_comment_event = last_comment_event + 1
+            FROM edited WHERE polls.id = edited.poll_id
+            RETURNING polls.id AS poll_id, polls.last_comment_event AS event_id,
+                edited.id AS comment_id
+        )
+        INSERT INTO comment_events (id, poll_id, comment_id, action)
+        SELECT event_id, poll_id, comment_id, 'edited' FROM numbered
+        "#,
+    )
+    .bind(comment_id)
+    .bind(body)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/**
+Deletes a comment. One that has replies is only blanked and marked deleted so the thread
+below it stays intact; deleted ancestors left without any reply are removed with it.
+*/
+pub async fn delete_comment(pool: &PgPool, comment_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query(
+        r#"
+        SELECT parent_id,
+            EXISTS(SELECT 1 FROM comments replies WHERE replies.parent_id = comments.id)
+                AS has_replies
+        FROM comments WHERE id = $1
+        "#,
+    )
+    .bind(comment_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    let has_replies: bool = row.get("has_replies");
+    // Only a comment that is gone for good can leave its parent without replies
+    let mut parent_id: Option<Uuid> = if has_replies {
+        None
+    } else {
+        row.get("parent_id")
+    };
+    let removal = if has_replies {
+        r#"
+        WITH removed AS (
+            UPDATE comments SET body = '', deleted_at = CURRENT_TIMESTAMP, pinned = FALSE
+            WHERE id = $1
+            RETURNING id, poll_id
+        ), numbered AS (
+            UPDATE polls SET last_comment_event = last_comment_event + 1
+            FROM removed WHERE polls.id = removed.poll_id
+            RETURNING polls.id AS poll_id, polls.last_comment_event AS event_id,
+                removed.id AS comment_id
+        )
+        INSERT INTO comment_events (id, poll_id, comment_id, action)
+        SELECT event_id, poll_id, comment_id, 'deleted' FROM numbered
+        "#
+    } else {
+        r#"
+        WITH removed AS (
+            DELETE FROM comments WHERE id = $1
+            RETURNING id, poll_id
+        ), numbered AS (
+            UPDATE polls SET last_comment_event = last_comment_event + 1
+            FROM removed WHERE polls.id = removed.poll_id
+            RETURNING polls.id AS poll_id, polls.last_comment_event AS event_id,
+                removed.id AS comment_id
+        )
+        INSERT INTO comment_events (id, poll_id, comment_id, action)
+        SELECT event_id, poll_id, comment_id, 'deleted' FROM numbered
+        "#
+    };
+    sqlx::query(removal)
+        .bind(comment_id)
+        .execute(&mut *tx)
+        .await?;
+    while let Some(id) = parent_id {
+        let removed = sqlx::query(
+            r#"
+            WITH removed AS (
+                DELETE FROM comments
+                WHERE id = $1 AND deleted_at IS NOT NULL
+                    AND NOT EXISTS(SELECT 1 FROM comments replies WHERE replies.parent_id = $1)
+                RETURNING id, poll_id, parent_id
+            ), numbered AS (
+                UPDATE polls SET last_comment_event = last_comment_event + 1
+                FROM removed WHERE polls.id = removed.poll_id
+                RETURNING polls.id AS poll_id, polls.last_comment_event AS event_id,
+                    removed.id AS comment_id
+            ), logged AS (
+                INSERT INTO comment_events (id, poll_id, comment_id, action)
+                SELECT event_id, poll_id, comment_id, 'deleted' FROM numbered
+            )
+            SELECT parent_id FROM removed
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        parent_id = removed.and_then(|row| row.get("parent_id"));
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn set_comment_hidden<'e, E>(
+    executor: E,
+    comment_id: Uuid,
+    hidden: bool,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        WITH changed AS (
+            UPDATE comments SET hidden = $2
+            WHERE id = $1 AND hidden <> $2
+            RETURNING id, poll_id
+        ), numbered AS (
+            UPDATE polls SET last_comment_event = last_comment_event + 1
+            FROM changed WHERE polls.id = changed.poll_id
+            RETURNING polls.id AS poll_id, polls.last_comment_event AS event_id,
+                changed.id AS comment_id
+        )
+        INSERT INTO comment_events (id, poll_id, comment_id, action)
+        SELECT event_id, poll_id, comment_id, CASE WHEN $2 THEN 'hidden' ELSE 'unhidden' END
+        FROM numbered
+        "#,
+    )
+    .bind(comment_id)
+    .bind(hidden)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_comment_pinned(
+    pool: &PgPool,
+    comment_id: Uuid,
+    pinned: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        WITH changed AS (
+            UPDATE comments SET pinned = $2
+            WHERE id = $1 AND pinned <> $2
+            RETURNING id, poll_id
+        ), numbered AS (
+            UPDATE polls SET last_comment_event = last_comment_event + 1
+            FROM changed WHERE polls.id = changed.poll_id
+            RETURNING polls.id AS poll_id, polls.last_comment_event AS event_id,
+                changed.id AS comment_id
+        )
+        INSERT INTO comment_events (id, poll_id, comment_id, action)
+        SELECT event_id, poll_id, comment_id, CASE WHEN $2 THEN 'pinned' ELSE 'unpinned' END
+        FROM numbered
+        "#,
+    )
+    .bind(comment_id)
+    .bind(pinned)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/**
+Id of the poll's latest comment event, 0 when there is none
+*/
+pub async fn last_comment_event_id(pool: &PgPool, poll_id: Uuid) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT COALESCE(MAX(id), 0) AS id FROM comment_events WHERE poll_id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.get("id"))
+}
+
+/**
+The poll's comment events after `after_id`, oldest first. Events are numbered in the order
+they commit, so none can still turn up below the last one returned.
+*/
+pub async fn get_comment_events<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    after_id: i64,
+) -> Result<Vec<CommentEvent>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let events: Vec<CommentEvent> = sqlx::query_as(
+        r#"
+        SELECT id, comment_id, action FROM comment_events
+        WHERE poll_id = $1 AND id > $2
+        ORDER BY id
+        "#,
+    )
+    .bind(poll_id)
+    .bind(after_id)
+    .fetch_all(executor)
+    .await?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{connect, insert_poll, insert_user};
+    use sqlx::Connection;
+    use std::time::Duration;
+
+    fn numbers(events: &[CommentEvent]) -> Vec<i64> {
+        events.iter().map(|event| event.id).collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the database of DATABASE_URL"]
+    async fn events_are_numbered_per_poll() {
+        let mut conn = connect().await;
+        let mut tx = conn.begin().await.unwrap();
+        let owner = insert_user(&mut tx, "owner").await;
+        let (poll, _) = insert_poll(&mut tx, owner, &["Yes", "No"]).await;
+        let (other_poll, _) = insert_poll(&mut tx, owner, &["Yes", "No"]).await;
+        let comment = Uuid::new_v4();
+        create_comment(&mut *tx, comment, poll, None, owner, "First")
+            .await
+            .unwrap();
+        create_comment(
+            &mut *tx,
+            Uuid::new_v4(),
+            other_poll,
+            None,
+            owner,
+            "Elsewhere",
+        )
+        .await
+        .unwrap();
+        set_comment_hidden(&mut *tx, comment, true).await.unwrap();
+        // Hiding it again changes nothing and logs nothing
+        set_comment_hidden(&mut *tx, comment, true).await.unwrap();
+        set_comment_hidden(&mut *tx, comment, false).await.unwrap();
+
+        let events = get_comment_events(&mut *tx, poll, 0).await.unwrap();
+        assert_eq!(numbers(&events), [1, 2, 3]);
+        assert_eq!(
+            events.iter().map(|event| event.action).collect::<Vec<_>>(),
+            [
+                CommentAction::Created,
+                CommentAction::Hidden,
+                CommentAction::Unhidden
+            ]
+        );
+        assert_eq!(
+            numbers(&get_comment_events(&mut *tx, poll, 2).await.unwrap()),
+            [3]
+        );
+        assert_eq!(
+            numbers(&get_comment_events(&mut *tx, other_poll, 0).await.unwrap()),
+            [1]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the database of DATABASE_URL"]
+    async fn a_later_event_waits_for_the_earlier_one_to_commit() {
+        // Both writers have to commit, so this test cleans up after itself instead
+        let mut first = connect().await;
+        let mut second = connect().await;
+        let owner = insert_user(&mut first, "owner").await;
+        let (poll, _) = insert_poll(&mut first, owner, &["Yes", "No"]).await;
+
+        let mut slow = first.begin().await.unwrap();
+        create_comment(&mut *slow, Uuid::new_v4(), poll, None, owner, "Slow")
+            .await
+            .unwrap();
+        let fast = tokio::spawn(async move {
+            create_comment(&mut second, Uuid::new_v4(), poll, None, owner, "Fast")
+                .await
+                .unwrap();
+            second
+        });
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!fast.is_finished());
+        let mut reader = connect().await;
+        assert!(get_comment_events(&mut reader, poll, 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        slow.commit().await.unwrap();
+        let second = fast.await.unwrap();
+        assert_eq!(
+            numbers(&get_comment_events(&mut reader, poll, 0).await.unwrap()),
+            [1, 2]
+        );
+
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(owner)
+            .execute(&mut reader)
+            .await
+            .unwrap();
+        second.close().await.unwrap();
+    }
+}
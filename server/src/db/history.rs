@@ -0,0 +1,71 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgPool};
+
+/**
+Vote activity on one option within one time bucket
+*/
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct VoteBucket {
+    pub bucket: chrono::DateTime<chrono::Utc>,
+    pub option_id: Uuid,
+    pub casts: i64,
+    pub removals: i64,
+}
+
+/**
+Groups the poll's vote events into `unit` sized buckets (any `date_trunc` unit, in UTC),
+ordered by time. Removals and resets are both counted in `removals`.
+*/
+pub async fn get_vote_buckets(
+    pool: &PgPool,
+    poll_id: Uuid,
+    unit: &str,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Vec<VoteBucket>, sqlx::Error> {
+    let buckets: Vec<VoteBucket> = sqlx::query_as(
+        r#"
+        SELECT date_trunc($2, occurred_at, 'UTC') AS bucket,
+            poll_option_id AS option_id,
+            COUNT(*) FILTER (WHERE action = 'cast') AS casts,
+            COUNT(*) FILTER (WHERE action <> 'cast') AS removals
+        FROM vote_events
+        WHERE poll_id = $1
+            AND ($3::TIMESTAMPTZ IS NULL OR occurred_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR occurred_at < $4)
+        GROUP BY bucket, poll_option_id
+        ORDER BY bucket
+        "#,
+    )
+    .bind(poll_id)
+    .bind(unit)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+    Ok(buckets)
+}
+
+/**
+Net votes per option from events before `before`, the starting point of a windowed history.
+*/
+pub async fn get_vote_totals_before(
+    pool: &PgPool,
+    poll_id: Uuid,
+    before: chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<(Uuid, i64)>, sqlx::Error> {
+    let totals: Vec<(Uuid, i64)> = sqlx::query_as(
+        r#"
+        SELECT poll_option_id,
+            COUNT(*) FILTER (WHERE action = 'cast') - COUNT(*) FILTER (WHERE action <> 'cast')
+        FROM vote_events
+        WHERE poll_id = $1 AND occurred_at < $2
+        GROUP BY poll_option_id
+        "#,
+    )
+    .bind(poll_id)
+    .bind(before)
+    .fetch_all(pool)
+    .await?;
+    Ok(totals)
+}
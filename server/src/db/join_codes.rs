@@ -0,0 +1,91 @@
+use sqlx::{types::Uuid, PgPool};
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct JoinCode {
+    pub code: String,
+}
+
+/**
+A join code with what it leads to
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct JoinTarget {
+    pub code: String,
+    pub poll_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub title: String,
+    /// When the target poll was closed; always `None` for live sessions
+    pub closed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub async fn get_poll_join_code(
+    pool: &PgPool,
+    poll_id: Uuid,
+) -> Result<Option<JoinCode>, sqlx::Error> {
+    let code: Option<JoinCode> = sqlx::query_as(
+        r#"
+        SELECT code FROM join_codes WHERE poll_id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(code)
+}
+
+pub async fn get_session_join_code(
+    pool: &PgPool,
+    session_id: Uuid,
+) -> Result<Option<JoinCode>, sqlx::Error> {
+    let code: Option<JoinCode> = sqlx::query_as(
+        r#"
+        SELECT code FROM join_codes WHERE session_id = $1
+        "#,
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(code)
+}
+
+/**
+Assigns `code` to a poll or a live session. Returns `None` when the code is taken or the
+target already has a code.
+*/
+pub async fn create_join_code(
+    pool: &PgPool,
+    code: &str,
+    poll_id: Option<Uuid>,
+    session_id: Option<Uuid>,
+) -> Result<Option<JoinCode>, sqlx::Error> {
+    let code: Option<JoinCode> = sqlx::query_as(
+        r#"
+        INSERT INTO join_codes (code, poll_id, session_id) VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        RETURNING code
+        "#,
+    )
+    .bind(code)
+    .bind(poll_id)
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(code)
+}
+
+pub async fn find_join_code(pool: &PgPool, code: &str) -> Result<Option<JoinTarget>, sqlx::Error> {
+    let target: Option<JoinTarget> = sqlx::query_as(
+        r#"
+        SELECT join_codes.code, join_codes.poll_id, join_codes.session_id,
+               COALESCE(polls.title, live_sessions.title) AS title, polls.closed_at
+        FROM join_codes
+        LEFT JOIN polls ON join_codes.poll_id = polls.id
+        LEFT JOIN live_sessions ON join_codes.session_id = live_sessions.id
+        WHERE join_codes.code = $1
+        "#,
+    )
+    .bind(code)
+    .fetch_optional(pool)
+    .await?;
+    Ok(target)
+}
@@ -0,0 +1,210 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgPool, Row};
+use utoipa::ToSchema;
+
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct LiveSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub title: String,
+    pub current_poll_id: Option<Uuid>,
+    pub results_revealed: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/**
+Creates the session with its polls, which are shown in the order given.
+*/
+pub async fn create_live_session(
+    pool: &PgPool,
+    session_id: Uuid,
+    user_id: Uuid,
+    title: &str,
+    poll_ids: &[Uuid],
+) -> Result<LiveSession, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let session: LiveSession = sqlx::query_as(
+        r#"
+        INSERT INTO live_sessions (id, user_id, title) VALUES ($1, $2, $3)
+        RETURNING *
+        "#,
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .bind(title)
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO live_session_polls (session_id, poll_id, position)
+        SELECT $1, poll.id, (poll.ordinality - 1)::INT
+        FROM UNNEST($2::UUID[]) WITH ORDINALITY AS poll(id, ordinality)
+        "#,
+    )
+    .bind(session_id)
+    .bind(poll_ids)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(session)
+}
+
+/**
+Replaces the session's polls. A live poll that is no longer part of the session stops
+being live.
+*/
+pub async fn set_live_polls(
+    pool: &PgPool,
+    session_id: Uuid,
+    poll_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        DELETE FROM live_session_polls WHERE session_id = $1
+        "#,
+    )
+    .bind(session_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO live_session_polls (session_id, poll_id, position)
+        SELECT $1, poll.id, (poll.ordinality - 1)::INT
+        FROM UNNEST($2::UUID[]) WITH ORDINALITY AS poll(id, ordinality)
+        "#,
+    )
+    .bind(session_id)
+    .bind(poll_ids)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE live_sessions
+        SET current_poll_id = NULL, results_revealed = FALSE
+        WHERE id = $1 AND NOT (current_poll_id = ANY($2))
+        "#,
+    )
+    .bind(session_id)
+    .bind(poll_ids)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_live_session(pool: &PgPool, session_id: Uuid) -> Result<LiveSession, sqlx::Error> {
+    let session: LiveSession = sqlx::query_as(
+        r#"
+        SELECT * FROM live_sessions WHERE id = $1
+        "#,
+    )
+    .bind(session_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(session)
+}
+
+pub async fn get_user_live_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<LiveSession>, sqlx::Error> {
+    let sessions: Vec<LiveSession> = sqlx::query_as(
+        r#"
+        SELECT * FROM live_sessions WHERE user_id = $1 ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(sessions)
+}
+
+/**
+The session's polls in the order they are presented
+*/
+pub async fn get_live_poll_ids(pool: &PgPool, session_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT poll_id FROM live_session_polls WHERE session_id = $1 ORDER BY position
+        "#,
+    )
+    .bind(session_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.iter().map(|row| row.get("poll_id")).collect())
+}
+
+/**
+Whether every one of `poll_ids` exists and belongs to `user_id`
+*/
+pub async fn owns_polls(
+    pool: &PgPool,
+    user_id: Uuid,
+    poll_ids: &[Uuid],
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS count FROM polls WHERE id = ANY($1) AND user_id = $2
+        "#,
+    )
+    .bind(poll_ids)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.get::<i64, _>("count") == poll_ids.len() as i64)
+}
+
+/**
+Makes `poll_id` the live poll; its results start out hidden from the audience. The first
+time a quiz poll goes live starts the clock for its time bonus.
+*/
+pub async fn set_current_poll(
+    pool: &PgPool,
+    session_id: Uuid,
+    poll_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        WITH started AS (
+            UPDATE polls SET quiz_started_at = COALESCE(quiz_started_at, CURRENT_TIMESTAMP)
+            WHERE id = $2 AND quiz
+        )
+        UPDATE live_sessions SET current_poll_id = $2, results_revealed = FALSE WHERE id = $1
+        "#,
+    )
+    .bind(session_id)
+    .bind(poll_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_results_revealed(
+    pool: &PgPool,
+    session_id: Uuid,
+    revealed: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE live_sessions SET results_revealed = $2 WHERE id = $1
+        "#,
+    )
+    .bind(session_id)
+    .bind(revealed)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_live_session(pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        DELETE FROM live_sessions WHERE id = $1
+        "#,
+    )
+    .bind(session_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -1,4 +1,20 @@
+pub mod answers;
+pub mod assets;
+pub mod audience_questions;
+pub mod auth;
+pub mod comments;
 pub mod create_pool;
+pub mod history;
+pub mod join_codes;
+pub mod live;
 pub mod migrations;
-pub mod auth;
-pub mod polls;
\ No newline at end of file
+pub mod notifications;
+pub mod polls;
+pub mod quiz;
+pub mod reactions;
+pub mod surveys;
+pub mod templates;
+#[cfg(test)]
+pub mod testing;
+pub mod webhooks;
+pub mod weights;
@@ -0,0 +1,420 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Uuid, PgPool, Row};
+use utoipa::ToSchema;
+
+/**
+What a notification tells its user about a poll
+*/
+#[derive(Serialize, Deserialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// The owner made the user a participant of the poll
+    Invite,
+    /// The poll closes soon and the user has not voted yet
+    ClosingReminder,
+    /// The poll closed and its results can be seen
+    ResultsReady,
+}
+
+/**
+How often pending notifications are sent, batched into one digest
+*/
+#[derive(Serialize, Deserialize, ToSchema, sqlx::Type, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum DigestFrequency {
+    /// As soon as the scheduler finds them
+    Immediate,
+    /// At most one digest an hour
+    #[default]
+    Hourly,
+    /// At most one digest a day
+    Daily,
+}
+
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct NotificationPreferences {
+    /// Where notifications are sent; nothing is sent without one
+    pub email: Option<String>,
+    pub invites: bool,
+    pub closing_reminders: bool,
+    pub results_ready: bool,
+    pub digest: DigestFrequency,
+    /// When the last digest went out
+    pub last_digest_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        NotificationPreferences {
+            email: None,
+            invites: true,
+            closing_reminders: true,
+            results_ready: true,
+            digest: DigestFrequency::default(),
+            last_digest_at: None,
+        }
+    }
+}
+
+/**
+A user whose digest the scheduler claimed
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct DueDigest {
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: String,
+    /// When the digest before this one went out, to give the claim back on failure
+    pub previous_digest_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/**
+A notification waiting to be sent, with what its template needs
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct PendingNotification {
+    pub id: Uuid,
+    pub kind: NotificationKind,
+    pub poll_id: Uuid,
+    pub title: String,
+    /// Username of the poll's owner
+    pub owner: String,
+    pub is_active: bool,
+    /// When a recurring poll closes to make way for its next instance
+    pub next_run_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether the user voted on the poll by now
+    pub voted: bool,
+}
+
+pub async fn get_preferences(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<NotificationPreferences>, sqlx::Error> {
+    let preferences: Option<NotificationPreferences> = sqlx::query_as(
+        r#"
+        SELECT email, invites, closing_reminders, results_ready, digest, last_digest_at
+        FROM notification_preferences
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(preferences)
+}
+
+/**
+Saves a user's preferences and drops pending notifications of the kinds they turned off.
+*/
+pub async fn set_preferences(
+    pool: &PgPool,
+    user_id: Uuid,
+    preferences: &NotificationPreferences,
+) -> Result<NotificationPreferences, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let saved: NotificationPreferences = sqlx::query_as(
+        r#"
+        INSERT INTO notification_preferences
+            (user_id, email, invites, closing_reminders, results_ready, digest)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (user_id) DO UPDATE
+        SET email = EXCLUDED.email, invites = EXCLUDED.invites,
+            closing_reminders = EXCLUDED.closing_reminders,
+            results_ready = EXCLUDED.results_ready, digest = EXCLUDED.digest
+        RETURNING email, invites, closing_reminders, results_ready, digest, last_digest_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(&preferences.email)
+    .bind(preferences.invites)
+    .bind(preferences.closing_reminders)
+    .bind(preferences.results_ready)
+    .bind(preferences.digest)
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        DELETE FROM notifications
+        WHERE user_id = $1 AND sent_at IS NULL
+            AND ((kind = 'invite' AND NOT $2)
+                OR (kind = 'closing_reminder' AND NOT $3)
+                OR (kind = 'results_ready' AND NOT $4))
+        "#,
+    )
+    .bind(user_id)
+    .bind(saved.invites)
+    .bind(saved.closing_reminders)
+    .bind(saved.results_ready)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(saved)
+}
+
+/**
+Queues an invite to an open poll for a user who wants to hear about invites. Returns
+whether one was queued.
+*/
+pub async fn queue_invite(
+    pool: &PgPool,
+    poll_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO notifications (id, user_id, kind, poll_id)
+        SELECT gen_random_uuid(), notification_preferences.user_id, 'invite', polls.id
+        FROM notification_preferences
+        JOIN polls ON polls.id = $1 AND polls.is_active
+        WHERE notification_preferences.user_id = $2
+            AND notification_preferences.email IS NOT NULL
+            AND notification_preferences.invites
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/**
+Queues a reminder for every participant who has not voted on a recurring poll that
+closes within `within_seconds`. Returns how many were queued.
+*/
+pub async fn queue_closing_reminders(
+    pool: &PgPool,
+    within_seconds: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO notifications (id, user_id, kind, poll_id)
+        SELECT gen_random_uuid(), poll_participants.user_id, 'closing_reminder', polls.id
+        FROM polls
+        JOIN poll_participants ON poll_participants.poll_id = polls.id
+        JOIN notification_preferences
+            ON notification_preferences.user_id = poll_participants.user_id
+        WHERE polls.is_active AND polls.recurrence IS NOT NULL
+            AND polls.next_run_at > CURRENT_TIMESTAMP
+            AND polls.next_run_at <= CURRENT_TIMESTAMP + make_interval(secs => $1)
+            AND notification_preferences.email IS NOT NULL
+            AND notification_preferences.closing_reminders
+            AND NOT EXISTS(
+                SELECT 1 FROM votes
+                JOIN poll_options ON votes.poll_option_id = poll_options.id
+                WHERE poll_options.poll_id = polls.id
+                    AND votes.user_id = poll_participants.user_id
+            )
+            AND NOT EXISTS(
+                SELECT 1 FROM numeric_answers
+                WHERE poll_id = polls.id AND user_id = poll_participants.user_id
+            )
+            AND NOT EXISTS(
+                SELECT 1 FROM text_answers
+                WHERE poll_id = polls.id AND user_id = poll_participants.user_id
+            )
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(within_seconds as f64)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/**
+Queues a results notification for the participants and voters of every poll closed in
+the last `lookback_seconds` whose results they may see. Returns how many were queued.
+*/
+pub async fn queue_results_ready(pool: &PgPool, lookback_seconds: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        WITH closed AS (
+            SELECT id, user_id, results_visibility FROM polls
+            WHERE NOT is_active
+                AND closed_at > CURRENT_TIMESTAMP - make_interval(secs => $1)
+        ), recipients AS (
+            SELECT poll_id, user_id FROM poll_participants
+            WHERE poll_id IN (SELECT id FROM closed)
+            UNION
+            SELECT poll_options.poll_id, votes.user_id FROM votes
+            JOIN poll_options ON votes.poll_option_id = poll_options.id
+            WHERE poll_options.poll_id IN (SELECT id FROM closed)
+            UNION
+            SELECT poll_id, user_id FROM numeric_answers
+            WHERE poll_id IN (SELECT id FROM closed)
+            UNION
+            SELECT poll_id, user_id FROM text_answers
+            WHERE poll_id IN (SELECT id FROM closed)
+        )
+        INSERT INTO notifications (id, user_id, kind, poll_id)
+        SELECT gen_random_uuid(), recipients.user_id, 'results_ready', recipients.poll_id
+        FROM recipients
+        JOIN closed ON closed.id = recipients.poll_id
+        JOIN notification_preferences ON notification_preferences.user_id = recipients.user_id
+        WHERE (closed.results_visibility <> 'owner_only' OR recipients.user_id = closed.user_id)
+            AND notification_preferences.email IS NOT NULL
+            AND notification_preferences.results_ready
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(lookback_seconds as f64)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/**
+Claims up to `limit` users with pending notifications whose digest is due, by stamping
+their digest as sent now. Users claimed by another scheduler are skipped.
+*/
+pub async fn claim_due_digests(pool: &PgPool, limit: i64) -> Result<Vec<DueDigest>, sqlx::Error> {
+    let digests: Vec<DueDigest> = sqlx::query_as(
+        r#"
+        WITH due AS (
+            SELECT user_id, last_digest_at FROM notification_preferences
+            WHERE email IS NOT NULL
+                AND EXISTS(
+                    SELECT 1 FROM notifications
+                    WHERE notifications.user_id = notification_preferences.user_id
+                        AND notifications.sent_at IS NULL
+                        AND notifications.failed_at IS NULL
+                        AND (notifications.retry_at IS NULL
+                            OR notifications.retry_at <= CURRENT_TIMESTAMP)
+                )
+                AND (last_digest_at IS NULL OR last_digest_at + CASE digest
+                        WHEN 'hourly' THEN INTERVAL '1 hour'
+                        WHEN 'daily' THEN INTERVAL '1 day'
+                        ELSE INTERVAL '0 seconds' END <= CURRENT_TIMESTAMP)
+            ORDER BY last_digest_at NULLS FIRST
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        ), claimed AS (
+            UPDATE notification_preferences
+            SET last_digest_at = CURRENT_TIMESTAMP
+            FROM due
+            WHERE notification_preferences.user_id = due.user_id
+            RETURNING notification_preferences.user_id, notification_preferences.email,
+                due.last_digest_at AS previous_digest_at
+        )
+        SELECT claimed.user_id, users.username, claimed.email, claimed.previous_digest_at
+        FROM claimed
+        JOIN users ON claimed.user_id = users.id
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(digests)
+}
+
+/**
+Gives a claimed digest back so the next run tries it again.
+*/
+pub async fn release_digest(
+    pool: &PgPool,
+    user_id: Uuid,
+    previous_digest_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE notification_preferences SET last_digest_at = $2 WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(previous_digest_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/**
+The user's pending notifications, oldest first. Notifications waiting for a retry are
+included, as the digest goes out anyway.
+*/
+pub async fn get_pending(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<PendingNotification>, sqlx::Error> {
+    let notifications: Vec<PendingNotification> = sqlx::query_as(
+        r#"
+        SELECT notifications.id, notifications.kind, notifications.poll_id, polls.title,
+               users.username AS owner, polls.is_active, polls.next_run_at,
+               (EXISTS(
+                   SELECT 1 FROM votes
+                   JOIN poll_options ON votes.poll_option_id = poll_options.id
+                   WHERE poll_options.poll_id = polls.id AND votes.user_id = $1
+               ) OR EXISTS(
+                   SELECT 1 FROM numeric_answers WHERE poll_id = polls.id AND user_id = $1
+               ) OR EXISTS(
+                   SELECT 1 FROM text_answers WHERE poll_id = polls.id AND user_id = $1
+               )) AS voted
+        FROM notifications
+        JOIN polls ON notifications.poll_id = polls.id
+        JOIN users ON polls.user_id = users.id
+        WHERE notifications.user_id = $1 AND notifications.sent_at IS NULL
+            AND notifications.failed_at IS NULL
+        ORDER BY notifications.created_at, notifications.id
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(notifications)
+}
+
+pub async fn mark_sent(pool: &PgPool, notification_ids: &[Uuid]) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE notifications SET sent_at = CURRENT_TIMESTAMP WHERE id = ANY($1)
+        "#,
+    )
+    .bind(notification_ids)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/**
+Records a failed attempt to send the notifications. They are retried after a backoff
+that starts at `retry_base_seconds` and doubles per attempt up to `retry_max_seconds`,
+and given up once `max_attempts` is reached or at once when the failure is `permanent`.
+Returns how many were given up.
+*/
+pub async fn record_failure(
+    pool: &PgPool,
+    notification_ids: &[Uuid],
+    error: &str,
+    permanent: bool,
+    max_attempts: i32,
+    retry_base_seconds: f64,
+    retry_max_seconds: f64,
+) -> Result<usize, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        UPDATE notifications
+        SET attempts = attempts + 1,
+            last_error = $2,
+            retry_at = CURRENT_TIMESTAMP
+                + make_interval(secs => LEAST($5 * POWER(2, attempts), $6)),
+            failed_at = CASE WHEN $3 OR attempts + 1 >= $4 THEN CURRENT_TIMESTAMP END
+        WHERE id = ANY($1)
+        RETURNING failed_at IS NOT NULL AS gave_up
+        "#,
+    )
+    .bind(notification_ids)
+    .bind(error)
+    .bind(permanent)
+    .bind(max_attempts)
+    .bind(retry_base_seconds)
+    .bind(retry_max_seconds)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .filter(|row| row.get::<bool, _>("gave_up"))
+        .count())
+}
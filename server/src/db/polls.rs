@@ -1,25 +1,249 @@
 use serde::{Deserialize, Serialize};
-use sqlx::{types::Uuid, PgPool, Row};
-pub async fn create_poll(
-    pool: &PgPool,
+use sqlx::{types::Uuid, Acquire, PgConnection, PgExecutor, PgPool, Postgres, Row};
+use utoipa::ToSchema;
+/**
+Option to be inserted by [create_poll_with_options]
+*/
+#[derive(Debug, Clone, Default)]
+pub struct NewPollOption {
+    pub option_text: String,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub image_asset_id: Option<Uuid>,
+    pub color: Option<String>,
+    /// Marks a correct answer of a quiz
+    pub is_correct: bool,
+}
+
+/**
+Who may see a poll's tallies. The owner always can.
+*/
+#[derive(Serialize, Deserialize, ToSchema, sqlx::Type, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum ResultsVisibility {
+    /// Anyone, at any time
+    #[default]
+    Always,
+    /// Users who voted, and everyone once the poll is closed
+    AfterVote,
+    /// Everyone once the poll is closed
+    AfterClose,
+    /// Nobody but the owner
+    OwnerOnly,
+}
+
+/**
+Share of the votes (by weight in weighted polls) the leading option needs to be decided
+*/
+#[derive(Serialize, Deserialize, ToSchema, sqlx::Type, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum PassThreshold {
+    /// More than any other option
+    #[default]
+    Plurality,
+    /// More than half
+    Majority,
+    /// At least two thirds
+    TwoThirds,
+    /// Every vote
+    Unanimous,
+}
+
+/**
+Rules deciding whether a result counts; replaced as a whole when changed
+*/
+#[derive(Debug, Clone, Default)]
+pub struct OutcomeRules {
+    pub participants_only: bool,
+    pub quorum_votes: Option<i32>,
+    pub quorum_percent: Option<f64>,
+    pub pass_threshold: PassThreshold,
+    pub auto_close: bool,
+}
+
+/**
+What a poll asks its voters for
+*/
+#[derive(Serialize, Deserialize, ToSchema, sqlx::Type, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum QuestionKind {
+    /// One of the poll's options
+    #[default]
+    Choice,
+    /// A whole number from 1 to `scale_max`
+    Scale,
+    /// Any number between `number_min` and `number_max`
+    Number,
+    /// A short free-text answer of at most `text_max_length` characters
+    Text,
+}
+
+/**
+The kind of question and the bounds its answers have to respect
+*/
+#[derive(Debug, Clone, Default)]
+pub struct QuestionSettings {
+    pub kind: QuestionKind,
+    pub scale_max: Option<i32>,
+    pub number_min: Option<f64>,
+    pub number_max: Option<f64>,
+    pub text_max_length: Option<i32>,
+}
+
+impl From<&Poll> for QuestionSettings {
+    fn from(poll: &Poll) -> Self {
+        QuestionSettings {
+            kind: poll.kind,
+            scale_max: poll.scale_max,
+            number_min: poll.number_min,
+            number_max: poll.number_max,
+            text_max_length: poll.text_max_length,
+        }
+    }
+}
+
+/**
+Scoring of a quiz poll. The time bonus shrinks linearly from `bonus_points` to nothing
+over `bonus_seconds`; both are set or neither is.
+*/
+#[derive(Debug, Clone)]
+pub struct QuizSettings {
+    pub points: i32,
+    pub bonus_points: Option<i32>,
+    pub bonus_seconds: Option<i32>,
+}
+
+impl QuizSettings {
+    /// The settings of `poll`, or `None` when it is not a quiz
+    pub fn of(poll: &Poll) -> Option<Self> {
+        poll.quiz.then_some(QuizSettings {
+            points: poll.quiz_points,
+            bonus_points: poll.quiz_bonus_points,
+            bonus_seconds: poll.quiz_bonus_seconds,
+        })
+    }
+}
+
+/**
+Per-poll behaviour chosen when the poll is created
+*/
+#[derive(Debug, Clone, Default)]
+pub struct PollSettings {
+    pub secret_ballot: bool,
+    pub results_visibility: ResultsVisibility,
+    pub recurrence: Option<String>,
+    pub next_run_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Instance of the same recurring series this poll follows
+    pub previous_poll_id: Option<Uuid>,
+    /// Decide by summed voter weight instead of headcount
+    pub weighted: bool,
+    /// Weight of voters without a role or personal weight; `None` means 1
+    pub default_weight: Option<f64>,
+    pub rules: OutcomeRules,
+    pub question: QuestionSettings,
+    /// Makes the poll a quiz; its correct options are flagged with `is_correct`
+    pub quiz: Option<QuizSettings>,
+}
+
+/**
+Inserts a poll and all of its options in a single transaction. Options are written with
+one batched statement and keep the order they were given in through `position`.
+Passing a connection that is already in a transaction nests this one as a savepoint.
+*/
+pub async fn create_poll_with_options<'a, A>(
+    conn: A,
     poll_id: Uuid,
     user_id: Uuid,
     poll_name: &str,
     poll_description: &str,
-) -> Result<(), sqlx::Error> {
-    sqlx::query(
+    settings: &PollSettings,
+    options: &[NewPollOption],
+) -> Result<(Poll, Vec<PollOption>), sqlx::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tx = conn.begin().await?;
+    let poll: Poll = sqlx::query_as(
         r#"
-        INSERT INTO polls (id, user_id, title, description)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO polls
+            (id, user_id, title, description, secret_ballot, recurrence, next_run_at,
+                previous_poll_id, results_visibility, weighted, default_weight,
+                participants_only, quorum_votes, quorum_percent, pass_threshold, auto_close,
+                kind, scale_max, number_min, number_max, text_max_length, quiz, quiz_points,
+                quiz_bonus_points, quiz_bonus_seconds)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, COALESCE($11, 1),
+            $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, COALESCE($23, 100),
+            $24, $25)
+        RETURNING *
         "#,
     )
     .bind(poll_id)
     .bind(user_id)
     .bind(poll_name)
     .bind(poll_description)
-    .execute(pool)
+    .bind(settings.secret_ballot)
+    .bind(settings.recurrence.as_deref())
+    .bind(settings.next_run_at)
+    .bind(settings.previous_poll_id)
+    .bind(settings.results_visibility)
+    .bind(settings.weighted)
+    .bind(settings.default_weight)
+    .bind(settings.rules.participants_only)
+    .bind(settings.rules.quorum_votes)
+    .bind(settings.rules.quorum_percent)
+    .bind(settings.rules.pass_threshold)
+    .bind(settings.rules.auto_close)
+    .bind(settings.question.kind)
+    .bind(settings.question.scale_max)
+    .bind(settings.question.number_min)
+    .bind(settings.question.number_max)
+    .bind(settings.question.text_max_length)
+    .bind(settings.quiz.is_some())
+    .bind(settings.quiz.as_ref().map(|quiz| quiz.points))
+    .bind(settings.quiz.as_ref().and_then(|quiz| quiz.bonus_points))
+    .bind(settings.quiz.as_ref().and_then(|quiz| quiz.bonus_seconds))
+    .fetch_one(&mut *tx)
     .await?;
-    Ok(())
+
+    let option_ids: Vec<Uuid> = options.iter().map(|_| Uuid::new_v4()).collect();
+    let texts: Vec<&str> = options.iter().map(|o| o.option_text.as_str()).collect();
+    let descriptions: Vec<Option<&str>> =
+        options.iter().map(|o| o.description.as_deref()).collect();
+    let image_urls: Vec<Option<&str>> = options.iter().map(|o| o.image_url.as_deref()).collect();
+    let image_asset_ids: Vec<Option<Uuid>> = options.iter().map(|o| o.image_asset_id).collect();
+    let colors: Vec<Option<&str>> = options.iter().map(|o| o.color.as_deref()).collect();
+    let correct: Vec<bool> = options.iter().map(|o| o.is_correct).collect();
+    let mut poll_options: Vec<PollOption> = sqlx::query_as(
+        r#"
+        INSERT INTO poll_options
+            (id, poll_id, option_text, position, description, image_url, image_asset_id, color,
+                is_correct)
+        SELECT option.id, $2, option.option_text, (option.ordinality - 1)::INT,
+            option.description, option.image_url, option.image_asset_id, option.color,
+            option.is_correct
+        FROM UNNEST($1::UUID[], $3::TEXT[], $4::TEXT[], $5::TEXT[], $6::UUID[], $7::TEXT[],
+                $8::BOOL[])
+            WITH ORDINALITY AS option(id, option_text, description, image_url, image_asset_id, color, is_correct, ordinality)
+        RETURNING *
+        "#,
+    )
+    .bind(&option_ids)
+    .bind(poll_id)
+    .bind(&texts)
+    .bind(&descriptions)
+    .bind(&image_urls)
+    .bind(&image_asset_ids)
+    .bind(&colors)
+    .bind(&correct)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    poll_options.sort_by_key(|option| option.position);
+    Ok((poll, poll_options))
 }
 
 // pub async fn update_poll(pool: &PgPool, poll_id: Uuid, new_title: &str, new_description: &str) -> Result<(), sqlx::Error>{
@@ -36,147 +260,446 @@ pub async fn create_poll(
 //     Ok(())
 // }
 
-pub async fn create_option(
+/**
+Closes a poll. Returns whether it was still open.
+*/
+pub async fn close_poll<'e, E>(executor: E, poll_id: Uuid) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
+        r#"
+        UPDATE polls SET is_active = FALSE, closed_at = COALESCE(closed_at, CURRENT_TIMESTAMP)
+        FROM (SELECT is_active FROM polls WHERE id = $1 FOR UPDATE) AS previous
+        WHERE polls.id = $1
+        RETURNING previous.is_active AS was_active
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.is_some_and(|row| row.get("was_active")))
+}
+
+/**
+Reopens a poll. Returns whether it was closed.
+*/
+pub async fn reopen_poll<'e, E>(executor: E, poll_id: Uuid) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
+        r#"
+        UPDATE polls SET is_active = TRUE, closed_at = NULL
+        FROM (SELECT is_active FROM polls WHERE id = $1 FOR UPDATE) AS previous
+        WHERE polls.id = $1
+        RETURNING previous.is_active AS was_active
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.is_some_and(|row| !row.get::<bool, _>("was_active")))
+}
+
+pub async fn set_results_visibility<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    visibility: ResultsVisibility,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        UPDATE polls SET results_visibility = $2 WHERE id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .bind(visibility)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/**
+Sets or clears (`None`) the recurrence rule of a poll together with its next run.
+*/
+pub async fn set_recurrence<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    recurrence: Option<&str>,
+    next_run_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        UPDATE polls SET recurrence = $2, next_run_at = $3 WHERE id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .bind(recurrence)
+    .bind(next_run_at)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/**
+Locks the recurring poll that is most overdue, skipping rows another server instance is
+already rolling over.
+*/
+pub async fn lock_due_recurring_poll(conn: &mut PgConnection) -> Result<Option<Poll>, sqlx::Error> {
+    let poll: Option<Poll> = sqlx::query_as(
+        r#"
+        SELECT * FROM polls
+        WHERE recurrence IS NOT NULL AND next_run_at <= NOW()
+        ORDER BY next_run_at
+        LIMIT 1
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(conn)
+    .await?;
+    Ok(poll)
+}
+
+/**
+Closes an instance of a recurring series and hands the rule over to its successor.
+Returns whether the instance was still open; one the owner closed keeps its close time.
+*/
+pub async fn end_recurring_instance(
+    conn: &mut PgConnection,
+    poll_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        UPDATE polls
+        SET is_active = false, closed_at = COALESCE(closed_at, CURRENT_TIMESTAMP),
+            recurrence = NULL, next_run_at = NULL
+        FROM (SELECT is_active FROM polls WHERE id = $1) AS previous
+        WHERE polls.id = $1
+        RETURNING previous.is_active AS was_active
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_one(conn)
+    .await?;
+    Ok(row.get("was_active"))
+}
+
+/**
+Changes how votes are weighed; `None` leaves a setting untouched. Votes already cast keep
+the weight they were cast with.
+*/
+pub async fn set_weighting<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    weighted: Option<bool>,
+    default_weight: Option<f64>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        UPDATE polls
+        SET weighted = COALESCE($2, weighted), default_weight = COALESCE($3, default_weight)
+        WHERE id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .bind(weighted)
+    .bind(default_weight)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+impl From<&Poll> for OutcomeRules {
+    fn from(poll: &Poll) -> Self {
+        OutcomeRules {
+            participants_only: poll.participants_only,
+            quorum_votes: poll.quorum_votes,
+            quorum_percent: poll.quorum_percent,
+            pass_threshold: poll.pass_threshold,
+            auto_close: poll.auto_close,
+        }
+    }
+}
+
+pub async fn set_outcome_rules<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    rules: &OutcomeRules,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        UPDATE polls
+        SET participants_only = $2, quorum_votes = $3, quorum_percent = $4,
+            pass_threshold = $5, auto_close = $6
+        WHERE id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .bind(rules.participants_only)
+    .bind(rules.quorum_votes)
+    .bind(rules.quorum_percent)
+    .bind(rules.pass_threshold)
+    .bind(rules.auto_close)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/**
+Turns the poll into a quiz scored by `quiz` with `correct_option_ids` as its right
+answers, or back into a plain poll when `quiz` is `None`. Answers start out hidden again.
+*/
+pub async fn set_quiz(
     pool: &PgPool,
-    option_id: Uuid,
     poll_id: Uuid,
-    option_text: String,
+    quiz: Option<&QuizSettings>,
+    correct_option_ids: &[Uuid],
 ) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
     sqlx::query(
         r#"
-        INSERT INTO poll_options (id, poll_id, option_text)
-        VALUES ($1, $2, $3)
+        UPDATE polls
+        SET quiz = $2, quiz_points = COALESCE($3, quiz_points), quiz_bonus_points = $4,
+            quiz_bonus_seconds = $5, quiz_revealed = FALSE
+        WHERE id = $1
         "#,
     )
-    .bind(option_id)
     .bind(poll_id)
-    .bind(option_text)
-    .execute(pool)
+    .bind(quiz.is_some())
+    .bind(quiz.map(|quiz| quiz.points))
+    .bind(quiz.and_then(|quiz| quiz.bonus_points))
+    .bind(quiz.and_then(|quiz| quiz.bonus_seconds))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE poll_options SET is_correct = (id = ANY($2)) WHERE poll_id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .bind(correct_option_ids)
+    .execute(&mut *tx)
     .await?;
+    tx.commit().await?;
     Ok(())
 }
 
-pub async fn close_poll(pool: &PgPool, poll_id: Uuid) -> Result<(), sqlx::Error> {
+pub async fn set_quiz_revealed(
+    pool: &PgPool,
+    poll_id: Uuid,
+    revealed: bool,
+) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-        UPDATE polls SET is_active = FALSE WHERE id = $1
+        UPDATE polls SET quiz_revealed = $2 WHERE id = $1
         "#,
     )
     .bind(poll_id)
+    .bind(revealed)
     .execute(pool)
     .await?;
     Ok(())
 }
 
-pub async fn delete_votes(pool: &PgPool, poll_id: Uuid) -> Result<(), sqlx::Error> {
+/**
+Deletes every vote on the poll, logging each one as a `reset` event.
+*/
+pub async fn delete_votes<'e, E>(executor: E, poll_id: Uuid) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     sqlx::query(
         r#"
-        DELETE FROM votes WHERE poll_option_id IN (SELECT id FROM poll_options WHERE poll_id = $1)
+        WITH removed AS (
+            DELETE FROM votes
+            WHERE poll_option_id IN (SELECT id FROM poll_options WHERE poll_id = $1)
+            RETURNING user_id, poll_option_id
+        )
+        INSERT INTO vote_events (poll_id, poll_option_id, user_id, action)
+        SELECT $1, poll_option_id, user_id, 'reset' FROM removed
         "#,
     )
     .bind(poll_id)
-    .execute(pool)
+    .execute(executor)
     .await?;
     Ok(())
 }
 
-pub async fn reset_votes_count(pool: &PgPool, poll_id: Uuid) -> Result<(), sqlx::Error> {
+pub async fn reset_votes_count<'e, E>(executor: E, poll_id: Uuid) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     sqlx::query(
         r#"
-        UPDATE poll_options SET votes_count = 0 WHERE poll_id = $1
+        UPDATE poll_options SET votes_count = 0, weighted_votes = 0 WHERE poll_id = $1
         "#,
     )
     .bind(poll_id)
-    .execute(pool)
+    .execute(executor)
     .await?;
     Ok(())
 }
 
-pub async fn delete_poll_options(pool: &PgPool, poll_id: Uuid) -> Result<(), sqlx::Error> {
+pub async fn delete_poll_options<'e, E>(executor: E, poll_id: Uuid) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     sqlx::query(
         r#"
-        DELETE FROM poll_options WHERE id = $1
+        DELETE FROM poll_options WHERE poll_id = $1
         "#,
     )
     .bind(poll_id)
-    .execute(pool)
+    .execute(executor)
     .await?;
     Ok(())
 }
 
-pub async fn delete_poll(pool: &PgPool, poll_id: Uuid) -> Result<(), sqlx::Error> {
+pub async fn delete_poll<'e, E>(executor: E, poll_id: Uuid) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     sqlx::query(
         r#"
         DELETE FROM polls WHERE id = $1
         "#,
     )
     .bind(poll_id)
-    .execute(pool)
+    .execute(executor)
     .await?;
     Ok(())
 }
 
-pub async fn vote(
-    pool: &PgPool,
+/**
+Records a vote and its `cast` event in one statement. The vote keeps the voter's current
+weight in the poll (personal weight, else role weight, else the poll default), which is
+returned.
+*/
+pub async fn vote<'e, E>(
+    executor: E,
     poll_option_id: Uuid,
     user_id: Uuid,
     vote_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query(
+) -> Result<f64, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
         r#"
-        INSERT INTO votes (id, user_id, poll_option_id)
-        VALUES ($1, $2, $3)
+        WITH cast_vote AS (
+            INSERT INTO votes (id, user_id, poll_option_id, weight)
+            SELECT $1, $2, poll_options.id,
+                COALESCE(poll_participants.weight, poll_roles.weight, polls.default_weight)
+            FROM poll_options
+            JOIN polls ON poll_options.poll_id = polls.id
+            LEFT JOIN poll_participants
+                ON poll_participants.poll_id = polls.id AND poll_participants.user_id = $2
+            LEFT JOIN poll_roles ON poll_participants.role_id = poll_roles.id
+            WHERE poll_options.id = $3
+            RETURNING user_id, poll_option_id, voted_at, weight
+        ), logged AS (
+            INSERT INTO vote_events (poll_id, poll_option_id, user_id, action, occurred_at)
+            SELECT poll_options.poll_id, cast_vote.poll_option_id, cast_vote.user_id, 'cast',
+                cast_vote.voted_at
+            FROM cast_vote
+            JOIN poll_options ON cast_vote.poll_option_id = poll_options.id
+        )
+        SELECT weight FROM cast_vote
         "#,
     )
     .bind(vote_id)
     .bind(user_id)
     .bind(poll_option_id)
-    .execute(pool)
+    .fetch_one(executor)
     .await?;
-    Ok(())
+    Ok(row.get("weight"))
 }
 
-pub async fn delete_vote(
-    pool: &PgPool,
+/**
+Removes a user's vote and logs it as a `removed` event. Returns the weight the vote
+carried, or `None` when there was no such vote.
+*/
+pub async fn delete_vote<'e, E>(
+    executor: E,
     poll_option_id: Uuid,
     user_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query(
+) -> Result<Option<f64>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
         r#"
-        DELETE FROM votes WHERE user_id = $1 AND poll_option_id = $2
+        WITH removed AS (
+            DELETE FROM votes WHERE user_id = $1 AND poll_option_id = $2
+            RETURNING user_id, poll_option_id, weight
+        ), logged AS (
+            INSERT INTO vote_events (poll_id, poll_option_id, user_id, action)
+            SELECT poll_options.poll_id, removed.poll_option_id, removed.user_id, 'removed'
+            FROM removed
+            JOIN poll_options ON removed.poll_option_id = poll_options.id
+        )
+        SELECT weight FROM removed
         "#,
     )
     .bind(user_id)
     .bind(poll_option_id)
-    .execute(pool)
+    .fetch_optional(executor)
     .await?;
-    Ok(())
+    Ok(row.map(|row| row.get("weight")))
 }
 
-pub async fn increase_vote_count(pool: &PgPool, poll_option_id: Uuid) -> Result<(), sqlx::Error> {
+pub async fn increase_vote_count<'e, E>(
+    executor: E,
+    poll_option_id: Uuid,
+    weight: f64,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     sqlx::query(
         r#"
         UPDATE poll_options
-        SET votes_count = votes_count + 1
+        SET votes_count = votes_count + 1, weighted_votes = weighted_votes + $2
         WHERE id = $1
         "#,
     )
     .bind(poll_option_id)
-    .execute(pool)
+    .bind(weight)
+    .execute(executor)
     .await?;
     Ok(())
 }
 
-pub async fn decrease_vote_count(pool: &PgPool, poll_option_id: Uuid) -> Result<(), sqlx::Error> {
+pub async fn decrease_vote_count<'e, E>(
+    executor: E,
+    poll_option_id: Uuid,
+    weight: f64,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     sqlx::query(
         r#"
         UPDATE poll_options
-        SET votes_count = votes_count - 1
+        SET votes_count = votes_count - 1, weighted_votes = weighted_votes - $2
         WHERE id = $1
         "#,
     )
     .bind(poll_option_id)
-    .execute(pool)
+    .bind(weight)
+    .execute(executor)
     .await?;
     Ok(())
 }
@@ -189,123 +712,282 @@ pub struct Poll {
     pub description: String,
     pub is_active: bool,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub secret_ballot: bool,
+    pub recurrence: Option<String>,
+    pub next_run_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub previous_poll_id: Option<Uuid>,
+    pub results_visibility: ResultsVisibility,
+    pub weighted: bool,
+    pub default_weight: f64,
+    pub participants_only: bool,
+    pub quorum_votes: Option<i32>,
+    pub quorum_percent: Option<f64>,
+    pub pass_threshold: PassThreshold,
+    pub auto_close: bool,
+    pub kind: QuestionKind,
+    pub scale_max: Option<i32>,
+    pub number_min: Option<f64>,
+    pub number_max: Option<f64>,
+    pub text_max_length: Option<i32>,
+    pub quiz: bool,
+    pub quiz_points: i32,
+    pub quiz_bonus_points: Option<i32>,
+    pub quiz_bonus_seconds: Option<i32>,
+    pub quiz_started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub quiz_revealed: bool,
+}
+
+#[cfg(test)]
+impl Poll {
+    /// An open choice poll with every setting at its default, for tests to adjust
+    pub fn fixture() -> Self {
+        Poll {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            title: "Fixture".to_string(),
+            description: String::new(),
+            is_active: true,
+            created_at: chrono::Utc::now(),
+            secret_ballot: false,
+            recurrence: None,
+            next_run_at: None,
+            previous_poll_id: None,
+            results_visibility: ResultsVisibility::default(),
+            weighted: false,
+            default_weight: 1.0,
+            participants_only: false,
+            quorum_votes: None,
+            quorum_percent: None,
+            pass_threshold: PassThreshold::default(),
+            auto_close: false,
+            kind: QuestionKind::default(),
+            scale_max: None,
+            number_min: None,
+            number_max: None,
+            text_max_length: None,
+            quiz: false,
+            quiz_points: 100,
+            quiz_bonus_points: None,
+            quiz_bonus_seconds: None,
+            quiz_started_at: None,
+            quiz_revealed: false,
+        }
+    }
 }
 
-pub async fn get_poll(pool: &PgPool, poll_id: Uuid) -> Result<Poll, sqlx::Error> {
+pub async fn get_poll<'e, E>(executor: E, poll_id: Uuid) -> Result<Poll, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     println!("{:?}", poll_id);
-    let poll = sqlx::query(
+    let poll: Poll = sqlx::query_as(
         r#"
         SELECT * FROM polls WHERE id = $1
         "#,
     )
     .bind(poll_id)
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
-    let poll = Poll {
-        id: poll.get("id"),
-        user_id: poll.get("user_id"),
-        title: poll.get("title"),
-        description: poll.get("description"),
-        is_active: poll.get("is_active"),
-        created_at: poll.get("created_at"),
-    };
     println!("{:?}", poll);
     Ok(poll)
 }
 
+/**
+Like [get_poll], but also locks the poll's row until the transaction ends, so changes
+that depend on the poll's state, like a user's single ballot, are made one at a time.
+*/
+pub async fn lock_poll<'e, E>(executor: E, poll_id: Uuid) -> Result<Poll, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let poll: Poll = sqlx::query_as(
+        r#"
+        SELECT * FROM polls WHERE id = $1 FOR UPDATE
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(poll)
+}
+
 pub async fn get_user_polls_brief(pool: &PgPool, user_id: Uuid) -> Result<Vec<Poll>, sqlx::Error> {
-    let polls = sqlx::query(
+    let polls: Vec<Poll> = sqlx::query_as(
         r#"
-        SELECT * FROM polls WHERE user_id = $1
+        SELECT * FROM polls
+        WHERE user_id = $1
+            AND NOT EXISTS (SELECT 1 FROM survey_questions WHERE poll_id = polls.id)
         "#,
     )
     .bind(user_id)
     .fetch_all(pool)
     .await?;
-    let polls = polls
-        .iter()
-        .map(|poll| Poll {
-            id: poll.get("id"),
-            user_id: poll.get("user_id"),
-            title: poll.get("title"),
-            description: poll.get("description"),
-            is_active: poll.get("is_active"),
-            created_at: poll.get("created_at"),
-        })
-        .collect();
     Ok(polls)
 }
 
 pub async fn get_all_polls(pool: &PgPool) -> Result<Vec<Poll>, sqlx::Error> {
-    let polls = sqlx::query(
+    let polls: Vec<Poll> = sqlx::query_as(
         r#"
         SELECT * FROM polls
+        WHERE NOT EXISTS (SELECT 1 FROM survey_questions WHERE poll_id = polls.id)
         "#,
     )
     .fetch_all(pool)
     .await?;
-    let polls = polls
-        .iter()
-        .map(|poll| Poll {
-            id: poll.get("id"),
-            user_id: poll.get("user_id"),
-            title: poll.get("title"),
-            description: poll.get("description"),
-            is_active: poll.get("is_active"),
-            created_at: poll.get("created_at"),
-        })
-        .collect();
     Ok(polls)
 }
 
-#[derive(sqlx::FromRow, Serialize, Debug)]
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
 pub struct PollOption {
     pub id: Uuid,
     pub poll_id: Uuid,
     pub option_text: String,
     pub votes_count: Option<i32>,
+    /// Summed weight of the option's votes
+    pub weighted_votes: Option<f64>,
+    pub position: i32,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub image_asset_id: Option<Uuid>,
+    pub color: Option<String>,
+    /// Whether the option is a correct quiz answer; absent for polls that are not quizzes
+    /// and for voters until the answers are revealed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_correct: Option<bool>,
+}
+
+/**
+Changes to an option's content. `None` leaves a column untouched, `Some(None)` clears it.
+*/
+#[derive(Debug, Default)]
+pub struct PollOptionChanges {
+    pub option_text: Option<String>,
+    pub description: Option<Option<String>>,
+    pub image_url: Option<Option<String>>,
+    pub image_asset_id: Option<Option<Uuid>>,
+    pub color: Option<Option<String>>,
 }
-pub async fn get_poll_options_data(
+
+pub async fn update_option(
     pool: &PgPool,
     poll_id: Uuid,
-) -> Result<Vec<PollOption>, sqlx::Error> {
-    let poll_options: Vec<PollOption> = sqlx::query_as(
+    poll_option_id: Uuid,
+    changes: &PollOptionChanges,
+) -> Result<PollOption, sqlx::Error> {
+    let option: PollOption = sqlx::query_as(
         r#"
-        SELECT * FROM poll_options WHERE poll_id = $1
+        UPDATE poll_options SET
+            option_text = COALESCE($3, option_text),
+            description = CASE WHEN $4 THEN $5 ELSE description END,
+            image_url = CASE WHEN $6 THEN $7 ELSE image_url END,
+            image_asset_id = CASE WHEN $8 THEN $9 ELSE image_asset_id END,
+            color = CASE WHEN $10 THEN $11 ELSE color END
+        WHERE id = $2 AND poll_id = $1
+        RETURNING *
         "#,
     )
     .bind(poll_id)
-    .fetch_all(pool)
+    .bind(poll_option_id)
+    .bind(&changes.option_text)
+    .bind(changes.description.is_some())
+    .bind(changes.description.clone().flatten())
+    .bind(changes.image_url.is_some())
+    .bind(changes.image_url.clone().flatten())
+    .bind(changes.image_asset_id.is_some())
+    .bind(changes.image_asset_id.flatten())
+    .bind(changes.color.is_some())
+    .bind(changes.color.clone().flatten())
+    .fetch_one(pool)
     .await?;
-    Ok(poll_options)
+    Ok(option)
 }
 
-pub async fn is_poll_active(pool: &PgPool, poll_id: Uuid) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query(
+/**
+Rewrites `position` for every option of a poll to match `option_ids`.
+*/
+pub async fn reorder_options(
+    pool: &PgPool,
+    poll_id: Uuid,
+    option_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
         r#"
-        SELECT is_active FROM polls WHERE id = $1
+        UPDATE poll_options
+        SET position = (ordered.ordinality - 1)::INT
+        FROM UNNEST($2::UUID[]) WITH ORDINALITY AS ordered(id, ordinality)
+        WHERE poll_options.id = ordered.id AND poll_options.poll_id = $1
         "#,
     )
     .bind(poll_id)
-    .fetch_optional(pool)
+    .bind(option_ids)
+    .execute(pool)
     .await?;
+    Ok(())
+}
 
-    match result {
-        Some(record) => match record.get("is_active") {
-            Some(true) => Ok(true),
-            Some(false) => Ok(false),
-            None => Err(sqlx::Error::RowNotFound),
-        },
-        None => Err(sqlx::Error::RowNotFound),
-    }
+pub async fn get_poll_option_ids(pool: &PgPool, poll_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id FROM poll_options WHERE poll_id = $1 ORDER BY position
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.iter().map(|row| row.get("id")).collect())
+}
+pub async fn get_poll_options_data<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+) -> Result<Vec<PollOption>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let poll_options: Vec<PollOption> = sqlx::query_as(
+        r#"
+        SELECT * FROM poll_options WHERE poll_id = $1 ORDER BY position
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_all(executor)
+    .await?;
+    Ok(poll_options)
 }
 
-pub async fn has_user_voted(
-    pool: &PgPool,
+pub async fn is_option_in_poll<'e, E>(
+    executor: E,
     poll_option_id: Uuid,
+    poll_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM poll_options WHERE id = $1 AND poll_id = $2
+        )
+        "#,
+    )
+    .bind(poll_option_id)
+    .bind(poll_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(row.get::<Option<bool>, _>("exists").unwrap_or(false))
+}
+
+/**
+Whether the user has a ballot in the poll: a vote, or an answer to a scale, number or
+text question.
+*/
+pub async fn has_user_voted<'e, E>(
+    executor: E,
     user_id: Uuid,
     poll_id: Uuid,
-) -> Result<bool, sqlx::Error> {
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     let vote = sqlx::query(
         r#"
         SELECT EXISTS(
@@ -313,12 +995,16 @@ pub async fn has_user_voted(
             FROM votes 
             JOIN poll_options ON votes.poll_option_id = poll_options.id 
             WHERE votes.user_id = $1 AND poll_options.poll_id = $2
-        )
+        ) OR EXISTS(
+            SELECT 1 FROM numeric_answers WHERE user_id = $1 AND poll_id = $2
+        ) OR EXISTS(
+            SELECT 1 FROM text_answers WHERE user_id = $1 AND poll_id = $2
+        ) AS exists
         "#,
     )
     .bind(user_id)
     .bind(poll_id)
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
     Ok(match vote.get("exists") {
         Some(true) => true,
@@ -327,6 +1013,26 @@ pub async fn has_user_voted(
     })
 }
 
+pub async fn get_user_vote_option(
+    pool: &PgPool,
+    user_id: Uuid,
+    poll_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let vote = sqlx::query(
+        r#"
+        SELECT votes.poll_option_id
+        FROM votes
+        JOIN poll_options ON votes.poll_option_id = poll_options.id
+        WHERE votes.user_id = $1 AND poll_options.poll_id = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(poll_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(vote.map(|row| row.get("poll_option_id")))
+}
+
 pub async fn does_poll_exist(pool: &PgPool, poll_id: Uuid) -> Result<Uuid, sqlx::Error> {
     let result = sqlx::query(
         r#"
@@ -342,3 +1048,62 @@ pub async fn does_poll_exist(pool: &PgPool, poll_id: Uuid) -> Result<Uuid, sqlx:
         None => Err(sqlx::Error::RowNotFound),
     }
 }
+
+/**
+A single vote on a poll, as listed in result exports
+*/
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct Ballot {
+    pub voter_id: Uuid,
+    pub username: String,
+    pub option_id: Uuid,
+    pub option_text: String,
+    pub weight: f64,
+    pub voted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub async fn get_poll_ballots(pool: &PgPool, poll_id: Uuid) -> Result<Vec<Ballot>, sqlx::Error> {
+    let ballots: Vec<Ballot> = sqlx::query_as(
+        r#"
+        SELECT votes.user_id AS voter_id, users.username, poll_options.id AS option_id,
+               poll_options.option_text, votes.weight, votes.voted_at
+        FROM votes
+        JOIN poll_options ON votes.poll_option_id = poll_options.id
+        JOIN users ON votes.user_id = users.id
+        WHERE poll_options.poll_id = $1
+        ORDER BY votes.voted_at, votes.id
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(ballots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{connect, insert_poll, insert_user};
+    use sqlx::Acquire;
+
+    #[tokio::test]
+    #[ignore = "needs the database of DATABASE_URL"]
+    async fn options_only_belong_to_their_own_poll() {
+        let mut conn = connect().await;
+        let mut tx = conn.begin().await.unwrap();
+        let owner = insert_user(&mut tx, "owner").await;
+        let (poll, options) = insert_poll(&mut tx, owner, &["Pizza", "Pasta"]).await;
+        let (other_poll, other_options) = insert_poll(&mut tx, owner, &["Tea", "Coffee"]).await;
+
+        assert!(is_option_in_poll(&mut *tx, options[1], poll).await.unwrap());
+        assert!(!is_option_in_poll(&mut *tx, other_options[0], poll)
+            .await
+            .unwrap());
+        assert!(!is_option_in_poll(&mut *tx, options[0], other_poll)
+            .await
+            .unwrap());
+        assert!(!is_option_in_poll(&mut *tx, Uuid::new_v4(), poll)
+            .await
+            .unwrap());
+    }
+}
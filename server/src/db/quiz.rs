@@ -0,0 +1,151 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgExecutor};
+use utoipa::ToSchema;
+
+/**
+A voter's standing over one or more quiz polls
+*/
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct QuizScore {
+    /// Shared by voters with the same score
+    pub rank: i64,
+    pub user_id: Uuid,
+    pub username: String,
+    pub score: i64,
+    pub correct_answers: i64,
+    pub answered: i64,
+}
+
+/**
+Scores every voter of the quiz polls among `poll_ids`, best first. A correct vote earns
+the poll's points plus whatever is left of its time bonus when the vote was cast.
+Unless `include_unrevealed` is set, only polls whose answers are revealed count.
+*/
+pub async fn get_scores<'e, E>(
+    executor: E,
+    poll_ids: &[Uuid],
+    include_unrevealed: bool,
+) -> Result<Vec<QuizScore>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let scores: Vec<QuizScore> = sqlx::query_as(
+        r#"
+        WITH scored AS (
+            SELECT votes.user_id, poll_options.is_correct,
+                CASE WHEN poll_options.is_correct THEN
+                    polls.quiz_points + COALESCE(ROUND(polls.quiz_bonus_points * GREATEST(0,
+                        LEAST(1, 1 - EXTRACT(EPOCH FROM votes.voted_at
+                            - COALESCE(polls.quiz_started_at, polls.created_at))
+                            / polls.quiz_bonus_seconds))), 0)
+                ELSE 0 END AS points
+            FROM polls
+            JOIN poll_options ON poll_options.poll_id = polls.id
+            JOIN votes ON votes.poll_option_id = poll_options.id
+            WHERE polls.id = ANY($1) AND polls.quiz AND (polls.quiz_revealed OR $2)
+        )
+        SELECT RANK() OVER (ORDER BY SUM(scored.points) DESC) AS rank,
+            scored.user_id, users.username, SUM(scored.points)::BIGINT AS score,
+            COUNT(*) FILTER (WHERE scored.is_correct) AS correct_answers,
+            COUNT(*) AS answered
+        FROM scored
+        JOIN users ON scored.user_id = users.id
+        GROUP BY scored.user_id, users.username
+        ORDER BY score DESC, correct_answers DESC, users.username
+        "#,
+    )
+    .bind(poll_ids)
+    .bind(include_unrevealed)
+    .fetch_all(executor)
+    .await?;
+    Ok(scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{connect, insert_poll, insert_user};
+    use sqlx::{Acquire, PgConnection};
+
+    /// A revealed quiz worth 100 points plus `bonus` (points, seconds) whose first option
+    /// is correct
+    async fn insert_quiz(
+        conn: &mut PgConnection,
+        owner: Uuid,
+        bonus: Option<(i32, i32)>,
+    ) -> (Uuid, Uuid, Uuid) {
+        let (poll_id, options) = insert_poll(conn, owner, &["Right", "Wrong"]).await;
+        sqlx::query(
+            r#"
+            UPDATE polls SET quiz = TRUE, quiz_points = 100, quiz_bonus_points = $2,
+                quiz_bonus_seconds = $3, quiz_started_at = '2025-01-01T12:00:00Z',
+                quiz_revealed = TRUE
+            WHERE id = $1
+            "#,
+        )
+        .bind(poll_id)
+        .bind(bonus.map(|(points, _)| points))
+        .bind(bonus.map(|(_, seconds)| seconds))
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        sqlx::query("UPDATE poll_options SET is_correct = TRUE WHERE id = $1")
+            .bind(options[0])
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        (poll_id, options[0], options[1])
+    }
+
+    /// Casts a vote `seconds` after the quiz started
+    async fn vote(conn: &mut PgConnection, user_id: Uuid, option_id: Uuid, seconds: f64) {
+        sqlx::query(
+            r#"
+            INSERT INTO votes (id, user_id, poll_option_id, voted_at)
+            VALUES ($1, $2, $3, '2025-01-01T12:00:00Z'::TIMESTAMPTZ + make_interval(secs => $4))
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(option_id)
+        .bind(seconds)
+        .execute(conn)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the database of DATABASE_URL"]
+    async fn the_time_bonus_shrinks_until_it_runs_out() {
+        let mut conn = connect().await;
+        let mut tx = conn.begin().await.unwrap();
+
+        let owner = insert_user(&mut tx, "owner").await;
+        // 100 points, plus up to 50 that run out over 10 seconds
+        let (timed, right, wrong) = insert_quiz(&mut tx, owner, Some((50, 10))).await;
+        let (untimed, untimed_right, _) = insert_quiz(&mut tx, owner, None).await;
+
+        let mut expected = vec![];
+        for (name, option, seconds, score) in [
+            ("instant", right, 0.0, 150),
+            ("quarter", right, 2.5, 138),
+            ("half", right, 5.0, 125),
+            ("late", right, 20.0, 100),
+            ("wrong", wrong, 1.0, 0),
+        ] {
+            let user_id = insert_user(&mut tx, name).await;
+            vote(&mut tx, user_id, option, seconds).await;
+            expected.push((user_id, score));
+        }
+        // A quiz without a time bonus only adds its points, however long the vote took
+        vote(&mut tx, expected[3].0, untimed_right, 600.0).await;
+        expected[3].1 += 100;
+
+        let scores = get_scores(&mut *tx, &[timed, untimed], false)
+            .await
+            .unwrap();
+        let scores: Vec<(Uuid, i64)> = scores.iter().map(|s| (s.user_id, s.score)).collect();
+        expected.sort_by_key(|(_, score)| -score);
+        assert_eq!(scores, expected);
+    }
+}
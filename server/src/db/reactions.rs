@@ -0,0 +1,88 @@
+use sqlx::{types::Uuid, PgPool};
+
+/**
+How often one emoji was sent on a poll during one time window
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct ReactionCount {
+    pub window_start: chrono::DateTime<chrono::Utc>,
+    pub emoji: String,
+    pub count: i64,
+}
+
+/**
+Records a reaction, unless the user already sent `limit` reactions on the poll since
+`since`. Returns whether it was recorded. Concurrent reactions of the same user on the
+same poll wait for each other, so a burst cannot slip past the limit.
+*/
+pub async fn add_reaction(
+    pool: &PgPool,
+    poll_id: Uuid,
+    user_id: Uuid,
+    emoji: &str,
+    limit: i64,
+    since: chrono::DateTime<chrono::Utc>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        SELECT pg_advisory_xact_lock(hashtext($1::TEXT || $2::TEXT))
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    let added = sqlx::query(
+        r#"
+        INSERT INTO reactions (poll_id, user_id, emoji)
+        SELECT $1, $2, $3
+        WHERE (
+            SELECT COUNT(*) FROM reactions
+            WHERE poll_id = $1 AND user_id = $2 AND reacted_at > $5
+        ) < $4
+        RETURNING id
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .bind(emoji)
+    .bind(limit)
+    .bind(since)
+    .fetch_optional(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(added.is_some())
+}
+
+/**
+Counts the poll's reactions sent in `[from, to)` per emoji and window of
+`window_seconds`, oldest window first. Windows are aligned on the Unix epoch, so every
+reader buckets reactions the same way.
+*/
+pub async fn get_reaction_counts(
+    pool: &PgPool,
+    poll_id: Uuid,
+    window_seconds: i64,
+    from: chrono::DateTime<chrono::Utc>,
+    to: chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<ReactionCount>, sqlx::Error> {
+    let counts: Vec<ReactionCount> = sqlx::query_as(
+        r#"
+        SELECT to_timestamp((FLOOR(EXTRACT(EPOCH FROM reacted_at) / $2) * $2)::DOUBLE PRECISION)
+                AS window_start,
+            emoji, COUNT(*) AS count
+        FROM reactions
+        WHERE poll_id = $1 AND reacted_at >= $3 AND reacted_at < $4
+        GROUP BY window_start, emoji
+        ORDER BY window_start, count DESC, emoji
+        "#,
+    )
+    .bind(poll_id)
+    .bind(window_seconds)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+    Ok(counts)
+}
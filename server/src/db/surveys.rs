@@ -0,0 +1,286 @@
+use crate::db::polls::Poll;
+use serde::Serialize;
+use sqlx::{types::Uuid, PgExecutor, PgPool, Row};
+use utoipa::ToSchema;
+
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct Survey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub is_active: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/**
+A question of a survey: the poll holding it and when it is asked
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct SurveyQuestion {
+    #[sqlx(flatten)]
+    pub poll: Poll,
+    pub required: bool,
+    pub condition_poll_id: Option<Uuid>,
+    pub condition_option_ids: Vec<Uuid>,
+    pub condition_min: Option<f64>,
+    pub condition_max: Option<f64>,
+}
+
+impl SurveyQuestion {
+    pub fn condition(&self) -> Option<QuestionCondition> {
+        Some(QuestionCondition {
+            question_id: self.condition_poll_id?,
+            option_ids: self.condition_option_ids.clone(),
+            min: self.condition_min,
+            max: self.condition_max,
+        })
+    }
+}
+
+/**
+Asks a question only when the answer to an earlier question matches: a choice answer
+picking one of `option_ids`, a scale or number answer within `min`..=`max`, or any text
+answer.
+*/
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct QuestionCondition {
+    /// The earlier question
+    pub question_id: Uuid,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub option_ids: Vec<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+pub async fn create_survey<'e, E>(
+    executor: E,
+    survey_id: Uuid,
+    user_id: Uuid,
+    title: &str,
+    description: &str,
+) -> Result<Survey, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let survey: Survey = sqlx::query_as(
+        r#"
+        INSERT INTO surveys (id, user_id, title, description)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(survey_id)
+    .bind(user_id)
+    .bind(title)
+    .bind(description)
+    .fetch_one(executor)
+    .await?;
+    Ok(survey)
+}
+
+pub async fn add_question<'e, E>(
+    executor: E,
+    survey_id: Uuid,
+    poll_id: Uuid,
+    position: i32,
+    required: bool,
+    condition: Option<&QuestionCondition>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO survey_questions
+            (survey_id, poll_id, position, required, condition_poll_id, condition_option_ids,
+                condition_min, condition_max)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(survey_id)
+    .bind(poll_id)
+    .bind(position)
+    .bind(required)
+    .bind(condition.map(|c| c.question_id))
+    .bind(condition.map(|c| c.option_ids.clone()).unwrap_or_default())
+    .bind(condition.and_then(|c| c.min))
+    .bind(condition.and_then(|c| c.max))
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_survey(pool: &PgPool, survey_id: Uuid) -> Result<Survey, sqlx::Error> {
+    let survey: Survey = sqlx::query_as(
+        r#"
+        SELECT * FROM surveys WHERE id = $1
+        "#,
+    )
+    .bind(survey_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(survey)
+}
+
+pub async fn get_user_surveys(pool: &PgPool, user_id: Uuid) -> Result<Vec<Survey>, sqlx::Error> {
+    let surveys: Vec<Survey> = sqlx::query_as(
+        r#"
+        SELECT * FROM surveys WHERE user_id = $1 ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(surveys)
+}
+
+/**
+The survey's questions with their polls, in the order they are asked
+*/
+pub async fn get_questions<'e, E>(
+    executor: E,
+    survey_id: Uuid,
+) -> Result<Vec<SurveyQuestion>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let questions: Vec<SurveyQuestion> = sqlx::query_as(
+        r#"
+        SELECT polls.*, survey_questions.required, survey_questions.condition_poll_id,
+            survey_questions.condition_option_ids, survey_questions.condition_min,
+            survey_questions.condition_max
+        FROM survey_questions
+        JOIN polls ON polls.id = survey_questions.poll_id
+        WHERE survey_questions.survey_id = $1
+        ORDER BY survey_questions.position
+        "#,
+    )
+    .bind(survey_id)
+    .fetch_all(executor)
+    .await?;
+    Ok(questions)
+}
+
+/**
+The survey a poll is a question of, if any
+*/
+pub async fn survey_of_poll(pool: &PgPool, poll_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT survey_id FROM survey_questions WHERE poll_id = $1
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| row.get("survey_id")))
+}
+
+/**
+Updates the given fields. Opening or closing the survey opens or closes its questions
+along with it.
+*/
+pub async fn update_survey(
+    pool: &PgPool,
+    survey_id: Uuid,
+    title: Option<&str>,
+    description: Option<&str>,
+    is_active: Option<bool>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        WITH updated AS (
+            UPDATE surveys
+            SET title = COALESCE($2, title),
+                description = COALESCE($3, description),
+                is_active = COALESCE($4, is_active)
+            WHERE id = $1
+            RETURNING id, is_active
+        )
+        UPDATE polls
+        SET is_active = updated.is_active,
+            closed_at = CASE
+                WHEN updated.is_active THEN NULL
+                ELSE COALESCE(polls.closed_at, CURRENT_TIMESTAMP)
+            END
+        FROM updated
+        JOIN survey_questions ON survey_questions.survey_id = updated.id
+        WHERE polls.id = survey_questions.poll_id AND $4 IS NOT NULL
+        "#,
+    )
+    .bind(survey_id)
+    .bind(title)
+    .bind(description)
+    .bind(is_active)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/**
+Deletes the survey together with the polls holding its questions.
+*/
+pub async fn delete_survey(pool: &PgPool, survey_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        DELETE FROM polls
+        WHERE id IN (SELECT poll_id FROM survey_questions WHERE survey_id = $1)
+        "#,
+    )
+    .bind(survey_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        DELETE FROM surveys WHERE id = $1
+        "#,
+    )
+    .bind(survey_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/**
+Marks the user as having submitted the survey; fails with a unique violation when they
+already did.
+*/
+pub async fn record_response<'e, E>(
+    executor: E,
+    survey_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO survey_responses (survey_id, user_id) VALUES ($1, $2)
+        "#,
+    )
+    .bind(survey_id)
+    .bind(user_id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn count_responses<'e, E>(executor: E, survey_id: Uuid) -> Result<i64, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS count FROM survey_responses WHERE survey_id = $1
+        "#,
+    )
+    .bind(survey_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(row.get("count"))
+}
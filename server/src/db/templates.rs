@@ -0,0 +1,89 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgPool};
+
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct PollTemplate {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub definition: serde_json::Value,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub async fn get_user_templates(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<PollTemplate>, sqlx::Error> {
+    let templates: Vec<PollTemplate> = sqlx::query_as(
+        r#"
+        SELECT * FROM poll_templates WHERE user_id = $1 ORDER BY name
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(templates)
+}
+
+/**
+Fetches a template owned by `user_id`; other users' templates are reported as missing.
+*/
+pub async fn get_template(
+    pool: &PgPool,
+    template_id: Uuid,
+    user_id: Uuid,
+) -> Result<PollTemplate, sqlx::Error> {
+    let template: PollTemplate = sqlx::query_as(
+        r#"
+        SELECT * FROM poll_templates WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(template_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(template)
+}
+
+pub async fn create_template(
+    pool: &PgPool,
+    template_id: Uuid,
+    user_id: Uuid,
+    name: &str,
+    definition: &serde_json::Value,
+) -> Result<PollTemplate, sqlx::Error> {
+    let template: PollTemplate = sqlx::query_as(
+        r#"
+        INSERT INTO poll_templates (id, user_id, name, definition)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(template_id)
+    .bind(user_id)
+    .bind(name)
+    .bind(definition)
+    .fetch_one(pool)
+    .await?;
+    Ok(template)
+}
+
+pub async fn delete_template(
+    pool: &PgPool,
+    template_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM poll_templates WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(template_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
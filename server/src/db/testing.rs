@@ -0,0 +1,56 @@
+use sqlx::{types::Uuid, Connection, PgConnection};
+
+/**
+Connects to the database of `DATABASE_URL`. Tests using it are `#[ignore]`d and work
+inside a transaction they never commit, so they leave nothing behind; run them with
+`cargo test -- --ignored`.
+*/
+pub async fn connect() -> PgConnection {
+    let _ = dotenvy::dotenv();
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL should be set");
+    PgConnection::connect(&url).await.unwrap()
+}
+
+/// A user whose name starts with `name`
+pub async fn insert_user(conn: &mut PgConnection, name: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username) VALUES ($1, $2)")
+        .bind(id)
+        .bind(format!("{}-{}", name, id))
+        .execute(conn)
+        .await
+        .unwrap();
+    id
+}
+
+/// An open choice poll of `owner` with `options` in order; returns the poll's id and
+/// the options' ids
+pub async fn insert_poll(
+    conn: &mut PgConnection,
+    owner: Uuid,
+    options: &[&str],
+) -> (Uuid, Vec<Uuid>) {
+    let poll_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO polls (id, user_id, title) VALUES ($1, $2, 'Test poll')")
+        .bind(poll_id)
+        .bind(owner)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    let mut option_ids = vec![];
+    for (position, text) in options.iter().enumerate() {
+        let option_id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO poll_options (id, poll_id, option_text, position) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(option_id)
+        .bind(poll_id)
+        .bind(text)
+        .bind(position as i32)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        option_ids.push(option_id);
+    }
+    (poll_id, option_ids)
+}
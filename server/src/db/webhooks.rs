@@ -0,0 +1,371 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Uuid, PgExecutor, PgPool, Row};
+use utoipa::ToSchema;
+
+/**
+Poll lifecycle events a webhook can subscribe to
+*/
+#[derive(Serialize, Deserialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "text")]
+pub enum WebhookEvent {
+    #[serde(rename = "poll_created")]
+    #[sqlx(rename = "poll_created")]
+    Created,
+    /// A vote or an answer was cast
+    #[serde(rename = "poll_voted")]
+    #[sqlx(rename = "poll_voted")]
+    Voted,
+    #[serde(rename = "poll_closed")]
+    #[sqlx(rename = "poll_closed")]
+    Closed,
+    /// Every vote was removed
+    #[serde(rename = "poll_reset")]
+    #[sqlx(rename = "poll_reset")]
+    Reset,
+    /// A closed poll accepts votes again
+    #[serde(rename = "poll_reopened")]
+    #[sqlx(rename = "poll_reopened")]
+    Reopened,
+    /// A voter withdrew their vote or answer
+    #[serde(rename = "poll_vote_removed")]
+    #[sqlx(rename = "poll_vote_removed")]
+    VoteRemoved,
+}
+
+impl WebhookEvent {
+    /// Name of the event, as sent in `X-Webhook-Event`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::Created => "poll_created",
+            WebhookEvent::Voted => "poll_voted",
+            WebhookEvent::Closed => "poll_closed",
+            WebhookEvent::Reset => "poll_reset",
+            WebhookEvent::Reopened => "poll_reopened",
+            WebhookEvent::VoteRemoved => "poll_vote_removed",
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or for a retry
+    Pending,
+    Delivered,
+    /// Gave up after the last attempt
+    Failed,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub poll_id: Option<Uuid>,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+    pub active: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/**
+An entry of a webhook's delivery log
+*/
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub event: WebhookEvent,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    /// When the next attempt is due, while pending
+    pub next_attempt_at: chrono::DateTime<chrono::Utc>,
+    pub last_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// HTTP status the endpoint answered the last attempt with
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// The JSON document sent as the request body
+    pub payload: serde_json::Value,
+}
+
+/**
+A delivery claimed by the dispatcher, with what it needs to send it
+*/
+#[derive(sqlx::FromRow, Debug)]
+pub struct DueDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub event: WebhookEvent,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+}
+
+pub async fn create_webhook(
+    pool: &PgPool,
+    webhook_id: Uuid,
+    user_id: Uuid,
+    poll_id: Option<Uuid>,
+    url: &str,
+    secret: &str,
+    events: &[WebhookEvent],
+) -> Result<Webhook, sqlx::Error> {
+    let webhook: Webhook = sqlx::query_as(
+        r#"
+        INSERT INTO webhooks (id, user_id, poll_id, url, secret, events)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        "#,
+    )
+    .bind(webhook_id)
+    .bind(user_id)
+    .bind(poll_id)
+    .bind(url)
+    .bind(secret)
+    .bind(events)
+    .fetch_one(pool)
+    .await?;
+    Ok(webhook)
+}
+
+pub async fn get_user_webhooks(pool: &PgPool, user_id: Uuid) -> Result<Vec<Webhook>, sqlx::Error> {
+    let webhooks: Vec<Webhook> = sqlx::query_as(
+        r#"
+        SELECT * FROM webhooks WHERE user_id = $1 ORDER BY created_at, id
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(webhooks)
+}
+
+pub async fn get_webhook(pool: &PgPool, webhook_id: Uuid) -> Result<Option<Webhook>, sqlx::Error> {
+    let webhook: Option<Webhook> = sqlx::query_as(
+        r#"
+        SELECT * FROM webhooks WHERE id = $1
+        "#,
+    )
+    .bind(webhook_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(webhook)
+}
+
+/**
+Changes a webhook; `None` leaves a setting untouched.
+*/
+pub async fn update_webhook(
+    pool: &PgPool,
+    webhook_id: Uuid,
+    url: Option<&str>,
+    events: Option<&[WebhookEvent]>,
+    active: Option<bool>,
+) -> Result<Webhook, sqlx::Error> {
+    let webhook: Webhook = sqlx::query_as(
+        r#"
+        UPDATE webhooks
+        SET url = COALESCE($2, url), events = COALESCE($3, events), active = COALESCE($4, active)
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(webhook_id)
+    .bind(url)
+    .bind(events)
+    .bind(active)
+    .fetch_one(pool)
+    .await?;
+    Ok(webhook)
+}
+
+pub async fn delete_webhook(pool: &PgPool, webhook_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        DELETE FROM webhooks WHERE id = $1
+        "#,
+    )
+    .bind(webhook_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/**
+Whether any active webhook covers the poll and subscribes to `event`
+*/
+pub async fn has_subscribers<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    event: WebhookEvent,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM webhooks
+            JOIN polls ON polls.id = $1 AND webhooks.user_id = polls.user_id
+            WHERE webhooks.active
+                AND (webhooks.poll_id IS NULL OR webhooks.poll_id = $1)
+                AND (cardinality(webhooks.events) = 0 OR $2 = ANY(webhooks.events))
+        ) AS subscribed
+        "#,
+    )
+    .bind(poll_id)
+    .bind(event)
+    .fetch_one(executor)
+    .await?;
+    Ok(row.get("subscribed"))
+}
+
+/**
+Queues `payload` for every active webhook of the poll's owner that covers the poll and
+subscribes to `event`. Returns how many deliveries were queued.
+*/
+pub async fn enqueue_event<'e, E>(
+    executor: E,
+    poll_id: Uuid,
+    event: WebhookEvent,
+    payload: &serde_json::Value,
+) -> Result<u64, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let result = sqlx::query(
+        r#"
+        INSERT INTO webhook_deliveries (id, webhook_id, event, payload)
+        SELECT gen_random_uuid(), webhooks.id, $2, $3
+        FROM webhooks
+        JOIN polls ON polls.id = $1 AND webhooks.user_id = polls.user_id
+        WHERE webhooks.active
+            AND (webhooks.poll_id IS NULL OR webhooks.poll_id = $1)
+            AND (cardinality(webhooks.events) = 0 OR $2 = ANY(webhooks.events))
+        "#,
+    )
+    .bind(poll_id)
+    .bind(event)
+    .bind(payload)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/**
+Claims up to `limit` pending deliveries that are due, oldest first, by pushing their
+next attempt `lease_seconds` out. Deliveries claimed by another dispatcher are skipped,
+and one whose dispatcher dies is picked up again once the lease runs out.
+*/
+pub async fn claim_due_deliveries(
+    pool: &PgPool,
+    limit: i64,
+    lease_seconds: i64,
+) -> Result<Vec<DueDelivery>, sqlx::Error> {
+    let deliveries: Vec<DueDelivery> = sqlx::query_as(
+        r#"
+        WITH due AS (
+            SELECT webhook_deliveries.id FROM webhook_deliveries
+            JOIN webhooks ON webhook_deliveries.webhook_id = webhooks.id
+            WHERE webhook_deliveries.status = 'pending'
+                AND webhook_deliveries.next_attempt_at <= CURRENT_TIMESTAMP
+                AND webhooks.active
+            ORDER BY webhook_deliveries.next_attempt_at
+            LIMIT $1
+            FOR UPDATE OF webhook_deliveries SKIP LOCKED
+        ), claimed AS (
+            UPDATE webhook_deliveries
+            SET next_attempt_at = CURRENT_TIMESTAMP + make_interval(secs => $2)
+            FROM due
+            WHERE webhook_deliveries.id = due.id
+            RETURNING webhook_deliveries.*
+        )
+        SELECT claimed.id, claimed.webhook_id, webhooks.url, webhooks.secret, claimed.event,
+            claimed.payload, claimed.attempts
+        FROM claimed
+        JOIN webhooks ON claimed.webhook_id = webhooks.id
+        "#,
+    )
+    .bind(limit)
+    .bind(lease_seconds as f64)
+    .fetch_all(pool)
+    .await?;
+    Ok(deliveries)
+}
+
+/**
+Records an attempt. A failed attempt is retried at `retry_at`, or the delivery is given
+up when there is none.
+*/
+pub async fn record_attempt(
+    pool: &PgPool,
+    delivery_id: Uuid,
+    delivered: bool,
+    response_status: Option<i32>,
+    error: Option<&str>,
+    retry_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE webhook_deliveries
+        SET attempts = attempts + 1,
+            last_attempt_at = CURRENT_TIMESTAMP,
+            response_status = $3,
+            last_error = $4,
+            status = CASE WHEN $2 THEN 'delivered'
+                WHEN $5::TIMESTAMPTZ IS NULL THEN 'failed'
+                ELSE 'pending' END,
+            next_attempt_at = COALESCE($5, next_attempt_at)
+        WHERE id = $1
+        "#,
+    )
+    .bind(delivery_id)
+    .bind(delivered)
+    .bind(response_status)
+    .bind(error)
+    .bind(retry_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn count_deliveries(pool: &PgPool, webhook_id: Uuid) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS count FROM webhook_deliveries WHERE webhook_id = $1
+        "#,
+    )
+    .bind(webhook_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.get("count"))
+}
+
+/**
+One page of a webhook's deliveries, newest first
+*/
+pub async fn get_deliveries(
+    pool: &PgPool,
+    webhook_id: Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    let deliveries: Vec<Delivery> = sqlx::query_as(
+        r#"
+        SELECT id, event, status, attempts, next_attempt_at, last_attempt_at, response_status,
+            last_error, created_at, payload
+        FROM webhook_deliveries
+        WHERE webhook_id = $1
+        ORDER BY created_at DESC, id
+        LIMIT $2 OFFSET $3
+        "#,
+    )
+    .bind(webhook_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    Ok(deliveries)
+}
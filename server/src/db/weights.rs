@@ -0,0 +1,252 @@
+use serde::Serialize;
+use sqlx::{types::Uuid, PgConnection, PgExecutor, PgPool, Row};
+use utoipa::ToSchema;
+
+/**
+A named group of voters and the weight its members carry in one poll
+*/
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct PollRole {
+    pub id: Uuid,
+    pub poll_id: Uuid,
+    pub name: String,
+    pub weight: f64,
+}
+
+/**
+A user the owner gave a role or a personal weight in a poll
+*/
+#[derive(sqlx::FromRow, Serialize, Debug, ToSchema)]
+pub struct Participant {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role_id: Option<Uuid>,
+    pub role_name: Option<String>,
+    /// Personal weight, overriding the role's
+    pub weight: Option<f64>,
+    /// Weight the user's next vote would carry
+    pub effective_weight: f64,
+}
+
+/**
+The participants of a poll, and how much of their say is still unused
+*/
+#[derive(sqlx::FromRow, Debug, Clone, Copy)]
+pub struct Electorate {
+    pub voters: i64,
+    /// Participants who have not voted yet
+    pub remaining_voters: i64,
+    /// Summed weight those participants would vote with
+    pub remaining_weight: f64,
+}
+
+pub async fn get_electorate<'e, E>(executor: E, poll_id: Uuid) -> Result<Electorate, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let electorate: Electorate = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) AS voters,
+               COUNT(*) FILTER (WHERE NOT voted) AS remaining_voters,
+               COALESCE(SUM(weight) FILTER (WHERE NOT voted), 0) AS remaining_weight
+        FROM (
+            SELECT COALESCE(poll_participants.weight, poll_roles.weight, polls.default_weight)
+                       AS weight,
+                   EXISTS(
+                       SELECT 1
+                       FROM votes
+                       JOIN poll_options ON votes.poll_option_id = poll_options.id
+                       WHERE poll_options.poll_id = $1
+                           AND votes.user_id = poll_participants.user_id
+                   ) AS voted
+            FROM poll_participants
+            JOIN polls ON poll_participants.poll_id = polls.id
+            LEFT JOIN poll_roles ON poll_participants.role_id = poll_roles.id
+            WHERE poll_participants.poll_id = $1
+        ) participants
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(electorate)
+}
+
+pub async fn is_participant(
+    pool: &PgPool,
+    poll_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT EXISTS(SELECT 1 FROM poll_participants WHERE poll_id = $1 AND user_id = $2)
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.get("exists"))
+}
+
+pub async fn get_poll_roles(pool: &PgPool, poll_id: Uuid) -> Result<Vec<PollRole>, sqlx::Error> {
+    let roles: Vec<PollRole> = sqlx::query_as(
+        r#"
+        SELECT * FROM poll_roles WHERE poll_id = $1 ORDER BY name
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(roles)
+}
+
+pub async fn create_role(
+    pool: &PgPool,
+    role_id: Uuid,
+    poll_id: Uuid,
+    name: &str,
+    weight: f64,
+) -> Result<PollRole, sqlx::Error> {
+    let role: PollRole = sqlx::query_as(
+        r#"
+        INSERT INTO poll_roles (id, poll_id, name, weight)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(role_id)
+    .bind(poll_id)
+    .bind(name)
+    .bind(weight)
+    .fetch_one(pool)
+    .await?;
+    Ok(role)
+}
+
+/**
+Deletes a role of the poll. Its members fall back to their personal or the default weight.
+*/
+pub async fn delete_role(pool: &PgPool, role_id: Uuid, poll_id: Uuid) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM poll_roles WHERE id = $1 AND poll_id = $2
+        "#,
+    )
+    .bind(role_id)
+    .bind(poll_id)
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+pub async fn get_participants(
+    pool: &PgPool,
+    poll_id: Uuid,
+) -> Result<Vec<Participant>, sqlx::Error> {
+    let participants: Vec<Participant> = sqlx::query_as(
+        r#"
+        SELECT poll_participants.user_id, users.username, poll_participants.role_id,
+               poll_roles.name AS role_name, poll_participants.weight,
+               COALESCE(poll_participants.weight, poll_roles.weight, polls.default_weight)
+                   AS effective_weight
+        FROM poll_participants
+        JOIN polls ON poll_participants.poll_id = polls.id
+        JOIN users ON poll_participants.user_id = users.id
+        LEFT JOIN poll_roles ON poll_participants.role_id = poll_roles.id
+        WHERE poll_participants.poll_id = $1
+        ORDER BY users.username
+        "#,
+    )
+    .bind(poll_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(participants)
+}
+
+/**
+Assigns a user's role and personal weight in the poll, replacing any earlier assignment.
+Returns whether the user was not a participant before. A role of another poll is reported
+as [sqlx::Error::RowNotFound].
+*/
+pub async fn set_participant(
+    pool: &PgPool,
+    poll_id: Uuid,
+    user_id: Uuid,
+    role_id: Option<Uuid>,
+    weight: Option<f64>,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO poll_participants (poll_id, user_id, role_id, weight)
+        SELECT $1, $2, $3, $4
+        WHERE $3::UUID IS NULL OR EXISTS(SELECT 1 FROM poll_roles WHERE id = $3 AND poll_id = $1)
+        ON CONFLICT (poll_id, user_id)
+        DO UPDATE SET role_id = EXCLUDED.role_id, weight = EXCLUDED.weight
+        RETURNING (xmax = 0) AS inserted
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .bind(role_id)
+    .bind(weight)
+    .fetch_optional(pool)
+    .await?;
+    match row {
+        Some(row) => Ok(row.get("inserted")),
+        None => Err(sqlx::Error::RowNotFound),
+    }
+}
+
+pub async fn delete_participant(
+    pool: &PgPool,
+    poll_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM poll_participants WHERE poll_id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(poll_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/**
+Gives `to_poll` the roles and participants of `from_poll`, for polls that continue another.
+*/
+pub async fn copy_weights(
+    conn: &mut PgConnection,
+    from_poll: Uuid,
+    to_poll: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        WITH new_roles AS (
+            INSERT INTO poll_roles (id, poll_id, name, weight)
+            SELECT gen_random_uuid(), $2, name, weight FROM poll_roles WHERE poll_id = $1
+            RETURNING id, name
+        )
+        INSERT INTO poll_participants (poll_id, user_id, role_id, weight)
+        SELECT $2, poll_participants.user_id, new_roles.id, poll_participants.weight
+        FROM poll_participants
+        LEFT JOIN poll_roles ON poll_participants.role_id = poll_roles.id
+        LEFT JOIN new_roles ON new_roles.name = poll_roles.name
+        WHERE poll_participants.poll_id = $1
+        "#,
+    )
+    .bind(from_poll)
+    .bind(to_poll)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
@@ -2,13 +2,13 @@ use actix_cors::Cors;
 use actix_session::SessionMiddleware;
 use actix_web::{
     cookie::Key,
-    middleware::Logger,
-    web::{self, get, post, route, Data, JsonConfig},
-    App, HttpResponse, HttpServer, Responder,
+    middleware::{from_fn, Logger},
+    web::{self, Data, JsonConfig, PathConfig, QueryConfig},
+    App, HttpServer,
 };
-use dotenvy;
 use log::info;
-use std::env;
+use std::{env, sync::Arc};
+mod api;
 mod auth;
 pub use auth::{
     login::{finish_authentication, start_authentication},
@@ -20,7 +20,14 @@ pub use auth::{
 mod db;
 use db::{create_pool::create_db_pool, migrations::run_migrations};
 
+mod media;
+mod notifications;
 mod polls;
+use media::{
+    storage::{storage_from_env, Storage},
+    upload::MediaConfig,
+};
+use polls::validation::PollLimits;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -38,7 +45,7 @@ async fn main() -> std::io::Result<()> {
         }
     };
     loop {
-        match run_migrations(&*pool).await {
+        match run_migrations(&pool).await {
             Ok(_) => {
                 info!("Migrations completed successfully.");
                 break;
@@ -49,25 +56,44 @@ async fn main() -> std::io::Result<()> {
             }
         }
     }
+    tokio::spawn(polls::recurrence::run_scheduler(
+        pool.as_ref().clone(),
+        polls::recurrence::check_interval_from_env(),
+    ));
+    tokio::spawn(polls::webhooks::run_dispatcher(
+        pool.as_ref().clone(),
+        polls::webhooks::WebhookConfig::from_env(),
+    ));
+    tokio::spawn(notifications::digest::run_scheduler(
+        pool.as_ref().clone(),
+        notifications::digest::NotificationConfig::from_env(),
+        notifications::transport::transport_from_env(),
+    ));
     let key = Key::from(format!("{:0<100}", "qwerty").as_bytes());
     let (webauthn, webauthn_users) = startup();
+    let poll_limits = Data::new(PollLimits::from_env());
+    let media_config = Data::new(MediaConfig::from_env());
+    let join_config = Data::new(polls::join::JoinConfig::from_env());
+    let reaction_config = Data::new(polls::reactions::ReactionConfig::from_env());
+    let storage: Data<dyn Storage> = Data::from(Arc::from(storage_from_env()));
     let host = env::var("HOST").expect("HOST should be specified in the env");
     let port: u16 = env::var("PORT")
         .expect("PORT should be specified in the env")
         .parse()
         .expect("PORT must be a number");
-    let rp_origin = env::var("RP_ORIGIN").expect("RP_ORIGIN should be specified in the env");
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin() // Allow requests from any origin
-            .allowed_methods(vec!["GET", "POST", "OPTIONS"]) // Allow necessary HTTP methods
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]) // Allow necessary HTTP methods
             .allowed_headers(vec!["Content-Type", "Authorization", "X-Requested-With"]) // Allow necessary headers
             .allow_any_header() // Allow cookies to be sent with requests
+            .expose_headers(vec!["X-Request-Id"])
             .supports_credentials()
             .max_age(3600);
         App::new()
             .wrap(cors)
             .wrap(Logger::default())
+            .wrap(from_fn(api::request_id::request_id))
             .wrap(
                 SessionMiddleware::builder(MemorySession, key.clone())
                     .cookie_name("webauthnrs".to_string())
@@ -77,39 +103,339 @@ async fn main() -> std::io::Result<()> {
                     .build(),
             )
             .app_data(Data::new(pool.as_ref().clone()))
-            .app_data(JsonConfig::default())
+            .app_data(
+                JsonConfig::default().error_handler(|err, _| {
+                    auth::error::Error::InvalidRequest(err.to_string()).into()
+                }),
+            )
+            .app_data(
+                PathConfig::default().error_handler(|err, _| {
+                    auth::error::Error::InvalidRequest(err.to_string()).into()
+                }),
+            )
+            .app_data(
+                QueryConfig::default().error_handler(|err, _| {
+                    auth::error::Error::InvalidRequest(err.to_string()).into()
+                }),
+            )
             .app_data(webauthn.clone())
             .app_data(webauthn_users.clone())
+            .app_data(poll_limits.clone())
+            .app_data(media_config.clone())
+            .app_data(join_config.clone())
+            .app_data(reaction_config.clone())
+            .app_data(storage.clone())
             .service(
-                web::scope("/api/auth")
-                    .route("/me", web::post().to(auth::get_user::get_user))
-                    .route("/register", web::post().to(start_register))
-                    .route("/register_complete", web::post().to(finish_register))
-                    .route("/login", post().to(start_authentication))
-                    .route("/login_complete", post().to(finish_authentication))
-                    .route("logout", post().to(auth::get_user::logout)),
-            )
-            .service(
-                web::scope("/api/polls")
-                    .route(
-                        "/{poll_id}/close",
-                        web::post().to(polls::manage_polls::close_poll),
+                web::scope("/api/v1")
+                    .route("/openapi.json", web::get().to(api::openapi::openapi_spec))
+                    .service(
+                        web::scope("/auth")
+                            .route("/me", web::get().to(auth::get_user::get_user))
+                            .route("/register", web::post().to(start_register))
+                            .route("/register/complete", web::post().to(finish_register))
+                            .route("/login", web::post().to(start_authentication))
+                            .route("/login/complete", web::post().to(finish_authentication))
+                            .route("/logout", web::post().to(auth::get_user::logout)),
                     )
-                    .route(
-                        "/{poll_id}/vote",
-                        web::post().to(polls::manage_polls::vote_poll),
+                    .service(
+                        web::scope("/polls")
+                            .route("", web::get().to(polls::manage_polls::get_polls_brief))
+                            .route("", web::post().to(polls::manage_polls::create_poll))
+                            .route("/import", web::post().to(polls::definition::import_poll))
+                            .route("/{poll_id}", web::get().to(polls::manage_polls::get_poll))
+                            .route(
+                                "/{poll_id}",
+                                web::patch().to(polls::manage_polls::update_poll),
+                            )
+                            .route(
+                                "/{poll_id}",
+                                web::delete().to(polls::manage_polls::delete_poll),
+                            )
+                            .route(
+                                "/{poll_id}/votes",
+                                web::post().to(polls::manage_polls::vote_poll),
+                            )
+                            .route(
+                                "/{poll_id}/votes",
+                                web::delete().to(polls::manage_polls::reset_poll),
+                            )
+                            .route(
+                                "/{poll_id}/votes/me",
+                                web::delete().to(polls::manage_polls::remove_vote),
+                            )
+                            .route(
+                                "/{poll_id}/answers/me",
+                                web::delete().to(polls::questions::remove_answer),
+                            )
+                            .route(
+                                "/{poll_id}/options/order",
+                                web::put().to(polls::options::reorder_options),
+                            )
+                            .route(
+                                "/{poll_id}/options/{option_id}",
+                                web::patch().to(polls::options::update_option),
+                            )
+                            .route(
+                                "/{poll_id}/duplicate",
+                                web::post().to(polls::definition::duplicate_poll),
+                            )
+                            .route(
+                                "/{poll_id}/definition",
+                                web::get().to(polls::definition::get_poll_definition),
+                            )
+                            .route(
+                                "/{poll_id}/export",
+                                web::get().to(polls::export::export_poll),
+                            )
+                            .route(
+                                "/{poll_id}/history",
+                                web::get().to(polls::history::get_poll_history),
+                            )
+                            .route(
+                                "/{poll_id}/results",
+                                web::get().to(polls::manage_polls::get_poll_results),
+                            )
+                            .route(
+                                "/{poll_id}/roles",
+                                web::get().to(polls::weights::list_roles),
+                            )
+                            .route(
+                                "/{poll_id}/roles",
+                                web::post().to(polls::weights::create_role),
+                            )
+                            .route(
+                                "/{poll_id}/roles/{role_id}",
+                                web::delete().to(polls::weights::delete_role),
+                            )
+                            .route(
+                                "/{poll_id}/participants",
+                                web::get().to(polls::weights::list_participants),
+                            )
+                            .route(
+                                "/{poll_id}/participants/{user_id}",
+                                web::put().to(polls::weights::set_participant),
+                            )
+                            .route(
+                                "/{poll_id}/participants/{user_id}",
+                                web::delete().to(polls::weights::delete_participant),
+                            )
+                            .route(
+                                "/{poll_id}/answers",
+                                web::post().to(polls::questions::submit_answer),
+                            )
+                            .route(
+                                "/{poll_id}/answers",
+                                web::get().to(polls::questions::list_answers),
+                            )
+                            .route(
+                                "/{poll_id}/join-code",
+                                web::post().to(polls::join::poll_join_code),
+                            )
+                            .route("/{poll_id}/quiz", web::put().to(polls::quiz::set_quiz))
+                            .route(
+                                "/{poll_id}/quiz",
+                                web::delete().to(polls::quiz::remove_quiz),
+                            )
+                            .route(
+                                "/{poll_id}/quiz/reveal",
+                                web::put().to(polls::quiz::reveal_answers),
+                            )
+                            .route(
+                                "/{poll_id}/quiz/scores",
+                                web::get().to(polls::quiz::get_quiz_scores),
+                            )
+                            .route(
+                                "/{poll_id}/comments",
+                                web::get().to(polls::comments::list_comments),
+                            )
+                            .route(
+                                "/{poll_id}/comments",
+                                web::post().to(polls::comments::create_comment),
+                            )
+                            .route(
+                                "/{poll_id}/comments/{comment_id}",
+                                web::patch().to(polls::comments::edit_comment),
+                            )
+                            .route(
+                                "/{poll_id}/comments/{comment_id}",
+                                web::delete().to(polls::comments::delete_comment),
+                            )
+                            .route(
+                                "/{poll_id}/comments/{comment_id}/hidden",
+                                web::put().to(polls::comments::hide_comment),
+                            )
+                            .route(
+                                "/{poll_id}/comments/{comment_id}/pinned",
+                                web::put().to(polls::comments::pin_comment),
+                            )
+                            .route(
+                                "/{poll_id}/reactions",
+                                web::post().to(polls::reactions::react),
+                            )
+                            .route(
+                                "/{poll_id}/reactions",
+                                web::get().to(polls::reactions::get_reactions),
+                            ),
                     )
-                    .route(
-                        "/{poll_id}/reset",
-                        web::post().to(polls::manage_polls::reset_poll),
+                    .service(
+                        web::scope("/surveys")
+                            .route("", web::get().to(polls::surveys::list_surveys))
+                            .route("", web::post().to(polls::surveys::create_survey))
+                            .route("/{survey_id}", web::get().to(polls::surveys::get_survey))
+                            .route(
+                                "/{survey_id}",
+                                web::patch().to(polls::surveys::update_survey),
+                            )
+                            .route(
+                                "/{survey_id}",
+                                web::delete().to(polls::surveys::delete_survey),
+                            )
+                            .route(
+                                "/{survey_id}/responses",
+                                web::post().to(polls::surveys::submit_response),
+                            ),
                     )
-                    .route(
-                        "/{poll_id}/results",
-                        web::get().to(polls::manage_polls::get_poll_results),
+                    .service(
+                        web::scope("/live-sessions")
+                            .route("", web::get().to(polls::live::list_live_sessions))
+                            .route("", web::post().to(polls::live::create_live_session))
+                            .route(
+                                "/{session_id}",
+                                web::get().to(polls::live::get_live_session),
+                            )
+                            .route(
+                                "/{session_id}",
+                                web::delete().to(polls::live::delete_live_session),
+                            )
+                            .route(
+                                "/{session_id}/polls",
+                                web::put().to(polls::live::set_live_polls),
+                            )
+                            .route("/{session_id}/next", web::post().to(polls::live::next_poll))
+                            .route(
+                                "/{session_id}/current",
+                                web::put().to(polls::live::set_current_poll),
+                            )
+                            .route(
+                                "/{session_id}/open",
+                                web::post().to(polls::live::open_current_poll),
+                            )
+                            .route(
+                                "/{session_id}/close",
+                                web::post().to(polls::live::close_current_poll),
+                            )
+                            .route(
+                                "/{session_id}/reveal",
+                                web::put().to(polls::live::reveal_results),
+                            )
+                            .route(
+                                "/{session_id}/stream",
+                                web::get().to(polls::live::stream_live_session),
+                            )
+                            .route(
+                                "/{session_id}/join-code",
+                                web::post().to(polls::join::live_session_join_code),
+                            )
+                            .route(
+                                "/{session_id}/leaderboard",
+                                web::get().to(polls::quiz::get_leaderboard),
+                            )
+                            .route(
+                                "/{session_id}/leaderboard/stream",
+                                web::get().to(polls::quiz::stream_leaderboard),
+                            )
+                            .route(
+                                "/{session_id}/questions",
+                                web::get().to(polls::audience_questions::list_questions),
+                            )
+                            .route(
+                                "/{session_id}/questions",
+                                web::post().to(polls::audience_questions::ask_question),
+                            )
+                            .route(
+                                "/{session_id}/questions/stream",
+                                web::get().to(polls::audience_questions::stream_questions),
+                            )
+                            .route(
+                                "/{session_id}/questions/{question_id}",
+                                web::delete().to(polls::audience_questions::delete_question),
+                            )
+                            .route(
+                                "/{session_id}/questions/{question_id}/votes",
+                                web::post().to(polls::audience_questions::upvote_question),
+                            )
+                            .route(
+                                "/{session_id}/questions/{question_id}/votes/me",
+                                web::delete().to(polls::audience_questions::remove_question_upvote),
+                            )
+                            .route(
+                                "/{session_id}/questions/{question_id}/answered",
+                                web::put().to(polls::audience_questions::mark_answered),
+                            )
+                            .route(
+                                "/{session_id}/questions/{question_id}/hidden",
+                                web::put().to(polls::audience_questions::hide_question),
+                            ),
+                    )
+                    .service(
+                        web::scope("/join")
+                            .route("/{code}", web::get().to(polls::join::join))
+                            .route("/{code}/qr", web::get().to(polls::join::join_qr)),
+                    )
+                    .service(
+                        web::scope("/webhooks")
+                            .route("", web::get().to(polls::webhooks::list_webhooks))
+                            .route("", web::post().to(polls::webhooks::create_webhook))
+                            .route(
+                                "/{webhook_id}",
+                                web::patch().to(polls::webhooks::update_webhook),
+                            )
+                            .route(
+                                "/{webhook_id}",
+                                web::delete().to(polls::webhooks::delete_webhook),
+                            )
+                            .route(
+                                "/{webhook_id}/deliveries",
+                                web::get().to(polls::webhooks::list_deliveries),
+                            ),
                     )
-                    .route("/{poll_id}", web::get().to(polls::manage_polls::get_poll))
-                    .route("/create", web::post().to(polls::manage_polls::create_poll))
-                    .route("/", web::get().to(polls::manage_polls::get_polls_brief)),
+                    .service(
+                        web::scope("/notifications")
+                            .route(
+                                "/preferences",
+                                web::get().to(notifications::preferences::get_preferences),
+                            )
+                            .route(
+                                "/preferences",
+                                web::put().to(notifications::preferences::set_preferences),
+                            ),
+                    )
+                    .service(
+                        web::scope("/templates")
+                            .route("", web::get().to(polls::templates::list_templates))
+                            .route("", web::post().to(polls::templates::create_template))
+                            .route(
+                                "/{template_id}",
+                                web::delete().to(polls::templates::delete_template),
+                            )
+                            .route(
+                                "/{template_id}/polls",
+                                web::post().to(polls::templates::create_poll_from_template),
+                            ),
+                    )
+                    .service(
+                        web::scope("/assets")
+                            .route("", web::post().to(media::upload::upload_asset))
+                            .route("/{asset_id}", web::get().to(media::upload::get_asset))
+                            .route(
+                                "/{asset_id}/thumbnail",
+                                web::get().to(media::upload::get_asset_thumbnail),
+                            ),
+                    )
+                    .route(
+                        "/users/{user_id}/polls",
+                        web::get().to(polls::manage_polls::get_user_polls),
+                    ),
             )
     })
     .bind((host.as_str(), port))?
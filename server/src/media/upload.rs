@@ -0,0 +1,324 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, FieldError, WebResult},
+        validate_session::validate_session,
+    },
+    db::assets::{self, Asset, NewAsset},
+    media::storage::{Storage, StorageError},
+};
+use actix_multipart::Multipart;
+use actix_session::Session;
+use actix_web::{
+    http::header::{self, ETag, EntityTag},
+    web::{self, Data, Path},
+    HttpRequest, HttpResponse,
+};
+use futures_util::StreamExt;
+use image::{ImageFormat, ImageReader, Limits};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{env, io::Cursor};
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+const ALLOWED_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+];
+
+/**
+Upload limits, read from the environment at startup.
+*/
+#[derive(Debug, Clone)]
+pub struct MediaConfig {
+    pub max_upload_bytes: usize,
+    pub max_dimension: u32,
+    pub thumbnail_size: u32,
+}
+
+impl MediaConfig {
+    pub fn from_env() -> Self {
+        let number = |key: &str, default: u32| match env::var(key) {
+            Ok(value) => value
+                .parse()
+                .unwrap_or_else(|_| panic!("{} must be a number", key)),
+            Err(_) => default,
+        };
+        MediaConfig {
+            max_upload_bytes: number("UPLOAD_MAX_BYTES", 5 * 1024 * 1024) as usize,
+            max_dimension: number("UPLOAD_MAX_DIMENSION", 8192),
+            thumbnail_size: number("THUMBNAIL_SIZE", 320),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AssetResponse {
+    pub id: Uuid,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub width: i32,
+    pub height: i32,
+    pub url: String,
+    pub thumbnail_url: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<Asset> for AssetResponse {
+    fn from(asset: Asset) -> Self {
+        AssetResponse {
+            url: format!("/api/v1/assets/{}", asset.id),
+            thumbnail_url: format!("/api/v1/assets/{}/thumbnail", asset.id),
+            id: asset.id,
+            content_type: asset.content_type,
+            size_bytes: asset.size_bytes,
+            width: asset.width,
+            height: asset.height,
+            created_at: asset.created_at,
+        }
+    }
+}
+
+/// Only describes the multipart form for the OpenAPI document
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct UploadForm {
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+struct ProcessedImage {
+    width: u32,
+    height: u32,
+    thumbnail: Vec<u8>,
+}
+
+/**
+Decodes the image (bounded by `max_dimension`) and renders a PNG thumbnail.
+CPU bound, so it is run on the blocking pool.
+*/
+fn process_image(
+    bytes: &[u8],
+    format: ImageFormat,
+    config: &MediaConfig,
+) -> Result<ProcessedImage, image::ImageError> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(config.max_dimension);
+    limits.max_image_height = Some(config.max_dimension);
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let image = reader.decode()?;
+
+    let mut thumbnail = Vec::new();
+    image
+        .thumbnail(config.thumbnail_size, config.thumbnail_size)
+        .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Png)?;
+    Ok(ProcessedImage {
+        width: image.width(),
+        height: image.height(),
+        thumbnail,
+    })
+}
+
+/**
+Reads the `file` field of the form, failing as soon as it grows past `max_bytes`.
+*/
+async fn read_file_field(payload: &mut Multipart, max_bytes: usize) -> WebResult<Vec<u8>> {
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|e| Error::InvalidRequest(e.to_string()))?;
+        if field.name() != Some("file") {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| Error::InvalidRequest(e.to_string()))?;
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(Error::PayloadTooLarge(max_bytes));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        return Ok(bytes);
+    }
+    Err(Error::Validation(vec![FieldError::new(
+        "file",
+        "is required",
+    )]))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/assets",
+    tag = "assets",
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "Image stored", body = AssetResponse),
+        (status = 200, description = "Identical image was already stored", body = AssetResponse),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 413, description = "File exceeds the upload limit", body = ErrorBody),
+        (status = 415, description = "Not a PNG, JPEG, GIF or WebP image", body = ErrorBody),
+    )
+)]
+pub async fn upload_asset(
+    session: Session,
+    pool: Data<PgPool>,
+    storage: Data<dyn Storage>,
+    config: Data<MediaConfig>,
+    mut payload: Multipart,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let bytes = read_file_field(&mut payload, config.max_upload_bytes).await?;
+
+    // Trust the bytes, not the client supplied content type
+    let format = image::guess_format(&bytes).map_err(|_| Error::UnsupportedMediaType)?;
+    if !ALLOWED_FORMATS.contains(&format) {
+        return Err(Error::UnsupportedMediaType);
+    }
+    let content_type = format.to_mime_type();
+    let content_hash = format!("{:x}", Sha256::digest(&bytes));
+
+    if let Some(existing) = assets::get_asset_by_hash(&pool, &content_hash)
+        .await
+        .map_err(Error::Database)?
+    {
+        return Ok(HttpResponse::Ok().json(AssetResponse::from(existing)));
+    }
+
+    let (bytes, processed) = {
+        let config = config.clone();
+        web::block(move || {
+            let processed = process_image(&bytes, format, &config);
+            (bytes, processed)
+        })
+        .await
+        .map_err(|e| Error::InvalidRequest(e.to_string()))?
+    };
+    let processed = processed.map_err(|_| Error::UnsupportedMediaType)?;
+
+    let storage_key = content_hash.clone();
+    let thumbnail_key = format!("{}-thumb.png", content_hash);
+    storage.put(&storage_key, &bytes, content_type).await?;
+    storage
+        .put(&thumbnail_key, &processed.thumbnail, "image/png")
+        .await?;
+
+    let asset = assets::insert_asset(
+        &pool,
+        &NewAsset {
+            id: Uuid::new_v4(),
+            user_id,
+            content_hash: &content_hash,
+            content_type,
+            size_bytes: bytes.len() as i64,
+            width: processed.width as i32,
+            height: processed.height as i32,
+            storage_key: &storage_key,
+            thumbnail_key: &thumbnail_key,
+        },
+    )
+    .await
+    .map_err(Error::Database)?;
+    Ok(HttpResponse::Created().json(AssetResponse::from(asset)))
+}
+
+/**
+Responds with a stored object. Asset content never changes for a given id, so responses
+are cacheable forever and revalidated through the content hash as ETag.
+*/
+async fn serve(
+    req: &HttpRequest,
+    storage: &dyn Storage,
+    key: &str,
+    content_type: &str,
+    etag: String,
+) -> WebResult<HttpResponse> {
+    let etag = EntityTag::new_strong(etag);
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').any(|tag| tag.trim() == etag.to_string()))
+        .unwrap_or(false);
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .finish());
+    }
+
+    let bytes = storage.get(key).await.map_err(|e| match e {
+        StorageError::NotFound => Error::AssetNotFound,
+        _ => Error::Storage(e),
+    })?;
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ETag(etag))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=31536000, immutable"))
+        .body(bytes))
+}
+
+async fn find_asset(pool: &PgPool, asset_id: Uuid) -> WebResult<Asset> {
+    assets::get_asset(pool, asset_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::AssetNotFound,
+            _ => Error::Database(e),
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/assets/{asset_id}",
+    tag = "assets",
+    params(("asset_id" = Uuid, Path, description = "Asset id")),
+    responses(
+        (status = 200, description = "The original image", content_type = "image/*", body = Vec<u8>),
+        (status = 304, description = "Cached copy is current"),
+        (status = 404, description = "Asset not found", body = ErrorBody),
+    )
+)]
+pub async fn get_asset(
+    asset_id: Path<Uuid>,
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    storage: Data<dyn Storage>,
+) -> WebResult<HttpResponse> {
+    let asset = find_asset(&pool, asset_id.into_inner()).await?;
+    serve(
+        &req,
+        storage.get_ref(),
+        &asset.storage_key,
+        &asset.content_type,
+        asset.content_hash,
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/assets/{asset_id}/thumbnail",
+    tag = "assets",
+    params(("asset_id" = Uuid, Path, description = "Asset id")),
+    responses(
+        (status = 200, description = "PNG thumbnail", content_type = "image/png", body = Vec<u8>),
+        (status = 304, description = "Cached copy is current"),
+        (status = 404, description = "Asset not found", body = ErrorBody),
+    )
+)]
+pub async fn get_asset_thumbnail(
+    asset_id: Path<Uuid>,
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    storage: Data<dyn Storage>,
+) -> WebResult<HttpResponse> {
+    let asset = find_asset(&pool, asset_id.into_inner()).await?;
+    serve(
+        &req,
+        storage.get_ref(),
+        &asset.thumbnail_key,
+        "image/png",
+        format!("{}-thumb", asset.content_hash),
+    )
+    .await
+}
@@ -0,0 +1,190 @@
+use crate::{
+    db::notifications::{self, DueDigest, NotificationKind, PendingNotification},
+    notifications::{
+        templates,
+        transport::{Message, Transport},
+    },
+};
+use log::{error, info, warn};
+use sqlx::PgPool;
+use std::{env, time::Duration};
+use webauthn_rs::prelude::Uuid;
+
+/// Users whose digest is sent per claim
+const DIGEST_BATCH: i64 = 50;
+/// How long after closing a poll still gets results notifications, so a scheduler that
+/// was down catches up without mailing about old polls
+const RESULTS_LOOKBACK_SECONDS: i64 = 24 * 60 * 60;
+
+/**
+When the notification scheduler runs, how early it reminds about closing polls and where
+links point. Loaded once at startup from the environment.
+*/
+#[derive(Debug, Clone)]
+pub struct NotificationConfig {
+    /// How often notifications are queued and due digests sent
+    pub check_interval: Duration,
+    /// How long before a poll closes its participants are reminded
+    pub closing_reminder_seconds: i64,
+    /// The web client, e.g. `https://livepool.app`; polls are linked below `/polls`
+    pub base_url: String,
+    /// Attempts before a notification is given up
+    pub max_attempts: i32,
+    /// Wait before the first retry of a failed send; it doubles with every further attempt
+    pub retry_base_seconds: i64,
+    /// Longest wait between two attempts
+    pub retry_max_seconds: i64,
+}
+
+fn number_env(name: &str, default: u64) -> u64 {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a number", name)),
+        Err(_) => default,
+    }
+}
+
+impl NotificationConfig {
+    pub fn from_env() -> Self {
+        NotificationConfig {
+            check_interval: Duration::from_secs(number_env("NOTIFY_CHECK_SECS", 60)),
+            closing_reminder_seconds: number_env("NOTIFY_CLOSING_REMINDER_MINUTES", 60) as i64 * 60,
+            base_url: env::var("NOTIFY_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            max_attempts: number_env("NOTIFY_MAX_ATTEMPTS", 5) as i32,
+            retry_base_seconds: number_env("NOTIFY_RETRY_BASE_SECS", 300) as i64,
+            retry_max_seconds: number_env("NOTIFY_RETRY_MAX_SECS", 6 * 60 * 60) as i64,
+        }
+    }
+}
+
+/**
+Queues an invite to the poll for a user who just became its participant. Failures are
+logged rather than failing the request that made the change.
+*/
+pub async fn notify_invite(pool: &PgPool, poll_id: Uuid, user_id: Uuid) {
+    if let Err(e) = notifications::queue_invite(pool, poll_id, user_id).await {
+        error!("queueing invite to poll {} failed: {:?}", poll_id, e);
+    }
+}
+
+/**
+Whether a pending notification is still worth sending. Reminders are dropped once the
+poll closed or the user voted.
+*/
+fn still_relevant(notification: &PendingNotification) -> bool {
+    notification.kind != NotificationKind::ClosingReminder
+        || (notification.is_active && !notification.voted)
+}
+
+/**
+Sends one user's pending notifications as a single message and returns whether it went
+out. When sending fails the claim is given back and the notifications are retried with
+backoff, or given up after too many attempts or an error that retrying cannot fix.
+*/
+async fn send_digest(
+    pool: &PgPool,
+    config: &NotificationConfig,
+    transport: &dyn Transport,
+    due: DueDigest,
+) -> Result<bool, sqlx::Error> {
+    let pending = notifications::get_pending(pool, due.user_id).await?;
+    let relevant: Vec<&PendingNotification> =
+        pending.iter().filter(|n| still_relevant(n)).collect();
+    let ids: Vec<Uuid> = pending.iter().map(|n| n.id).collect();
+    if relevant.is_empty() {
+        notifications::mark_sent(pool, &ids).await?;
+        notifications::release_digest(pool, due.user_id, due.previous_digest_at).await?;
+        return Ok(false);
+    }
+
+    let (subject, body) = templates::digest(&due.username, &relevant, &config.base_url);
+    let message = Message {
+        to_name: due.username,
+        to_email: due.email,
+        subject,
+        body,
+    };
+    match transport.send(&message).await {
+        Ok(()) => {
+            info!(
+                "sent {} notification(s) to user {}",
+                relevant.len(),
+                due.user_id
+            );
+            notifications::mark_sent(pool, &ids).await?;
+            Ok(true)
+        }
+        Err(e) => {
+            error!(
+                "sending notifications to user {} failed: {}",
+                due.user_id, e
+            );
+            let given_up = notifications::record_failure(
+                pool,
+                &ids,
+                &e.to_string(),
+                e.is_permanent(),
+                config.max_attempts,
+                config.retry_base_seconds as f64,
+                config.retry_max_seconds as f64,
+            )
+            .await?;
+            if given_up > 0 {
+                warn!(
+                    "gave up {} notification(s) to user {}",
+                    given_up, due.user_id
+                );
+            }
+            notifications::release_digest(pool, due.user_id, due.previous_digest_at).await?;
+            Ok(false)
+        }
+    }
+}
+
+async fn run_once(
+    pool: &PgPool,
+    config: &NotificationConfig,
+    transport: &dyn Transport,
+) -> Result<(), sqlx::Error> {
+    notifications::queue_closing_reminders(pool, config.closing_reminder_seconds).await?;
+    notifications::queue_results_ready(pool, RESULTS_LOOKBACK_SECONDS).await?;
+    loop {
+        let due = notifications::claim_due_digests(pool, DIGEST_BATCH).await?;
+        let claimed = due.len() as i64;
+        let mut all_sent = true;
+        for digest in due {
+            let user_id = digest.user_id;
+            match send_digest(pool, config, transport, digest).await {
+                Ok(sent) => all_sent &= sent,
+                Err(e) => {
+                    error!("notification digest of user {} failed: {:?}", user_id, e);
+                    all_sent = false;
+                }
+            }
+        }
+        // Digests given back would be claimed again right away; leave them to the next run
+        if claimed < DIGEST_BATCH || !all_sent {
+            return Ok(());
+        }
+    }
+}
+
+/**
+Background task that queues closing reminders and results notifications, then sends
+every user whose digest is due their pending notifications through `transport`.
+*/
+pub async fn run_scheduler(
+    pool: PgPool,
+    config: NotificationConfig,
+    transport: Box<dyn Transport>,
+) {
+    let mut interval = tokio::time::interval(config.check_interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = run_once(&pool, &config, transport.as_ref()).await {
+            error!("notification run failed: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod digest;
+pub mod preferences;
+pub mod templates;
+pub mod transport;
@@ -0,0 +1,98 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, WebResult},
+        validate_session::validate_session,
+    },
+    db::notifications::{self, DigestFrequency, NotificationPreferences},
+    polls::validation::Validator,
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json},
+    HttpResponse,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+/// Longest address SMTP allows
+const MAX_EMAIL_LEN: usize = 254;
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PreferencesRequest {
+    /// Where to send notifications; `null` stops them all
+    email: Option<String>,
+    /// Being made a participant of a poll
+    #[serde(default = "enabled")]
+    invites: bool,
+    /// A poll closing soon that the user has not voted on
+    #[serde(default = "enabled")]
+    closing_reminders: bool,
+    /// A poll the user took part in closing
+    #[serde(default = "enabled")]
+    results_ready: bool,
+    #[serde(default)]
+    digest: DigestFrequency,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications/preferences",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "The caller's notification preferences", body = NotificationPreferences),
+        (status = 401, description = "No active session", body = ErrorBody),
+    )
+)]
+pub async fn get_preferences(session: Session, pool: Data<PgPool>) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let preferences = notifications::get_preferences(&pool, user_id)
+        .await
+        .map_err(Error::Database)?
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/notifications/preferences",
+    tag = "notifications",
+    request_body = PreferencesRequest,
+    responses(
+        (status = 200, description = "Preferences saved; pending notifications of kinds turned off are dropped", body = NotificationPreferences),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 422, description = "The email address is not valid", body = ErrorBody),
+    )
+)]
+pub async fn set_preferences(
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<PreferencesRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let email = req.email.as_deref().map(str::trim);
+    let mut v = Validator::new();
+    if let Some(email) = email {
+        if email.len() > MAX_EMAIL_LEN || email.parse::<lettre::Address>().is_err() {
+            v.add("email", "must be a valid email address");
+        }
+    }
+    v.finish()?;
+
+    let preferences = NotificationPreferences {
+        email: email.map(str::to_string),
+        invites: req.invites,
+        closing_reminders: req.closing_reminders,
+        results_ready: req.results_ready,
+        digest: req.digest,
+        last_digest_at: None,
+    };
+    let saved = notifications::set_preferences(&pool, user_id, &preferences)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(saved))
+}
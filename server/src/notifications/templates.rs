@@ -0,0 +1,98 @@
+use crate::db::notifications::{NotificationKind, PendingNotification};
+use std::fmt::Write;
+
+/// Order the sections of a digest come in
+const SECTIONS: [NotificationKind; 3] = [
+    NotificationKind::ClosingReminder,
+    NotificationKind::Invite,
+    NotificationKind::ResultsReady,
+];
+
+fn heading(kind: NotificationKind) -> &'static str {
+    match kind {
+        NotificationKind::Invite => "You were invited to vote",
+        NotificationKind::ClosingReminder => "Closing soon",
+        NotificationKind::ResultsReady => "Results are ready",
+    }
+}
+
+/**
+The subject of a message telling about `notification` alone
+*/
+fn subject(notification: &PendingNotification) -> String {
+    match notification.kind {
+        NotificationKind::Invite => format!(
+            "{} invited you to vote on \"{}\"",
+            notification.owner, notification.title
+        ),
+        NotificationKind::ClosingReminder => format!("\"{}\" closes soon", notification.title),
+        NotificationKind::ResultsReady => {
+            format!("Results of \"{}\" are ready", notification.title)
+        }
+    }
+}
+
+/**
+One entry of a digest: what happened and a link to the poll
+*/
+fn line(notification: &PendingNotification, poll_url: &str) -> String {
+    let what = match notification.kind {
+        NotificationKind::Invite => format!(
+            "\"{}\" by {} is waiting for your vote.",
+            notification.title, notification.owner
+        ),
+        NotificationKind::ClosingReminder => match notification.next_run_at {
+            Some(at) => format!(
+                "\"{}\" closes at {} and you have not voted yet.",
+                notification.title,
+                at.format("%Y-%m-%d %H:%M UTC")
+            ),
+            None => format!(
+                "\"{}\" closes soon and you have not voted yet.",
+                notification.title
+            ),
+        },
+        NotificationKind::ResultsReady => format!(
+            "\"{}\" by {} has closed. See how it turned out.",
+            notification.title, notification.owner
+        ),
+    };
+    format!("- {}\n  {}\n", what, poll_url)
+}
+
+/**
+Renders the digest of `notifications` for `username` as a subject and a plain text
+body. A digest of a single notification gets that notification's own subject.
+*/
+pub fn digest(
+    username: &str,
+    notifications: &[&PendingNotification],
+    base_url: &str,
+) -> (String, String) {
+    let subject = match notifications {
+        [notification] => subject(notification),
+        _ => format!("{} updates on your polls", notifications.len()),
+    };
+
+    let mut body = format!("Hi {},\n", username);
+    for kind in SECTIONS {
+        let mut section = notifications.iter().filter(|n| n.kind == kind).peekable();
+        if section.peek().is_none() {
+            continue;
+        }
+        let _ = write!(body, "\n{}\n\n", heading(kind));
+        for notification in section {
+            let url = format!(
+                "{}/polls/{}",
+                base_url.trim_end_matches('/'),
+                notification.poll_id
+            );
+            body.push_str(&line(notification, &url));
+        }
+    }
+    body.push_str(
+        "\nYou get these messages because of your notification preferences, which you can \
+         change at any time.\n",
+    );
+    (subject, body)
+}
@@ -0,0 +1,253 @@
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use log::info;
+use std::{env, time::Duration};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error("Invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Invalid message: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+impl TransportError {
+    /**
+    Whether sending the message again cannot succeed: the address or message is invalid,
+    or the mail server rejected it for good.
+    */
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            TransportError::Address(_) | TransportError::Message(_) => true,
+            TransportError::Smtp(e) => e.is_permanent(),
+        }
+    }
+}
+
+/**
+A rendered notification, addressed to one user
+*/
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub to_name: String,
+    pub to_email: String,
+    pub subject: String,
+    /// Plain text body
+    pub body: String,
+}
+
+/**
+Sends rendered notifications. A failed send is retried by the scheduler with backoff,
+unless the error is permanent.
+*/
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, message: &Message) -> Result<(), TransportError>;
+}
+
+/**
+Only writes messages to the log, for development and for deployments without mail.
+*/
+pub struct LogTransport;
+
+#[async_trait]
+impl Transport for LogTransport {
+    async fn send(&self, message: &Message) -> Result<(), TransportError> {
+        info!(
+            "notification to {} <{}>: {}\n{}",
+            message.to_name, message.to_email, message.subject, message.body
+        );
+        Ok(())
+    }
+}
+
+/**
+How the SMTP connection is secured
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    /// Plain text, e.g. for a local mail sink
+    None,
+    /// Upgraded with STARTTLS, which the server must offer
+    StartTls,
+    /// TLS from the first byte
+    Tls,
+}
+
+impl SmtpSecurity {
+    fn default_port(&self) -> u16 {
+        match self {
+            SmtpSecurity::None => 25,
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+        }
+    }
+}
+
+/**
+Sends messages through an SMTP relay.
+*/
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        security: SmtpSecurity,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, TransportError> {
+        let builder = match security {
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+        let mut builder = builder.port(port).timeout(Some(Duration::from_secs(30)));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(SmtpTransport {
+            mailer: builder.build(),
+            from: from.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for SmtpTransport {
+    async fn send(&self, message: &Message) -> Result<(), TransportError> {
+        let email = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(Mailbox::new(
+                Some(message.to_name.clone()),
+                message.to_email.parse()?,
+            ))
+            .subject(&message.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body.clone())?;
+        self.mailer.send(email).await?;
+        Ok(())
+    }
+}
+
+/**
+Builds the transport selected by `NOTIFY_TRANSPORT` (`log` by default, or `smtp`).
+*/
+pub fn transport_from_env() -> Box<dyn Transport> {
+    let transport = env::var("NOTIFY_TRANSPORT").unwrap_or_else(|_| "log".to_string());
+    match transport.as_str() {
+        "log" => Box::new(LogTransport),
+        "smtp" => {
+            let security = match env::var("SMTP_SECURITY").as_deref() {
+                Ok("none") => SmtpSecurity::None,
+                Ok("starttls") | Err(_) => SmtpSecurity::StartTls,
+                Ok("tls") => SmtpSecurity::Tls,
+                Ok(other) => panic!("Unknown SMTP_SECURITY {}", other),
+            };
+            let port = match env::var("SMTP_PORT") {
+                Ok(port) => port.parse().expect("SMTP_PORT must be a number"),
+                Err(_) => security.default_port(),
+            };
+            let credentials = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+                (Ok(username), Ok(password)) => Some((username, password)),
+                _ => None,
+            };
+            Box::new(
+                SmtpTransport::new(
+                    &env::var("SMTP_HOST").expect("SMTP_HOST should be specified in the env"),
+                    port,
+                    security,
+                    credentials,
+                    &env::var("NOTIFY_FROM")
+                        .unwrap_or_else(|_| "LivePool <notifications@localhost>".to_string()),
+                )
+                .expect("Invalid SMTP configuration"),
+            )
+        }
+        other => panic!("Unknown NOTIFY_TRANSPORT {}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(subject: &str) -> Message {
+        Message {
+            to_name: "Tester".to_string(),
+            to_email: "tester@example.com".to_string(),
+            subject: subject.to_string(),
+            body: "Hi Tester,\n".to_string(),
+        }
+    }
+
+    #[test]
+    fn invalid_addresses_are_permanent() {
+        let error = TransportError::from("not an address".parse::<lettre::Address>().unwrap_err());
+        assert!(error.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn unreachable_servers_are_retried() {
+        let transport = SmtpTransport::new(
+            "127.0.0.1",
+            1,
+            SmtpSecurity::None,
+            None,
+            "LivePool <notifications@localhost>",
+        )
+        .unwrap();
+        let error = transport.send(&message("Unreachable")).await.unwrap_err();
+        assert!(!error.is_permanent(), "{}", error);
+    }
+
+    /// Sends through a real SMTP server and finds the message in its mailpit inbox. Start
+    /// one with `docker compose --profile mail up mailpit`, then run
+    /// `cargo test -- --ignored smtp_delivers`. `SMTP_TEST_HOST`, `SMTP_TEST_PORT` and
+    /// `MAILPIT_URL` point elsewhere.
+    #[tokio::test]
+    #[ignore = "needs the mailpit service of the `mail` compose profile"]
+    async fn smtp_delivers_to_the_sink() {
+        let host = env::var("SMTP_TEST_HOST").unwrap_or_else(|_| "localhost".to_string());
+        let port = env::var("SMTP_TEST_PORT")
+            .map(|port| port.parse().expect("SMTP_TEST_PORT must be a number"))
+            .unwrap_or(1025);
+        let inbox = env::var("MAILPIT_URL").unwrap_or_else(|_| "http://localhost:8025".to_string());
+        let transport = SmtpTransport::new(
+            &host,
+            port,
+            SmtpSecurity::None,
+            None,
+            "LivePool <notifications@localhost>",
+        )
+        .unwrap();
+        let subject = format!("Digest {}", webauthn_rs::prelude::Uuid::new_v4());
+        transport.send(&message(&subject)).await.unwrap();
+
+        let messages = reqwest::get(format!("{}/api/v1/messages", inbox))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let messages: serde_json::Value = serde_json::from_str(&messages).unwrap();
+        let sent = messages["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|m| m["Subject"] == subject.as_str())
+            .expect("the message should be in the inbox");
+        assert_eq!(sent["To"][0]["Address"], "tester@example.com");
+        assert_eq!(sent["From"]["Address"], "notifications@localhost");
+    }
+}
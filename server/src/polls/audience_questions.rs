@@ -0,0 +1,411 @@
+use crate::{
+    api::request_id,
+    auth::{
+        error::{Error, ErrorBody, WebResult},
+        validate_session::{session_user, validate_session},
+    },
+    db::{
+        audience_questions::{self, AudienceQuestion},
+        live::{self, LiveSession},
+    },
+    polls::{
+        manage_polls::sse_error,
+        validation::{PollLimits, Validator},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{self, Data, Json, Path},
+    HttpResponse, Responder,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::time::Duration;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+/**
+An audience question as its reader sees it
+*/
+#[derive(Serialize, ToSchema)]
+pub struct AudienceQuestionData {
+    id: Uuid,
+    session_id: Uuid,
+    user_id: Uuid,
+    username: String,
+    body: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// Set once the presenter marks the question answered
+    answered_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Hidden by the presenter; only they and the author still see it
+    hidden: bool,
+    votes: i64,
+    /// Whether the reader upvoted it
+    voted: bool,
+}
+
+impl From<AudienceQuestion> for AudienceQuestionData {
+    fn from(question: AudienceQuestion) -> Self {
+        AudienceQuestionData {
+            id: question.id,
+            session_id: question.session_id,
+            user_id: question.user_id,
+            username: question.username,
+            body: question.body,
+            created_at: question.created_at,
+            answered_at: question.answered_at,
+            hidden: question.hidden,
+            votes: question.votes,
+            voted: question.voted,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AskQuestionRequest {
+    body: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AnsweredRequest {
+    answered: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct HideQuestionRequest {
+    hidden: bool,
+}
+
+async fn find_live_session(pool: &PgPool, session_id: Uuid) -> WebResult<LiveSession> {
+    live::get_live_session(pool, session_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::LiveSessionNotFound,
+            _ => Error::Database(e),
+        })
+}
+
+/**
+Loads a question of `live` as `viewer` sees it; the presenter moderates the questions.
+*/
+async fn find_question(
+    pool: &PgPool,
+    live: &LiveSession,
+    question_id: Uuid,
+    viewer: Option<Uuid>,
+) -> WebResult<AudienceQuestion> {
+    let presenter = viewer == Some(live.user_id);
+    audience_questions::get_question(pool, live.id, question_id, viewer, presenter)
+        .await
+        .map_err(Error::Database)?
+        .ok_or(Error::QuestionNotFound)
+}
+
+/**
+A question of a live session the caller presents
+*/
+async fn moderated_question(
+    pool: &PgPool,
+    session_id: Uuid,
+    question_id: Uuid,
+    session: &Session,
+) -> WebResult<(LiveSession, AudienceQuestion)> {
+    let user_id = validate_session(session)?;
+    let live = find_live_session(pool, session_id).await?;
+    if live.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    let question = find_question(pool, &live, question_id, Some(user_id)).await?;
+    Ok((live, question))
+}
+
+/// The question after a change, as `viewer` now sees it
+async fn question_response(
+    pool: &PgPool,
+    live: &LiveSession,
+    question_id: Uuid,
+    viewer: Uuid,
+) -> WebResult<HttpResponse> {
+    let question = find_question(pool, live, question_id, Some(viewer)).await?;
+    Ok(HttpResponse::Ok().json(AudienceQuestionData::from(question)))
+}
+
+async fn question_list(
+    pool: &PgPool,
+    session_id: Uuid,
+    viewer: Option<Uuid>,
+) -> WebResult<Vec<AudienceQuestionData>> {
+    let live = find_live_session(pool, session_id).await?;
+    let presenter = viewer == Some(live.user_id);
+    let questions = audience_questions::list_questions(pool, live.id, viewer, presenter)
+        .await
+        .map_err(Error::Database)?;
+    Ok(questions
+        .into_iter()
+        .map(AudienceQuestionData::from)
+        .collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live-sessions/{session_id}/questions",
+    tag = "qa",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "Open questions before answered ones, each by most upvotes; \
+            hidden questions are left out for everyone but the presenter and their author",
+            body = [AudienceQuestionData]),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn list_questions(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let questions = question_list(&pool, session_id.into_inner(), session_user(&session)).await?;
+    Ok(HttpResponse::Ok().json(questions))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/live-sessions/{session_id}/questions",
+    tag = "qa",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    request_body = AskQuestionRequest,
+    responses(
+        (status = 201, description = "The new question", body = AudienceQuestionData),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+        (status = 422, description = "The question is blank or too long", body = ErrorBody),
+    )
+)]
+pub async fn ask_question(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<AskQuestionRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let live = find_live_session(&pool, session_id.into_inner()).await?;
+    let mut v = Validator::new();
+    v.text("body", &req.body, limits.max_audience_question_len);
+    v.finish()?;
+
+    let question_id = Uuid::new_v4();
+    audience_questions::create_question(
+        pool.get_ref(),
+        question_id,
+        live.id,
+        user_id,
+        req.body.trim(),
+    )
+    .await
+    .map_err(Error::Database)?;
+    let question = find_question(&pool, &live, question_id, Some(user_id)).await?;
+    Ok(HttpResponse::Created().json(AudienceQuestionData::from(question)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/live-sessions/{session_id}/questions/{question_id}",
+    tag = "qa",
+    params(
+        ("session_id" = Uuid, Path, description = "Live session id"),
+        ("question_id" = Uuid, Path, description = "Question id"),
+    ),
+    responses(
+        (status = 204, description = "Question deleted along with its upvotes"),
+        (status = 401, description = "Caller neither asked the question nor presents the session", body = ErrorBody),
+        (status = 404, description = "Live session or question not found", body = ErrorBody),
+    )
+)]
+pub async fn delete_question(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let (session_id, question_id) = path.into_inner();
+    let user_id = validate_session(&session)?;
+    let live = find_live_session(&pool, session_id).await?;
+    let question = find_question(&pool, &live, question_id, Some(user_id)).await?;
+    if question.user_id != user_id && live.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    audience_questions::delete_question(&pool, question.id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/live-sessions/{session_id}/questions/{question_id}/votes",
+    tag = "qa",
+    params(
+        ("session_id" = Uuid, Path, description = "Live session id"),
+        ("question_id" = Uuid, Path, description = "Question id"),
+    ),
+    responses(
+        (status = 200, description = "The upvoted question", body = AudienceQuestionData),
+        (status = 400, description = "The caller already upvoted this question", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "Live session or question not found", body = ErrorBody),
+    )
+)]
+pub async fn upvote_question(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let (session_id, question_id) = path.into_inner();
+    let user_id = validate_session(&session)?;
+    let live = find_live_session(&pool, session_id).await?;
+    let question = find_question(&pool, &live, question_id, Some(user_id)).await?;
+    let added = audience_questions::upvote(pool.get_ref(), question.id, user_id)
+        .await
+        .map_err(Error::Database)?;
+    if !added {
+        return Err(Error::AlreadyVoted);
+    }
+    question_response(&pool, &live, question.id, user_id).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/live-sessions/{session_id}/questions/{question_id}/votes/me",
+    tag = "qa",
+    params(
+        ("session_id" = Uuid, Path, description = "Live session id"),
+        ("question_id" = Uuid, Path, description = "Question id"),
+    ),
+    responses(
+        (status = 200, description = "The question without the caller's upvote", body = AudienceQuestionData),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "Live session, question or upvote not found", body = ErrorBody),
+    )
+)]
+pub async fn remove_question_upvote(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let (session_id, question_id) = path.into_inner();
+    let user_id = validate_session(&session)?;
+    let live = find_live_session(&pool, session_id).await?;
+    let question = find_question(&pool, &live, question_id, Some(user_id)).await?;
+    let removed = audience_questions::remove_upvote(&pool, question.id, user_id)
+        .await
+        .map_err(Error::Database)?;
+    if !removed {
+        return Err(Error::VoteNotFound);
+    }
+    question_response(&pool, &live, question.id, user_id).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/live-sessions/{session_id}/questions/{question_id}/answered",
+    tag = "qa",
+    params(
+        ("session_id" = Uuid, Path, description = "Live session id"),
+        ("question_id" = Uuid, Path, description = "Question id"),
+    ),
+    request_body = AnsweredRequest,
+    responses(
+        (status = 200, description = "The question, now answered or open again", body = AudienceQuestionData),
+        (status = 401, description = "Caller does not present the session", body = ErrorBody),
+        (status = 404, description = "Live session or question not found", body = ErrorBody),
+    )
+)]
+pub async fn mark_answered(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<AnsweredRequest>,
+) -> WebResult<HttpResponse> {
+    let (session_id, question_id) = path.into_inner();
+    let (live, question) = moderated_question(&pool, session_id, question_id, &session).await?;
+    audience_questions::set_answered(pool.get_ref(), question.id, req.answered)
+        .await
+        .map_err(Error::Database)?;
+    question_response(&pool, &live, question.id, live.user_id).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/live-sessions/{session_id}/questions/{question_id}/hidden",
+    tag = "qa",
+    params(
+        ("session_id" = Uuid, Path, description = "Live session id"),
+        ("question_id" = Uuid, Path, description = "Question id"),
+    ),
+    request_body = HideQuestionRequest,
+    responses(
+        (status = 200, description = "The question; hidden ones are only shown to the presenter \
+            and their author", body = AudienceQuestionData),
+        (status = 401, description = "Caller does not present the session", body = ErrorBody),
+        (status = 404, description = "Live session or question not found", body = ErrorBody),
+    )
+)]
+pub async fn hide_question(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<HideQuestionRequest>,
+) -> WebResult<HttpResponse> {
+    let (session_id, question_id) = path.into_inner();
+    let (live, question) = moderated_question(&pool, session_id, question_id, &session).await?;
+    audience_questions::set_hidden(pool.get_ref(), question.id, req.hidden)
+        .await
+        .map_err(Error::Database)?;
+    question_response(&pool, &live, question.id, live.user_id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live-sessions/{session_id}/questions/stream",
+    tag = "qa",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "Server-sent events; a `data:` frame carrying the ordered \
+            question list is sent on connect and whenever a question, its upvotes or its \
+            moderation changes. Failures arrive as an `error` event carrying an ErrorBody",
+            content_type = "text/event-stream", body = [AudienceQuestionData]),
+    )
+)]
+pub async fn stream_questions(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> impl Responder {
+    let session_id = session_id.into_inner();
+    let viewer = session_user(&session);
+    let request_id = request_id::current();
+    let mut interval = tokio::time::interval(Duration::from_secs(2));
+
+    let stream = async_stream::stream! {
+        let mut last: Option<String> = None;
+        loop {
+            interval.tick().await;
+
+            let questions = match question_list(&pool, session_id, viewer).await {
+                Ok(questions) => questions,
+                Err(e) => {
+                    yield Result::<web::Bytes, Box<dyn std::error::Error>>::Ok(sse_error(e, request_id));
+                    return;
+                }
+            };
+            let data = serde_json::to_string(&questions).unwrap();
+            if last.as_ref() == Some(&data) {
+                continue;
+            }
+            yield Ok(web::Bytes::from(format!("data: {}\n\n", data)));
+            last = Some(data);
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(stream)
+}
@@ -0,0 +1,579 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, FieldError, WebResult},
+        validate_session::{session_user, validate_session},
+    },
+    db::{
+        comments::{self, Comment, CommentAction, CommentEvent},
+        polls::{self, Poll},
+    },
+    polls::validation::{PollLimits, Validator},
+};
+use actix_session::Session;
+use actix_web::{
+    web::{self, Data, Json, Path, Query},
+    HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+/**
+A comment as its reader sees it
+*/
+#[derive(Serialize, ToSchema)]
+pub struct CommentData {
+    id: Uuid,
+    poll_id: Uuid,
+    /// The comment this one replies to, absent for top-level comments
+    parent_id: Option<Uuid>,
+    user_id: Uuid,
+    username: String,
+    /// Absent once the comment is deleted; it then only stays to hold its replies
+    body: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    edited_at: Option<chrono::DateTime<chrono::Utc>>,
+    deleted: bool,
+    /// Hidden by the poll's owner; only they and the author still see it
+    hidden: bool,
+    pinned: bool,
+    /// Replies the reader may see
+    reply_count: i64,
+}
+
+impl From<Comment> for CommentData {
+    fn from(comment: Comment) -> Self {
+        let deleted = comment.deleted_at.is_some();
+        CommentData {
+            id: comment.id,
+            poll_id: comment.poll_id,
+            parent_id: comment.parent_id,
+            user_id: comment.user_id,
+            username: comment.username,
+            body: (!deleted).then_some(comment.body),
+            created_at: comment.created_at,
+            edited_at: comment.edited_at,
+            deleted,
+            hidden: comment.hidden,
+            pinned: comment.pinned,
+            reply_count: comment.reply_count,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateCommentRequest {
+    body: String,
+    /// Comment of the same poll to reply to
+    parent_id: Option<Uuid>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct EditCommentRequest {
+    body: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct HideCommentRequest {
+    hidden: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PinCommentRequest {
+    pinned: bool,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CommentPageQuery {
+    /// List the replies to this comment instead of the top-level comments
+    parent_id: Option<Uuid>,
+    /// Page number, starting at 1
+    #[serde(default = "default_page")]
+    page: i64,
+    /// Comments per page, at most 100
+    #[serde(default = "default_per_page")]
+    per_page: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CommentPage {
+    pub page: i64,
+    pub per_page: i64,
+    /// Comments across all pages
+    pub total: i64,
+    pub comments: Vec<CommentData>,
+}
+
+async fn find_poll(pool: &PgPool, poll_id: Uuid) -> WebResult<Poll> {
+    polls::get_poll(pool, poll_id).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => Error::PollNotFound,
+        _ => Error::Database(e),
+    })
+}
+
+/**
+Loads a comment of `poll` as `viewer` sees it; the poll's owner moderates its comments.
+*/
+async fn find_comment(
+    pool: &PgPool,
+    poll: &Poll,
+    comment_id: Uuid,
+    viewer: Option<Uuid>,
+) -> WebResult<Comment> {
+    let moderator = viewer == Some(poll.user_id);
+    comments::get_comment(pool, poll.id, comment_id, viewer, moderator)
+        .await
+        .map_err(Error::Database)?
+        .ok_or(Error::CommentNotFound)
+}
+
+/**
+A comment the caller wrote and has not deleted
+*/
+async fn own_comment(
+    pool: &PgPool,
+    poll_id: Uuid,
+    comment_id: Uuid,
+    session: &Session,
+) -> WebResult<(Poll, Comment)> {
+    let user_id = validate_session(session)?;
+    let poll = find_poll(pool, poll_id).await?;
+    let comment = find_comment(pool, &poll, comment_id, Some(user_id)).await?;
+    if comment.deleted_at.is_some() {
+        return Err(Error::CommentNotFound);
+    }
+    if comment.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    Ok((poll, comment))
+}
+
+/**
+A comment of a poll the caller owns
+*/
+async fn moderated_comment(
+    pool: &PgPool,
+    poll_id: Uuid,
+    comment_id: Uuid,
+    session: &Session,
+) -> WebResult<(Poll, Comment)> {
+    let user_id = validate_session(session)?;
+    let poll = find_poll(pool, poll_id).await?;
+    if poll.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    let comment = find_comment(pool, &poll, comment_id, Some(user_id)).await?;
+    Ok((poll, comment))
+}
+
+/// The comment after a change, as `viewer` now sees it
+async fn comment_response(
+    pool: &PgPool,
+    poll: &Poll,
+    comment_id: Uuid,
+    viewer: Uuid,
+) -> WebResult<HttpResponse> {
+    let comment = find_comment(pool, poll, comment_id, Some(viewer)).await?;
+    Ok(HttpResponse::Ok().json(CommentData::from(comment)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/comments",
+    tag = "comments",
+    params(("poll_id" = Uuid, Path, description = "Poll id"), CommentPageQuery),
+    responses(
+        (status = 200, description = "Pinned comments first, then the oldest; hidden comments \
+            are left out for everyone but the poll's owner and their author", body = CommentPage),
+        (status = 404, description = "Poll, or the parent comment, not found", body = ErrorBody),
+        (status = 422, description = "The page is out of range", body = ErrorBody),
+    )
+)]
+pub async fn list_comments(
+    poll_id: Path<Uuid>,
+    query: Query<CommentPageQuery>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let mut v = Validator::new();
+    if query.page < 1 {
+        v.add("page", "must be at least 1");
+    }
+    if !(1..=100).contains(&query.per_page) {
+        v.add("per_page", "must be between 1 and 100");
+    }
+    v.finish()?;
+
+    let poll = find_poll(&pool, poll_id.into_inner()).await?;
+    let viewer = session_user(&session);
+    let moderator = viewer == Some(poll.user_id);
+    if let Some(parent_id) = query.parent_id {
+        find_comment(&pool, &poll, parent_id, viewer).await?;
+    }
+
+    let total = comments::count_comments(&pool, poll.id, query.parent_id, viewer, moderator)
+        .await
+        .map_err(Error::Database)?;
+    let comments = comments::list_comments(
+        &pool,
+        poll.id,
+        query.parent_id,
+        viewer,
+        moderator,
+        query.per_page,
+        (query.page - 1) * query.per_page,
+    )
+    .await
+    .map_err(Error::Database)?
+    .into_iter()
+    .map(CommentData::from)
+    .collect();
+    Ok(HttpResponse::Ok().json(CommentPage {
+        page: query.page,
+        per_page: query.per_page,
+        total,
+        comments,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/{poll_id}/comments",
+    tag = "comments",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = CreateCommentRequest,
+    responses(
+        (status = 201, description = "The new comment", body = CommentData),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The body is blank or too long, or the parent is not a \
+            comment of this poll", body = ErrorBody),
+    )
+)]
+pub async fn create_comment(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<CreateCommentRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let poll = find_poll(&pool, poll_id.into_inner()).await?;
+
+    let mut v = Validator::new();
+    v.text("body", &req.body, limits.max_comment_len);
+    if let Some(parent_id) = req.parent_id {
+        match find_comment(&pool, &poll, parent_id, Some(user_id)).await {
+            Ok(parent) if parent.deleted_at.is_some() => {
+                v.add("parent_id", "cannot reply to a deleted comment")
+            }
+            Ok(_) => {}
+            Err(Error::CommentNotFound) => v.add("parent_id", "is not a comment of this poll"),
+            Err(e) => return Err(e),
+        }
+    }
+    v.finish()?;
+
+    let comment_id = Uuid::new_v4();
+    comments::create_comment(
+        pool.get_ref(),
+        comment_id,
+        poll.id,
+        req.parent_id,
+        user_id,
+        req.body.trim(),
+    )
+    .await
+    .map_err(Error::Database)?;
+    let comment = find_comment(&pool, &poll, comment_id, Some(user_id)).await?;
+    Ok(HttpResponse::Created().json(CommentData::from(comment)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/polls/{poll_id}/comments/{comment_id}",
+    tag = "comments",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("comment_id" = Uuid, Path, description = "Comment id"),
+    ),
+    request_body = EditCommentRequest,
+    responses(
+        (status = 200, description = "The edited comment", body = CommentData),
+        (status = 401, description = "Caller did not write the comment", body = ErrorBody),
+        (status = 404, description = "Poll or comment not found", body = ErrorBody),
+        (status = 422, description = "The body is blank or too long", body = ErrorBody),
+    )
+)]
+pub async fn edit_comment(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<EditCommentRequest>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, comment_id) = path.into_inner();
+    let (poll, comment) = own_comment(&pool, poll_id, comment_id, &session).await?;
+    let mut v = Validator::new();
+    v.text("body", &req.body, limits.max_comment_len);
+    v.finish()?;
+    comments::edit_comment(&pool, comment.id, req.body.trim())
+        .await
+        .map_err(Error::Database)?;
+    comment_response(&pool, &poll, comment.id, comment.user_id).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}/comments/{comment_id}",
+    tag = "comments",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("comment_id" = Uuid, Path, description = "Comment id"),
+    ),
+    responses(
+        (status = 204, description = "Comment deleted; one with replies stays as a placeholder"),
+        (status = 401, description = "Caller did not write the comment", body = ErrorBody),
+        (status = 404, description = "Poll or comment not found", body = ErrorBody),
+    )
+)]
+pub async fn delete_comment(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, comment_id) = path.into_inner();
+    let (_, comment) = own_comment(&pool, poll_id, comment_id, &session).await?;
+    comments::delete_comment(&pool, comment.id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/polls/{poll_id}/comments/{comment_id}/hidden",
+    tag = "comments",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("comment_id" = Uuid, Path, description = "Comment id"),
+    ),
+    request_body = HideCommentRequest,
+    responses(
+        (status = 200, description = "The comment, now hidden from everyone but its author, or \
+            shown again", body = CommentData),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll or comment not found", body = ErrorBody),
+    )
+)]
+pub async fn hide_comment(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<HideCommentRequest>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, comment_id) = path.into_inner();
+    let (poll, comment) = moderated_comment(&pool, poll_id, comment_id, &session).await?;
+    comments::set_comment_hidden(pool.get_ref(), comment.id, req.hidden)
+        .await
+        .map_err(Error::Database)?;
+    comment_response(&pool, &poll, comment.id, poll.user_id).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/polls/{poll_id}/comments/{comment_id}/pinned",
+    tag = "comments",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("comment_id" = Uuid, Path, description = "Comment id"),
+    ),
+    request_body = PinCommentRequest,
+    responses(
+        (status = 200, description = "The comment, now listed first among its siblings, or \
+            back in order", body = CommentData),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll or comment not found", body = ErrorBody),
+        (status = 422, description = "The comment is deleted", body = ErrorBody),
+    )
+)]
+pub async fn pin_comment(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<PinCommentRequest>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, comment_id) = path.into_inner();
+    let (poll, comment) = moderated_comment(&pool, poll_id, comment_id, &session).await?;
+    if req.pinned && comment.deleted_at.is_some() {
+        return Err(Error::Validation(vec![FieldError::new(
+            "pinned",
+            "a deleted comment cannot be pinned",
+        )]));
+    }
+    comments::set_comment_pinned(&pool, comment.id, req.pinned)
+        .await
+        .map_err(Error::Database)?;
+    comment_response(&pool, &poll, comment.id, poll.user_id).await
+}
+
+/**
+A change to one of the poll's comments, sent as a `comment` event on the results stream
+*/
+#[derive(Serialize, ToSchema)]
+pub struct CommentEventData {
+    /// Increases with every event of the poll
+    id: i64,
+    action: CommentAction,
+    comment_id: Uuid,
+    /// The comment as it is now; absent once it is gone or hidden from the reader
+    comment: Option<CommentData>,
+}
+
+/**
+Formats the poll's comment events after `*after_id` as SSE `comment` frames for
+`viewer`, and moves `*after_id` past them.
+*/
+pub async fn comment_frames(
+    pool: &PgPool,
+    poll: &Poll,
+    viewer: Option<Uuid>,
+    after_id: &mut i64,
+) -> Result<Vec<web::Bytes>, sqlx::Error> {
+    let events = comments::get_comment_events(pool, poll.id, *after_id).await?;
+    let moderator = viewer == Some(poll.user_id);
+    let mut frames = Vec::with_capacity(events.len());
+    for event in events {
+        let comment =
+            comments::get_comment(pool, poll.id, event.comment_id, viewer, moderator).await?;
+        *after_id = event.id;
+        frames.extend(comment_frame(event, comment));
+    }
+    Ok(frames)
+}
+
+/**
+The SSE frame of one comment event, given the comment as the reader sees it now. Readers
+only hear about comments they cannot see when those disappear.
+*/
+fn comment_frame(event: CommentEvent, comment: Option<Comment>) -> Option<web::Bytes> {
+    let gone = matches!(event.action, CommentAction::Deleted | CommentAction::Hidden);
+    if comment.is_none() && !gone {
+        return None;
+    }
+    let data = CommentEventData {
+        id: event.id,
+        action: event.action,
+        comment_id: event.comment_id,
+        comment: comment.map(CommentData::from),
+    };
+    Some(web::Bytes::from(format!(
+        "event: comment\ndata: {}\n\n",
+        serde_json::to_string(&data).unwrap()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(action: CommentAction) -> CommentEvent {
+        CommentEvent {
+            id: 7,
+            comment_id: Uuid::new_v4(),
+            action,
+        }
+    }
+
+    fn comment(id: Uuid) -> Comment {
+        Comment {
+            id,
+            poll_id: Uuid::new_v4(),
+            parent_id: None,
+            user_id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            body: "Pizza, obviously".to_string(),
+            created_at: chrono::Utc::now(),
+            edited_at: None,
+            deleted_at: None,
+            hidden: false,
+            pinned: false,
+            reply_count: 0,
+        }
+    }
+
+    /// The JSON of a frame's data line
+    fn data(frame: web::Bytes) -> serde_json::Value {
+        let frame = std::str::from_utf8(&frame).unwrap();
+        let data = frame
+            .strip_prefix("event: comment\ndata: ")
+            .and_then(|rest| rest.strip_suffix("\n\n"))
+            .expect("a comment frame");
+        serde_json::from_str(data).unwrap()
+    }
+
+    #[test]
+    fn visible_comments_are_sent_with_every_change() {
+        for action in [
+            CommentAction::Created,
+            CommentAction::Edited,
+            CommentAction::Unhidden,
+            CommentAction::Pinned,
+            CommentAction::Unpinned,
+        ] {
+            let event = event(action);
+            let comment = comment(event.comment_id);
+            let data = data(comment_frame(event, Some(comment)).unwrap());
+            assert_eq!(data["id"], 7);
+            assert_eq!(data["comment"]["body"], "Pizza, obviously");
+        }
+    }
+
+    #[test]
+    fn changes_to_comments_the_reader_cannot_see_are_left_out() {
+        for action in [
+            CommentAction::Created,
+            CommentAction::Edited,
+            CommentAction::Unhidden,
+            CommentAction::Pinned,
+            CommentAction::Unpinned,
+        ] {
+            assert!(comment_frame(event(action), None).is_none());
+        }
+    }
+
+    #[test]
+    fn readers_hear_when_a_comment_disappears() {
+        for (action, name) in [
+            (CommentAction::Deleted, "deleted"),
+            (CommentAction::Hidden, "hidden"),
+        ] {
+            let event = event(action);
+            let comment_id = event.comment_id;
+            let data = data(comment_frame(event, None).unwrap());
+            assert_eq!(data["action"], name);
+            assert_eq!(data["comment_id"], comment_id.to_string());
+            assert!(data["comment"].is_null());
+        }
+    }
+
+    #[test]
+    fn moderators_keep_seeing_hidden_comments() {
+        let event = event(CommentAction::Hidden);
+        let comment = Comment {
+            hidden: true,
+            ..comment(event.comment_id)
+        };
+        let data = data(comment_frame(event, Some(comment)).unwrap());
+        assert_eq!(data["comment"]["hidden"], true);
+    }
+}
@@ -0,0 +1,453 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, FieldError, WebResult},
+        validate_session::{session_user, validate_session},
+    },
+    db::{polls, webhooks::WebhookEvent, weights},
+    polls::{
+        manage_polls::{
+            create_poll_on, insert_poll, poll_data, poll_valid_owner_authorized, CreatePollRequest,
+            PollData,
+        },
+        options::PollOptionInput,
+        questions::Question,
+        quiz::{self, QuizInput},
+        validation::PollLimits,
+        webhooks,
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    http::header,
+    web::{Bytes, Data, Json, Path, Query},
+    HttpRequest, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+/// Version written into exported definitions; imports of any other version are rejected
+pub const DEFINITION_VERSION: u32 = 1;
+
+fn default_version() -> u32 {
+    DEFINITION_VERSION
+}
+
+/**
+Portable description of a poll: everything needed to create it again, but none of its
+identity, ownership or votes.
+*/
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PollDefinition {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(flatten)]
+    pub poll: CreatePollRequest,
+}
+
+impl PollDefinition {
+    pub fn from_poll(poll: polls::Poll, options: Vec<polls::PollOption>) -> Self {
+        let question = Question::from(&poll);
+        let quiz = QuizInput::from_poll(&poll, &options);
+        PollDefinition {
+            version: DEFINITION_VERSION,
+            poll: CreatePollRequest {
+                poll_name: poll.title,
+                poll_description: poll.description,
+                poll_options: options.into_iter().map(PollOptionInput::from).collect(),
+                question,
+                secret_ballot: poll.secret_ballot,
+                results_visibility: poll.results_visibility,
+                recurrence: poll.recurrence,
+                weighted: poll.weighted,
+                default_weight: Some(poll.default_weight).filter(|&weight| weight != 1.0),
+                participants_only: poll.participants_only,
+                quorum_votes: poll.quorum_votes,
+                quorum_percent: poll.quorum_percent,
+                pass_threshold: poll.pass_threshold,
+                auto_close: poll.auto_close,
+                quiz,
+            },
+        }
+    }
+
+    /// Checks the version; the poll itself is validated when it is created
+    pub fn check_version(&self) -> Result<(), Error> {
+        if self.version != DEFINITION_VERSION {
+            return Err(Error::Validation(vec![FieldError::new(
+                "version",
+                format!("must be {}", DEFINITION_VERSION),
+            )]));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DefinitionFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl DefinitionFormat {
+    /**
+    Picks the format from a request's `Content-Type`, defaulting to JSON when none is set.
+    */
+    fn from_request(req: &HttpRequest) -> Result<Self, Error> {
+        let content_type = match req.headers().get(header::CONTENT_TYPE) {
+            Some(value) => value.to_str().map_err(|_| Error::UnsupportedMediaType)?,
+            None => return Ok(DefinitionFormat::Json),
+        };
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        match mime.to_ascii_lowercase().as_str() {
+            "application/json" => Ok(DefinitionFormat::Json),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                Ok(DefinitionFormat::Yaml)
+            }
+            _ => Err(Error::UnsupportedMediaType),
+        }
+    }
+
+    fn parse(self, body: &[u8]) -> Result<PollDefinition, Error> {
+        match self {
+            DefinitionFormat::Json => {
+                serde_json::from_slice(body).map_err(|e| Error::InvalidRequest(e.to_string()))
+            }
+            DefinitionFormat::Yaml => {
+                serde_yaml::from_slice(body).map_err(|e| Error::InvalidRequest(e.to_string()))
+            }
+        }
+    }
+
+    fn render(self, definition: &PollDefinition) -> Result<HttpResponse, Error> {
+        let (content_type, body) = match self {
+            DefinitionFormat::Json => (
+                "application/json",
+                serde_json::to_string_pretty(definition)
+                    .map_err(|e| Error::Export(e.to_string()))?,
+            ),
+            DefinitionFormat::Yaml => (
+                "application/yaml",
+                serde_yaml::to_string(definition).map_err(|e| Error::Export(e.to_string()))?,
+            ),
+        };
+        Ok(HttpResponse::Ok().content_type(content_type).body(body))
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DefinitionQuery {
+    #[param(inline)]
+    #[serde(default)]
+    format: DefinitionFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/definition",
+    tag = "templates",
+    params(("poll_id" = Uuid, Path, description = "Poll id"), DefinitionQuery),
+    responses(
+        (status = 200, description = "Portable definition of the poll; a quiz's `correct_options` stay empty for anyone but its owner until the answers are revealed", content(
+            (PollDefinition = "application/json"),
+            (PollDefinition = "application/yaml"),
+        )),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn get_poll_definition(
+    poll_id: Path<Uuid>,
+    query: Query<DefinitionQuery>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let definition = load_definition(&pool, poll_id.into_inner(), session_user(&session)).await?;
+    query.format.render(&definition)
+}
+
+/**
+Loads the definition of a poll as `viewer` may see it: a quiz's answers are left out until
+they are revealed, unless `viewer` owns the poll.
+*/
+async fn load_definition(
+    pool: &PgPool,
+    poll_id: Uuid,
+    viewer: Option<Uuid>,
+) -> WebResult<PollDefinition> {
+    let poll = polls::get_poll(pool, poll_id).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => Error::PollNotFound,
+        _ => Error::Database(e),
+    })?;
+    let mut options = polls::get_poll_options_data(pool, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    if !quiz::answers_visible(&poll, viewer) {
+        quiz::hide_answers(&mut options);
+    }
+    Ok(PollDefinition::from_poll(poll, options))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/import",
+    tag = "templates",
+    request_body(content(
+        (PollDefinition = "application/json"),
+        (PollDefinition = "application/yaml"),
+    )),
+    responses(
+        (status = 201, description = "The created poll with its options", body = PollData),
+        (status = 400, description = "The body is not a readable definition", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 415, description = "Body is neither JSON nor YAML", body = ErrorBody),
+        (status = 422, description = "The definition failed validation", body = ErrorBody),
+    )
+)]
+pub async fn import_poll(
+    req: HttpRequest,
+    body: Bytes,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let definition = DefinitionFormat::from_request(&req)?.parse(&body)?;
+    definition.check_version()?;
+    let poll = insert_poll(&pool, user_id, &definition.poll, &limits).await?;
+    Ok(HttpResponse::Created().json(poll))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DuplicatePollRequest {
+    /// Title of the copy; defaults to the original title
+    poll_name: Option<String>,
+    /// Also copy settings such as `secret_ballot`, `results_visibility`, weighting and
+    /// quorum rules
+    #[serde(default)]
+    include_settings: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/{poll_id}/duplicate",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll to copy")),
+    request_body = DuplicatePollRequest,
+    responses(
+        (status = 201, description = "The copy, with no votes and no recurrence", body = PollData),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The new title failed validation", body = ErrorBody),
+    )
+)]
+pub async fn duplicate_poll(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<DuplicatePollRequest>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    let user_id = validate_session(&session)?;
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+
+    let req = req.into_inner();
+    let mut copy = load_definition(&pool, poll_id, Some(user_id)).await?.poll;
+    // A copy starts a new poll, never a second instance of the same series
+    copy.recurrence = None;
+    if !req.include_settings {
+        copy.secret_ballot = false;
+        copy.results_visibility = Default::default();
+        copy.weighted = false;
+        copy.default_weight = None;
+        copy.participants_only = false;
+        copy.quorum_votes = None;
+        copy.quorum_percent = None;
+        copy.pass_threshold = Default::default();
+        copy.auto_close = false;
+    }
+    if let Some(poll_name) = req.poll_name {
+        copy.poll_name = poll_name;
+    }
+    // The copy is only announced once its participants are in place
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let mut poll = create_poll_on(&mut tx, user_id, &copy, &limits).await?;
+    if req.include_settings {
+        weights::copy_weights(&mut tx, poll_id, poll.id())
+            .await
+            .map_err(Error::Database)?;
+        let created = polls::get_poll(&mut *tx, poll.id())
+            .await
+            .map_err(Error::Database)?;
+        poll = poll_data(&mut tx, created, Some(user_id))
+            .await
+            .map_err(Error::Database)?;
+    }
+    webhooks::notify(&mut tx, poll.id(), WebhookEvent::Created, None)
+        .await
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
+    Ok(HttpResponse::Created().json(poll))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const DEFINITION: &str = r##"{
+        "version": 1,
+        "poll_name": "Lunch",
+        "poll_description": "Where do we eat?",
+        "poll_options": [
+            "Pizza",
+            { "option_text": "Sushi", "description": "Around the corner", "color": "#ff0000" }
+        ],
+        "secret_ballot": true,
+        "results_visibility": "after_close",
+        "recurrence": "weekly",
+        "quorum_votes": 3,
+        "pass_threshold": "majority"
+    }"##;
+
+    async fn render(format: DefinitionFormat, definition: &PollDefinition) -> Bytes {
+        let response = format.render(definition).unwrap();
+        actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap()
+    }
+
+    fn as_json(definition: &PollDefinition) -> serde_json::Value {
+        serde_json::to_value(definition).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn definitions_survive_json_and_yaml() {
+        let original = DefinitionFormat::Json.parse(DEFINITION.as_bytes()).unwrap();
+        let yaml = render(DefinitionFormat::Yaml, &original).await;
+        let from_yaml = DefinitionFormat::Yaml.parse(&yaml).unwrap();
+        assert_eq!(as_json(&from_yaml), as_json(&original));
+
+        let json = render(DefinitionFormat::Json, &from_yaml).await;
+        let from_json = DefinitionFormat::Json.parse(&json).unwrap();
+        assert_eq!(as_json(&from_json), as_json(&original));
+        assert_eq!(
+            as_json(&from_json)["poll_options"],
+            serde_json::json!([
+                "Pizza",
+                { "option_text": "Sushi", "description": "Around the corner", "color": "#ff0000" }
+            ])
+        );
+    }
+
+    #[test]
+    fn stored_polls_leave_out_defaults() {
+        let mut poll = polls::Poll::fixture();
+        poll.title = "Lunch".to_string();
+        let options = ["Pizza", "Sushi"]
+            .iter()
+            .enumerate()
+            .map(|(position, text)| polls::PollOption {
+                id: Uuid::new_v4(),
+                poll_id: poll.id,
+                option_text: text.to_string(),
+                votes_count: Some(4),
+                weighted_votes: Some(4.0),
+                position: position as i32,
+                description: None,
+                image_url: None,
+                image_asset_id: None,
+                color: None,
+                is_correct: None,
+            })
+            .collect();
+        let definition = as_json(&PollDefinition::from_poll(poll, options));
+        assert_eq!(definition["version"], DEFINITION_VERSION);
+        assert_eq!(
+            definition["poll_options"],
+            serde_json::json!(["Pizza", "Sushi"])
+        );
+        for absent in [
+            "default_weight",
+            "recurrence",
+            "quiz",
+            "question",
+            "id",
+            "votes_count",
+        ] {
+            assert!(definition.get(absent).is_none(), "{} is present", absent);
+        }
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let definition = DefinitionFormat::Json
+            .parse(
+                DEFINITION
+                    .replace("\"version\": 1", "\"version\": 2")
+                    .as_bytes(),
+            )
+            .unwrap();
+        match definition.check_version() {
+            Err(Error::Validation(errors)) => assert_eq!(errors[0].field, "version"),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        // Definitions without a version are taken to be of the current one
+        let definition = DefinitionFormat::Json
+            .parse(DEFINITION.replace("\"version\": 1,", "").as_bytes())
+            .unwrap();
+        assert_eq!(definition.version, DEFINITION_VERSION);
+        assert!(definition.check_version().is_ok());
+    }
+
+    #[test]
+    fn malformed_bodies_are_invalid_requests() {
+        assert!(matches!(
+            DefinitionFormat::Yaml.parse(b"poll_name: [unclosed"),
+            Err(Error::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            DefinitionFormat::Json.parse(b"{\"poll_name\": \"No description\"}"),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    fn format_of(content_type: Option<&str>) -> Result<DefinitionFormat, Error> {
+        let mut req = TestRequest::default();
+        if let Some(content_type) = content_type {
+            req = req.insert_header((header::CONTENT_TYPE, content_type));
+        }
+        DefinitionFormat::from_request(&req.to_http_request())
+    }
+
+    #[test]
+    fn the_format_follows_the_content_type() {
+        for (content_type, yaml) in [
+            (None, false),
+            (Some("application/json"), false),
+            (Some("application/json; charset=utf-8"), false),
+            (Some("application/yaml"), true),
+            (Some("Application/X-YAML"), true),
+            (Some("text/yaml"), true),
+            (Some("text/x-yaml; charset=utf-8"), true),
+        ] {
+            let format = format_of(content_type).unwrap();
+            assert_eq!(
+                matches!(format, DefinitionFormat::Yaml),
+                yaml,
+                "{:?}",
+                content_type
+            );
+        }
+        for content_type in ["text/plain", "application/xml", "multipart/form-data"] {
+            assert!(matches!(
+                format_of(Some(content_type)),
+                Err(Error::UnsupportedMediaType)
+            ));
+        }
+    }
+}
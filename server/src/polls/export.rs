@@ -0,0 +1,429 @@
+use crate::{
+    auth::error::{Error, ErrorBody, FieldError, WebResult},
+    db::polls::{self, Ballot},
+    polls::{
+        manage_polls::poll_valid_owner_authorized,
+        results::{self, OutcomeStatus, ResultStats},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    http::header,
+    web::{Data, Path, Query},
+    HttpResponse,
+};
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::borrow::Cow;
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Xlsx,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportSheet {
+    #[default]
+    Summary,
+    Ballots,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExportQuery {
+    #[param(inline)]
+    format: ExportFormat,
+    /// Table to export as CSV; JSON and XLSX always contain both
+    #[param(inline)]
+    #[serde(default)]
+    sheet: ExportSheet,
+}
+
+/**
+One row of the summary table; flat so it can be written as CSV
+*/
+#[derive(Serialize, ToSchema)]
+pub struct OptionSummary {
+    pub option_id: Uuid,
+    pub position: i32,
+    pub option_text: String,
+    pub votes: i64,
+    /// Summed voter weight, for weighted polls
+    pub weight: Option<f64>,
+    pub percentage: f64,
+    pub ci_lower: f64,
+    pub ci_upper: f64,
+    pub rank: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PollExport {
+    pub poll_id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub is_active: bool,
+    pub secret_ballot: bool,
+    pub weighted: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub total_votes: i64,
+    pub options: Vec<OptionSummary>,
+    pub stats: ResultStats,
+    /// Every vote in the order it was cast; left out for secret ballot polls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ballots: Option<Vec<Ballot>>,
+}
+
+async fn load_export(pool: &PgPool, poll_id: Uuid) -> WebResult<PollExport> {
+    let poll = polls::get_poll(pool, poll_id).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => Error::PollNotFound,
+        _ => Error::Database(e),
+    })?;
+    let options = polls::get_poll_options_data(pool, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    let ballots = if poll.secret_ballot {
+        None
+    } else {
+        Some(
+            polls::get_poll_ballots(pool, poll_id)
+                .await
+                .map_err(Error::Database)?,
+        )
+    };
+
+    let electorate = results::electorate(pool, &poll)
+        .await
+        .map_err(Error::Database)?;
+    let stats = ResultStats::compute(&poll, &options, electorate);
+    let options = options
+        .into_iter()
+        .zip(&stats.options)
+        .map(|(option, result)| OptionSummary {
+            option_id: option.id,
+            position: option.position,
+            option_text: option.option_text,
+            votes: result.votes,
+            weight: result.weight,
+            percentage: result.share,
+            ci_lower: result.confidence_interval.lower,
+            ci_upper: result.confidence_interval.upper,
+            rank: result.rank,
+        })
+        .collect();
+
+    Ok(PollExport {
+        poll_id: poll.id,
+        title: poll.title,
+        description: poll.description,
+        is_active: poll.is_active,
+        secret_ballot: poll.secret_ballot,
+        weighted: poll.weighted,
+        created_at: poll.created_at,
+        exported_at: chrono::Utc::now(),
+        total_votes: stats.total_votes,
+        options,
+        stats,
+        ballots,
+    })
+}
+
+/**
+Keeps a spreadsheet from evaluating user text as a formula by prefixing a `'` to cells
+that start with `=`, `+`, `-` or `@`. Numbers are left alone, negative ones included.
+*/
+fn escape_formula(cell: &str) -> Cow<'_, str> {
+    if cell.starts_with(['=', '+', '-', '@']) && cell.parse::<f64>().is_err() {
+        Cow::Owned(format!("'{}", cell))
+    } else {
+        Cow::Borrowed(cell)
+    }
+}
+
+fn to_csv<T: Serialize>(rows: &[T]) -> Result<Vec<u8>, Error> {
+    let mut plain = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        plain
+            .serialize(row)
+            .map_err(|e| Error::Export(e.to_string()))?;
+    }
+    let plain = plain
+        .into_inner()
+        .map_err(|e| Error::Export(e.to_string()))?;
+
+    // Rows are written through serde first so the escaping sees the final cell text
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(plain.as_slice());
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in reader.records() {
+        let record = record.map_err(|e| Error::Export(e.to_string()))?;
+        writer
+            .write_record(record.iter().map(|cell| escape_formula(cell).into_owned()))
+            .map_err(|e| Error::Export(e.to_string()))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| Error::Export(e.to_string()))
+}
+
+/**
+Describes the outcome in words for the top of the summary sheet.
+*/
+fn outcome(export: &PollExport) -> String {
+    let text_of = |id: &Uuid| {
+        export
+            .options
+            .iter()
+            .find(|option| option.option_id == *id)
+            .map(|option| option.option_text.as_str())
+            .unwrap_or_default()
+    };
+    match (&export.stats.winner, export.stats.is_tie) {
+        (Some(winner), _) => format!("Winner: {}", text_of(winner)),
+        (None, true) => {
+            let tied: Vec<&str> = export.stats.leaders.iter().map(text_of).collect();
+            format!("Tie: {}", tied.join(", "))
+        }
+        (None, false) => "No votes".to_string(),
+    }
+}
+
+fn write_summary_sheet(sheet: &mut Worksheet, export: &PollExport) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    let percent = Format::new().set_num_format("0.00");
+
+    sheet.set_name("Summary")?;
+    sheet.write_string_with_format(0, 0, "Poll", &bold)?;
+    sheet.write_string(0, 1, &export.title)?;
+    sheet.write_string_with_format(1, 0, "Total votes", &bold)?;
+    sheet.write_number(1, 1, export.total_votes as f64)?;
+    sheet.write_string_with_format(2, 0, "Outcome", &bold)?;
+    sheet.write_string(2, 1, outcome(export))?;
+    if let Some(margin) = export.stats.margin {
+        let label = if export.weighted {
+            "Margin (weight)"
+        } else {
+            "Margin (votes)"
+        };
+        sheet.write_string_with_format(3, 0, label, &bold)?;
+        sheet.write_number(3, 1, margin)?;
+    }
+    let status = match export.stats.outcome.status {
+        OutcomeStatus::Decided => "Decided",
+        OutcomeStatus::FailedQuorum => "Failed quorum",
+        OutcomeStatus::NoMajority => "No majority",
+    };
+    sheet.write_string_with_format(4, 0, "Status", &bold)?;
+    sheet.write_string(4, 1, status)?;
+
+    let header = 6;
+    for (col, title) in [
+        "Position",
+        "Option",
+        "Votes",
+        "Weight",
+        "Percentage",
+        "95% CI low",
+        "95% CI high",
+        "Rank",
+        "Option id",
+    ]
+    .iter()
+    .enumerate()
+    {
+        sheet.write_string_with_format(header, col as u16, *title, &bold)?;
+    }
+    for (i, option) in export.options.iter().enumerate() {
+        let row = header + 1 + i as u32;
+        sheet.write_number(row, 0, option.position)?;
+        sheet.write_string(row, 1, &option.option_text)?;
+        sheet.write_number(row, 2, option.votes as f64)?;
+        if let Some(weight) = option.weight {
+            sheet.write_number(row, 3, weight)?;
+        }
+        sheet.write_number_with_format(row, 4, option.percentage, &percent)?;
+        sheet.write_number_with_format(row, 5, option.ci_lower, &percent)?;
+        sheet.write_number_with_format(row, 6, option.ci_upper, &percent)?;
+        sheet.write_number(row, 7, option.rank as f64)?;
+        sheet.write_string(row, 8, option.option_id.to_string())?;
+    }
+    sheet.set_column_width(0, 16)?;
+    sheet.set_column_width(1, 40)?;
+    sheet.set_column_width(8, 38)?;
+    Ok(())
+}
+
+fn write_ballots_sheet(sheet: &mut Worksheet, ballots: &[Ballot]) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    let timestamp = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+    sheet.set_name("Ballots")?;
+    for (col, title) in [
+        "Voted at (UTC)",
+        "Username",
+        "Option",
+        "Weight",
+        "Voter id",
+        "Option id",
+    ]
+    .iter()
+    .enumerate()
+    {
+        sheet.write_string_with_format(0, col as u16, *title, &bold)?;
+    }
+    for (i, ballot) in ballots.iter().enumerate() {
+        let row = 1 + i as u32;
+        if let Some(voted_at) = ballot.voted_at {
+            sheet.write_datetime_with_format(row, 0, voted_at.naive_utc(), &timestamp)?;
+        }
+        sheet.write_string(row, 1, &ballot.username)?;
+        sheet.write_string(row, 2, &ballot.option_text)?;
+        sheet.write_number(row, 3, ballot.weight)?;
+        sheet.write_string(row, 4, ballot.voter_id.to_string())?;
+        sheet.write_string(row, 5, ballot.option_id.to_string())?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.set_column_width(0, 20)?;
+    sheet.set_column_width(2, 40)?;
+    sheet.set_column_width(4, 38)?;
+    sheet.set_column_width(5, 38)?;
+    Ok(())
+}
+
+/**
+Builds a workbook with a `Summary` sheet of per-option counts and, unless the poll is a
+secret ballot, a `Ballots` sheet listing every vote.
+*/
+fn to_xlsx(export: &PollExport) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+    write_summary_sheet(workbook.add_worksheet(), export)?;
+    if let Some(ballots) = &export.ballots {
+        write_ballots_sheet(workbook.add_worksheet(), ballots)?;
+    }
+    workbook.save_to_buffer()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/export",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id"), ExportQuery),
+    responses(
+        (status = 200, description = "Results as a file download", content(
+            (PollExport = "application/json"),
+            (String = "text/csv"),
+            (Vec<u8> = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        )),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "Ballots requested for a secret ballot poll", body = ErrorBody),
+    )
+)]
+pub async fn export_poll(
+    poll_id: Path<Uuid>,
+    query: Query<ExportQuery>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    let export = load_export(&pool, poll_id).await?;
+
+    let (content_type, extension, body) = match query.format {
+        ExportFormat::Json => (
+            "application/json",
+            "json",
+            serde_json::to_vec(&export).map_err(|e| Error::Export(e.to_string()))?,
+        ),
+        ExportFormat::Csv => {
+            let body = match (query.sheet, &export.ballots) {
+                (ExportSheet::Summary, _) => to_csv(&export.options)?,
+                (ExportSheet::Ballots, Some(ballots)) => to_csv(ballots)?,
+                (ExportSheet::Ballots, None) => {
+                    return Err(Error::Validation(vec![FieldError::new(
+                        "sheet",
+                        "ballots are not exported for secret ballot polls",
+                    )]))
+                }
+            };
+            ("text/csv; charset=utf-8", "csv", body)
+        }
+        ExportFormat::Xlsx => (
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "xlsx",
+            to_xlsx(&export).map_err(|e| Error::Export(e.to_string()))?,
+        ),
+    };
+
+    let filename = match (query.format, query.sheet) {
+        (ExportFormat::Csv, ExportSheet::Ballots) => format!("poll-{}-ballots.csv", poll_id),
+        _ => format!("poll-{}.{}", poll_id, extension),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        option_text: String,
+        votes: i64,
+        weight: f64,
+    }
+
+    fn csv_of(rows: &[Row]) -> String {
+        String::from_utf8(to_csv(rows).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn formulas_are_written_as_text() {
+        let rows: Vec<Row> = [
+            "=HYPERLINK(\"http://x\")",
+            "+1+1",
+            "-2+3",
+            "@SUM(A1)",
+            "Pizza",
+        ]
+        .into_iter()
+        .map(|text| Row {
+            option_text: text.to_string(),
+            votes: 1,
+            weight: 1.0,
+        })
+        .collect();
+        assert_eq!(
+            csv_of(&rows),
+            "option_text,votes,weight\n\
+             \"'=HYPERLINK(\"\"http://x\"\")\",1,1.0\n\
+             '+1+1,1,1.0\n\
+             '-2+3,1,1.0\n\
+             '@SUM(A1),1,1.0\n\
+             Pizza,1,1.0\n"
+        );
+    }
+
+    #[test]
+    fn negative_numbers_stay_numbers() {
+        let rows = [Row {
+            option_text: "-1.5".to_string(),
+            votes: -3,
+            weight: -0.5,
+        }];
+        assert_eq!(csv_of(&rows), "option_text,votes,weight\n-1.5,-3,-0.5\n");
+    }
+}
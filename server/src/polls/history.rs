@@ -0,0 +1,301 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, FieldError, WebResult},
+        validate_session::session_user,
+    },
+    db::{
+        history::{self, VoteBucket},
+        polls,
+    },
+    polls::visibility::can_view_results,
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Path, Query},
+    HttpResponse,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryInterval {
+    Minute,
+    #[default]
+    Hour,
+    Day,
+    Week,
+}
+
+impl HistoryInterval {
+    /// Unit passed to Postgres `date_trunc`
+    fn unit(self) -> &'static str {
+        match self {
+            HistoryInterval::Minute => "minute",
+            HistoryInterval::Hour => "hour",
+            HistoryInterval::Day => "day",
+            HistoryInterval::Week => "week",
+        }
+    }
+
+    /// Distance between the starts of two neighbouring buckets
+    fn step(self) -> TimeDelta {
+        match self {
+            HistoryInterval::Minute => TimeDelta::minutes(1),
+            HistoryInterval::Hour => TimeDelta::hours(1),
+            HistoryInterval::Day => TimeDelta::days(1),
+            HistoryInterval::Week => TimeDelta::weeks(1),
+        }
+    }
+}
+
+/// Most buckets one response may span, quiet ones included
+const MAX_BUCKETS: i64 = 10_000;
+
+#[derive(Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    /// Width of each bucket
+    #[param(inline)]
+    #[serde(default)]
+    interval: HistoryInterval,
+    /// Only return buckets from this time on; totals still include earlier votes
+    from: Option<DateTime<Utc>>,
+    /// Only return activity before this time
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OptionActivity {
+    pub option_id: Uuid,
+    /// Votes cast during the bucket
+    pub casts: i64,
+    /// Votes removed by voters or by a reset during the bucket
+    pub removals: i64,
+    /// Votes the option held at the end of the bucket
+    pub total: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HistoryBucket {
+    pub start: DateTime<Utc>,
+    /// One entry per option, in display order
+    pub options: Vec<OptionActivity>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VoteHistory {
+    pub poll_id: Uuid,
+    pub interval: HistoryInterval,
+    /// Every bucket from the first activity to the last, oldest first; quiet buckets have no casts or removals
+    pub buckets: Vec<HistoryBucket>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/history",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id"), HistoryQuery),
+    responses(
+        (status = 200, description = "Vote activity per option over time", body = VoteHistory),
+        (status = 403, description = "The poll's results_visibility hides results from the caller", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The activity spans too many buckets of this interval", body = ErrorBody),
+    )
+)]
+pub async fn get_poll_history(
+    poll_id: Path<Uuid>,
+    query: Query<HistoryQuery>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    let poll = polls::get_poll(pool.get_ref(), poll_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::PollNotFound,
+            _ => Error::Database(e),
+        })?;
+    // Running totals would reveal the results just as well as the tallies themselves
+    if !can_view_results(pool.get_ref(), &poll, session_user(&session))
+        .await
+        .map_err(Error::Database)?
+    {
+        return Err(Error::ResultsHidden);
+    }
+    let option_ids = polls::get_poll_option_ids(&pool, poll_id)
+        .await
+        .map_err(Error::Database)?;
+
+    let totals: HashMap<Uuid, i64> = match query.from {
+        Some(from) => history::get_vote_totals_before(&pool, poll_id, from)
+            .await
+            .map_err(Error::Database)?
+            .into_iter()
+            .collect(),
+        None => HashMap::new(),
+    };
+    let rows =
+        history::get_vote_buckets(&pool, poll_id, query.interval.unit(), query.from, query.to)
+            .await
+            .map_err(Error::Database)?;
+    if let (Some(first), Some(last)) = (rows.first(), rows.last()) {
+        if (last.bucket - first.bucket).num_seconds() / query.interval.step().num_seconds()
+            >= MAX_BUCKETS
+        {
+            return Err(Error::Validation(vec![FieldError::new(
+                "interval",
+                "spans too many buckets; use a longer interval or a narrower from and to",
+            )]));
+        }
+    }
+    let buckets = fold_buckets(&option_ids, totals, rows, query.interval);
+
+    Ok(HttpResponse::Ok().json(VoteHistory {
+        poll_id,
+        interval: query.interval,
+        buckets,
+    }))
+}
+
+/**
+Turns rows ordered by bucket into one entry per bucket, with a zero activity entry for every quiet
+bucket between the first and the last. Running totals start from `totals`, the votes each option
+held before the first row.
+*/
+fn fold_buckets(
+    option_ids: &[Uuid],
+    mut totals: HashMap<Uuid, i64>,
+    rows: Vec<VoteBucket>,
+    interval: HistoryInterval,
+) -> Vec<HistoryBucket> {
+    let mut buckets: Vec<HistoryBucket> = Vec::new();
+    let mut rows = rows.into_iter().peekable();
+    let Some(mut start) = rows.peek().map(|row| row.bucket) else {
+        return buckets;
+    };
+    while let Some(next) = rows.peek().map(|row| row.bucket) {
+        let mut activity: HashMap<Uuid, (i64, i64)> = HashMap::new();
+        if next == start {
+            while let Some(row) = rows.next_if(|row| row.bucket == start) {
+                activity.insert(row.option_id, (row.casts, row.removals));
+            }
+        }
+
+        let options = option_ids
+            .iter()
+            .map(|&option_id| {
+                let (casts, removals) = activity.get(&option_id).copied().unwrap_or((0, 0));
+                let total = totals.entry(option_id).or_insert(0);
+                *total += casts - removals;
+                OptionActivity {
+                    option_id,
+                    casts,
+                    removals,
+                    total: *total,
+                }
+            })
+            .collect();
+        buckets.push(HistoryBucket { start, options });
+        start += interval.step();
+    }
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 3, hour, 0, 0).unwrap()
+    }
+
+    fn row(hour: u32, option_id: Uuid, casts: i64, removals: i64) -> VoteBucket {
+        VoteBucket {
+            bucket: at(hour),
+            option_id,
+            casts,
+            removals,
+        }
+    }
+
+    /// (casts, removals, total) per option of each bucket
+    fn activity(buckets: &[HistoryBucket]) -> Vec<Vec<(i64, i64, i64)>> {
+        buckets
+            .iter()
+            .map(|bucket| {
+                bucket
+                    .options
+                    .iter()
+                    .map(|o| (o.casts, o.removals, o.total))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn no_activity_means_no_buckets() {
+        let a = Uuid::new_v4();
+        assert!(fold_buckets(&[a], HashMap::new(), vec![], HistoryInterval::Hour).is_empty());
+    }
+
+    #[test]
+    fn rows_of_one_bucket_fold_into_one_entry_in_option_order() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let rows = vec![row(9, c, 1, 0), row(9, a, 3, 1)];
+        let buckets = fold_buckets(&[a, b, c], HashMap::new(), rows, HistoryInterval::Hour);
+
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].start, at(9));
+        let order: Vec<Uuid> = buckets[0].options.iter().map(|o| o.option_id).collect();
+        assert_eq!(order, [a, b, c]);
+        assert_eq!(activity(&buckets), [[(3, 1, 2), (0, 0, 0), (1, 0, 1)]]);
+    }
+
+    #[test]
+    fn totals_run_across_buckets() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let rows = vec![row(9, a, 2, 0), row(10, a, 0, 1), row(10, b, 4, 0)];
+        let buckets = fold_buckets(&[a, b], HashMap::new(), rows, HistoryInterval::Hour);
+
+        assert_eq!(
+            activity(&buckets),
+            [[(2, 0, 2), (0, 0, 0)], [(0, 1, 1), (4, 0, 4)]]
+        );
+    }
+
+    #[test]
+    fn quiet_buckets_are_filled_in() {
+        let a = Uuid::new_v4();
+        let rows = vec![row(9, a, 2, 0), row(12, a, 1, 0)];
+        let buckets = fold_buckets(&[a], HashMap::new(), rows, HistoryInterval::Hour);
+
+        let starts: Vec<DateTime<Utc>> = buckets.iter().map(|b| b.start).collect();
+        assert_eq!(starts, [at(9), at(10), at(11), at(12)]);
+        assert_eq!(
+            activity(&buckets),
+            [[(2, 0, 2)], [(0, 0, 2)], [(0, 0, 2)], [(1, 0, 3)]]
+        );
+    }
+
+    #[test]
+    fn totals_before_the_window_are_carried_in() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let before = HashMap::from([(a, 5), (b, 2)]);
+        let rows = vec![row(9, a, 1, 0), row(11, b, 0, 2)];
+        let buckets = fold_buckets(&[a, b], before, rows, HistoryInterval::Hour);
+
+        assert_eq!(
+            activity(&buckets),
+            [
+                [(1, 0, 6), (0, 0, 2)],
+                [(0, 0, 6), (0, 0, 2)],
+                [(0, 0, 6), (0, 2, 0)]
+            ]
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{
+    auth::error::{Error, ErrorBody, FieldError, WebResult},
+    db::join_codes::{self, JoinCode, JoinTarget},
+    polls::{live::live_session_owner_authorized, manage_polls::poll_valid_owner_authorized},
+};
+use actix_session::Session;
+use actix_web::{
+    http::header,
+    web::{Data, Path, Query},
+    HttpResponse,
+};
+use image::{ImageFormat, Luma};
+use qrcode::{render::svg, QrCode};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{env, io::Cursor};
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+/// Characters of a join code; look-alikes such as `0`/`O` and `1`/`I`/`L` are left out
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 6;
+
+/**
+Where join codes point attendees and how long they outlive their poll. Loaded once at
+startup from the environment.
+*/
+#[derive(Debug, Clone)]
+pub struct JoinConfig {
+    /// Page of the web client that takes a code, e.g. `https://livepool.app/join`
+    pub base_url: String,
+    /// How long a poll's code keeps working after the poll is closed
+    pub grace: chrono::Duration,
+}
+
+impl JoinConfig {
+    pub fn from_env() -> Self {
+        let grace_minutes: i64 = match env::var("JOIN_CODE_GRACE_MINUTES") {
+            Ok(value) => value
+                .parse()
+                .expect("JOIN_CODE_GRACE_MINUTES must be a number"),
+            Err(_) => 24 * 60,
+        };
+        JoinConfig {
+            base_url: env::var("JOIN_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:3000/join".to_string()),
+            grace: chrono::Duration::minutes(grace_minutes),
+        }
+    }
+
+    fn url(&self, code: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), code)
+    }
+
+    /// When the code of a target closed at `closed_at` stops working; never while it is open
+    fn expires_at(
+        &self,
+        closed_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        closed_at.map(|closed_at| closed_at + self.grace)
+    }
+
+    fn is_expired(
+        &self,
+        closed_at: Option<chrono::DateTime<chrono::Utc>>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> bool {
+        self.expires_at(closed_at)
+            .is_some_and(|expires_at| expires_at < now)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinTargetKind {
+    Poll,
+    LiveSession,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JoinCodeResponse {
+    code: String,
+    /// Link to the web client's join page for this code
+    url: String,
+    kind: JoinTargetKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    poll_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<Uuid>,
+    title: String,
+    /// Set once the poll is closed; the code stops working afterwards
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl JoinCodeResponse {
+    fn new(target: JoinTarget, config: &JoinConfig) -> Self {
+        JoinCodeResponse {
+            url: config.url(&target.code),
+            kind: match target.poll_id {
+                Some(_) => JoinTargetKind::Poll,
+                None => JoinTargetKind::LiveSession,
+            },
+            expires_at: config.expires_at(target.closed_at),
+            code: target.code,
+            poll_id: target.poll_id,
+            session_id: target.session_id,
+            title: target.title,
+        }
+    }
+}
+
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/**
+Looks up a code, case-insensitively, and rejects it once it has expired.
+*/
+async fn active_target(pool: &PgPool, code: &str, config: &JoinConfig) -> WebResult<JoinTarget> {
+    let target = join_codes::find_join_code(pool, &code.trim().to_uppercase())
+        .await
+        .map_err(Error::Database)?
+        .ok_or(Error::JoinCodeNotFound)?;
+    if config.is_expired(target.closed_at, chrono::Utc::now()) {
+        return Err(Error::JoinCodeExpired);
+    }
+    Ok(target)
+}
+
+/**
+Returns the code of a poll or live session, generating one on first use. A random code
+that is already taken is simply drawn again.
+*/
+async fn code_for(
+    pool: &PgPool,
+    poll_id: Option<Uuid>,
+    session_id: Option<Uuid>,
+) -> Result<JoinCode, sqlx::Error> {
+    loop {
+        let existing = match (poll_id, session_id) {
+            (Some(poll_id), _) => join_codes::get_poll_join_code(pool, poll_id).await?,
+            (None, Some(session_id)) => join_codes::get_session_join_code(pool, session_id).await?,
+            (None, None) => None,
+        };
+        if let Some(code) = existing {
+            return Ok(code);
+        }
+        let created =
+            join_codes::create_join_code(pool, &generate_code(), poll_id, session_id).await?;
+        if let Some(code) = created {
+            return Ok(code);
+        }
+    }
+}
+
+async fn join_code_response(
+    pool: &PgPool,
+    code: JoinCode,
+    config: &JoinConfig,
+) -> WebResult<HttpResponse> {
+    let target = join_codes::find_join_code(pool, &code.code)
+        .await
+        .map_err(Error::Database)?
+        .ok_or(Error::JoinCodeNotFound)?;
+    Ok(HttpResponse::Ok().json(JoinCodeResponse::new(target, config)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/{poll_id}/join-code",
+    tag = "join",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 200, description = "The poll's join code, generated on first request", body = JoinCodeResponse),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn poll_join_code(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    config: Data<JoinConfig>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    let code = code_for(&pool, Some(poll_id), None)
+        .await
+        .map_err(Error::Database)?;
+    join_code_response(&pool, code, &config).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/live-sessions/{session_id}/join-code",
+    tag = "join",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "The session's join code, generated on first request", body = JoinCodeResponse),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn live_session_join_code(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    config: Data<JoinConfig>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    let code = code_for(&pool, None, Some(live.id))
+        .await
+        .map_err(Error::Database)?;
+    join_code_response(&pool, code, &config).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/join/{code}",
+    tag = "join",
+    params(("code" = String, Path, description = "Join code, in any case")),
+    responses(
+        (status = 200, description = "The poll or live session the code leads to", body = JoinCodeResponse),
+        (status = 404, description = "No such code", body = ErrorBody),
+        (status = 410, description = "The code's poll was closed too long ago", body = ErrorBody),
+    )
+)]
+pub async fn join(
+    code: Path<String>,
+    pool: Data<PgPool>,
+    config: Data<JoinConfig>,
+) -> WebResult<HttpResponse> {
+    let target = active_target(&pool, &code, &config).await?;
+    Ok(HttpResponse::Ok().json(JoinCodeResponse::new(target, &config)))
+}
+
+#[derive(Deserialize, ToSchema, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+fn default_qr_size() -> u32 {
+    512
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct QrQuery {
+    /// `png` (default) or `svg`
+    #[serde(default)]
+    #[param(inline)]
+    format: QrFormat,
+    /// Minimum width and height in pixels, from 64 to 2048
+    #[serde(default = "default_qr_size")]
+    size: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/join/{code}/qr",
+    tag = "join",
+    params(("code" = String, Path, description = "Join code, in any case"), QrQuery),
+    responses(
+        (status = 200, description = "QR code of the code's join URL", content_type = "image/png"),
+        (status = 200, description = "QR code of the code's join URL", content_type = "image/svg+xml"),
+        (status = 404, description = "No such code", body = ErrorBody),
+        (status = 410, description = "The code's poll was closed too long ago", body = ErrorBody),
+        (status = 422, description = "Size out of range", body = ErrorBody),
+    )
+)]
+pub async fn join_qr(
+    code: Path<String>,
+    query: Query<QrQuery>,
+    pool: Data<PgPool>,
+    config: Data<JoinConfig>,
+) -> WebResult<HttpResponse> {
+    if !(64..=2048).contains(&query.size) {
+        return Err(Error::Validation(vec![FieldError::new(
+            "size",
+            "must be between 64 and 2048",
+        )]));
+    }
+    let target = active_target(&pool, &code, &config).await?;
+    let qr = QrCode::new(config.url(&target.code).as_bytes())
+        .map_err(|e| Error::Export(e.to_string()))?;
+
+    match query.format {
+        QrFormat::Png => {
+            let image = qr
+                .render::<Luma<u8>>()
+                .min_dimensions(query.size, query.size)
+                .build();
+            let mut png = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(|e| Error::Export(e.to_string()))?;
+            Ok(HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, "image/png"))
+                .body(png))
+        }
+        QrFormat::Svg => {
+            let image = qr
+                .render::<svg::Color>()
+                .min_dimensions(query.size, query.size)
+                .build();
+            Ok(HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, "image/svg+xml"))
+                .body(image))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn config() -> JoinConfig {
+        JoinConfig {
+            base_url: "https://livepool.app/join/".to_string(),
+            grace: chrono::Duration::minutes(30),
+        }
+    }
+
+    #[test]
+    fn open_targets_never_expire() {
+        let config = config();
+        assert_eq!(config.expires_at(None), None);
+        assert!(!config.is_expired(None, Utc::now()));
+    }
+
+    #[test]
+    fn codes_work_until_the_grace_period_ends() {
+        let config = config();
+        let closed_at = Utc.with_ymd_and_hms(2025, 2, 19, 12, 0, 0).unwrap();
+        let expires_at = Utc.with_ymd_and_hms(2025, 2, 19, 12, 30, 0).unwrap();
+
+        assert_eq!(config.expires_at(Some(closed_at)), Some(expires_at));
+        assert!(!config.is_expired(Some(closed_at), closed_at));
+        assert!(!config.is_expired(Some(closed_at), expires_at));
+        assert!(config.is_expired(Some(closed_at), expires_at + chrono::Duration::seconds(1)));
+    }
+
+    #[test]
+    fn without_grace_codes_expire_right_after_closing() {
+        let config = JoinConfig {
+            grace: chrono::Duration::zero(),
+            ..config()
+        };
+        let closed_at = Utc::now();
+        assert!(!config.is_expired(Some(closed_at), closed_at));
+        assert!(config.is_expired(
+            Some(closed_at),
+            closed_at + chrono::Duration::milliseconds(1)
+        ));
+    }
+
+    #[test]
+    fn urls_join_the_base_and_the_code() {
+        assert_eq!(config().url("ABC234"), "https://livepool.app/join/ABC234");
+    }
+
+    #[test]
+    fn codes_use_only_the_unambiguous_alphabet() {
+        for _ in 0..100 {
+            let code = generate_code();
+            assert_eq!(code.len(), CODE_LENGTH);
+            assert!(code.bytes().all(|c| CODE_ALPHABET.contains(&c)), "{}", code);
+        }
+    }
+}
@@ -0,0 +1,564 @@
+use crate::{
+    api::request_id,
+    auth::{
+        error::{Error, ErrorBody, FieldError, WebResult},
+        validate_session::{session_user, validate_session},
+    },
+    db::{
+        live::{self, LiveSession},
+        polls,
+        webhooks::WebhookEvent,
+    },
+    polls::{
+        manage_polls::{poll_data, sse_error, PollData},
+        quiz,
+        reactions::{reaction_frames, ReactionConfig},
+        validation::{PollLimits, Validator},
+        webhooks,
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{self, Data, Json, Path},
+    HttpResponse, Responder,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::time::Duration;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateLiveSessionRequest {
+    title: String,
+    /// Polls of the caller, presented in this order
+    poll_ids: Vec<Uuid>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SetLivePollsRequest {
+    /// Polls of the caller, presented in this order
+    poll_ids: Vec<Uuid>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SetCurrentPollRequest {
+    /// One of the session's polls
+    poll_id: Uuid,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RevealRequest {
+    revealed: bool,
+}
+
+/**
+What the audience of a live session is shown
+*/
+#[derive(Serialize, ToSchema)]
+pub struct LiveState {
+    id: Uuid,
+    user_id: Uuid,
+    title: String,
+    poll_ids: Vec<Uuid>,
+    /// Index of the live poll in `poll_ids`, absent until the presenter starts
+    position: Option<usize>,
+    results_revealed: bool,
+    /// The live poll; its tallies, and a quiz's correct answers, are left out until the
+    /// presenter reveals them
+    poll: Option<PollData>,
+}
+
+/**
+Loads the state of the session as `viewer` sees it. The presenter always sees results.
+*/
+async fn live_state(
+    pool: &PgPool,
+    live: LiveSession,
+    viewer: Option<Uuid>,
+) -> Result<LiveState, sqlx::Error> {
+    let poll_ids = live::get_live_poll_ids(pool, live.id).await?;
+    let position = live
+        .current_poll_id
+        .and_then(|current| poll_ids.iter().position(|&poll_id| poll_id == current));
+    let poll = match live.current_poll_id {
+        Some(poll_id) => {
+            let poll = polls::get_poll(pool, poll_id).await?;
+            let answers_visible = quiz::answers_visible(&poll, viewer);
+            // Revealing overrides the poll's own results_visibility
+            let mut data = poll_data(&mut *pool.acquire().await?, poll, Some(live.user_id)).await?;
+            if !answers_visible {
+                data = data.hide_answers();
+            }
+            if live.results_revealed || viewer == Some(live.user_id) {
+                Some(data)
+            } else {
+                Some(data.hide_results())
+            }
+        }
+        None => None,
+    };
+    Ok(LiveState {
+        id: live.id,
+        user_id: live.user_id,
+        title: live.title,
+        poll_ids,
+        position,
+        results_revealed: live.results_revealed,
+        poll,
+    })
+}
+
+async fn find_live_session(pool: &PgPool, session_id: Uuid) -> WebResult<LiveSession> {
+    live::get_live_session(pool, session_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::LiveSessionNotFound,
+            _ => Error::Database(e),
+        })
+}
+
+pub async fn live_session_owner_authorized(
+    pool: &PgPool,
+    session_id: Uuid,
+    session: &Session,
+) -> WebResult<LiveSession> {
+    let user_id = validate_session(session)?;
+    let live = find_live_session(pool, session_id).await?;
+    if live.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    Ok(live)
+}
+
+/**
+Checks that `poll_ids` lists polls of `user_id`, each once.
+*/
+async fn check_poll_ids(
+    pool: &PgPool,
+    user_id: Uuid,
+    poll_ids: &[Uuid],
+    limits: &PollLimits,
+) -> WebResult<()> {
+    let mut v = Validator::new();
+    if poll_ids.len() > limits.max_questions {
+        v.add(
+            "poll_ids",
+            format!("must have at most {} polls", limits.max_questions),
+        );
+    }
+    for (i, poll_id) in poll_ids.iter().enumerate() {
+        if let Some(first) = poll_ids[..i].iter().position(|id| id == poll_id) {
+            v.add(
+                format!("poll_ids[{}]", i),
+                format!("duplicates poll_ids[{}]", first),
+            );
+        }
+    }
+    v.finish()?;
+    let owned = live::owns_polls(pool, user_id, poll_ids)
+        .await
+        .map_err(Error::Database)?;
+    if !owned {
+        return Err(Error::Validation(vec![FieldError::new(
+            "poll_ids",
+            "must only contain polls of yours",
+        )]));
+    }
+    Ok(())
+}
+
+/**
+Presenter's view of the session after a control action
+*/
+async fn presenter_state(pool: &PgPool, session_id: Uuid) -> WebResult<HttpResponse> {
+    let live = find_live_session(pool, session_id).await?;
+    let user_id = live.user_id;
+    let state = live_state(pool, live, Some(user_id))
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(state))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/live-sessions",
+    tag = "live",
+    request_body = CreateLiveSessionRequest,
+    responses(
+        (status = 201, description = "The session, with no poll live yet", body = LiveState),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 422, description = "Title or polls failed validation", body = ErrorBody),
+    )
+)]
+pub async fn create_live_session(
+    req: Json<CreateLiveSessionRequest>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let mut v = Validator::new();
+    v.text("title", &req.title, limits.max_title_len);
+    v.finish()?;
+    check_poll_ids(&pool, user_id, &req.poll_ids, &limits).await?;
+
+    let live = live::create_live_session(
+        &pool,
+        Uuid::new_v4(),
+        user_id,
+        req.title.trim(),
+        &req.poll_ids,
+    )
+    .await
+    .map_err(Error::Database)?;
+    let state = live_state(&pool, live, Some(user_id))
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Created().json(state))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live-sessions",
+    tag = "live",
+    responses(
+        (status = 200, description = "The caller's live sessions, newest first", body = [LiveSession]),
+        (status = 401, description = "No active session", body = ErrorBody),
+    )
+)]
+pub async fn list_live_sessions(session: Session, pool: Data<PgPool>) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let sessions = live::get_user_live_sessions(&pool, user_id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live-sessions/{session_id}",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "The session as the caller sees it", body = LiveState),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn get_live_session(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let live = find_live_session(&pool, session_id.into_inner()).await?;
+    let state = live_state(&pool, live, session_user(&session))
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(state))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/live-sessions/{session_id}/polls",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    request_body = SetLivePollsRequest,
+    responses(
+        (status = 200, description = "The session with its new polls; a live poll left out \
+            stops being live", body = LiveState),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+        (status = 422, description = "The polls failed validation", body = ErrorBody),
+    )
+)]
+pub async fn set_live_polls(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<SetLivePollsRequest>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    check_poll_ids(&pool, live.user_id, &req.poll_ids, &limits).await?;
+    live::set_live_polls(&pool, live.id, &req.poll_ids)
+        .await
+        .map_err(Error::Database)?;
+    presenter_state(&pool, live.id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/live-sessions/{session_id}/next",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "The next poll is live, with its results hidden", body = LiveState),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+        (status = 422, description = "The last poll is already live", body = ErrorBody),
+    )
+)]
+pub async fn next_poll(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    let poll_ids = live::get_live_poll_ids(&pool, live.id)
+        .await
+        .map_err(Error::Database)?;
+    let next = match live
+        .current_poll_id
+        .and_then(|current| poll_ids.iter().position(|&poll_id| poll_id == current))
+    {
+        Some(position) => poll_ids.get(position + 1),
+        None => poll_ids.first(),
+    };
+    let Some(&next) = next else {
+        return Err(Error::Validation(vec![FieldError::new(
+            "poll_ids",
+            "has no poll after the live one",
+        )]));
+    };
+    live::set_current_poll(&pool, live.id, next)
+        .await
+        .map_err(Error::Database)?;
+    presenter_state(&pool, live.id).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/live-sessions/{session_id}/current",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    request_body = SetCurrentPollRequest,
+    responses(
+        (status = 200, description = "The poll is live, with its results hidden", body = LiveState),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+        (status = 422, description = "The poll is not part of the session", body = ErrorBody),
+    )
+)]
+pub async fn set_current_poll(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<SetCurrentPollRequest>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    let poll_ids = live::get_live_poll_ids(&pool, live.id)
+        .await
+        .map_err(Error::Database)?;
+    if !poll_ids.contains(&req.poll_id) {
+        let mut v = Validator::new();
+        v.add("poll_id", "is not part of this session");
+        v.finish()?;
+    }
+    live::set_current_poll(&pool, live.id, req.poll_id)
+        .await
+        .map_err(Error::Database)?;
+    presenter_state(&pool, live.id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/live-sessions/{session_id}/open",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "The live poll accepts votes", body = LiveState),
+        (status = 400, description = "No poll is live", body = ErrorBody),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn open_current_poll(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    let poll_id = live.current_poll_id.ok_or(Error::NoLivePoll)?;
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let reopened = polls::reopen_poll(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    if reopened {
+        webhooks::notify(&mut tx, poll_id, WebhookEvent::Reopened, None)
+            .await
+            .map_err(Error::Database)?;
+    }
+    tx.commit().await.map_err(Error::Database)?;
+    presenter_state(&pool, live.id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/live-sessions/{session_id}/close",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "The live poll stops accepting votes", body = LiveState),
+        (status = 400, description = "No poll is live", body = ErrorBody),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn close_current_poll(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    let poll_id = live.current_poll_id.ok_or(Error::NoLivePoll)?;
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let closed = polls::close_poll(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    if closed {
+        webhooks::notify(&mut tx, poll_id, WebhookEvent::Closed, None)
+            .await
+            .map_err(Error::Database)?;
+    }
+    tx.commit().await.map_err(Error::Database)?;
+    presenter_state(&pool, live.id).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/live-sessions/{session_id}/reveal",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    request_body = RevealRequest,
+    responses(
+        (status = 200, description = "The audience now sees, or no longer sees, the live poll's \
+            results. Revealing a quiz poll also reveals its correct answers", body = LiveState),
+        (status = 400, description = "No poll is live", body = ErrorBody),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn reveal_results(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<RevealRequest>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    let poll_id = live.current_poll_id.ok_or(Error::NoLivePoll)?;
+    live::set_results_revealed(&pool, live.id, req.revealed)
+        .await
+        .map_err(Error::Database)?;
+    if req.revealed {
+        let poll = polls::get_poll(pool.get_ref(), poll_id)
+            .await
+            .map_err(Error::Database)?;
+        if poll.quiz {
+            polls::set_quiz_revealed(&pool, poll_id, true)
+                .await
+                .map_err(Error::Database)?;
+        }
+    }
+    presenter_state(&pool, live.id).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/live-sessions/{session_id}",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 204, description = "Session deleted; its polls are kept"),
+        (status = 401, description = "Caller does not own the session", body = ErrorBody),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn delete_live_session(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let live = live_session_owner_authorized(&pool, session_id.into_inner(), &session).await?;
+    live::delete_live_session(&pool, live.id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live-sessions/{session_id}/stream",
+    tag = "live",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "Server-sent events; a `data:` frame carrying the LiveState is \
+            sent on connect and whenever the live poll, its status, its results or the reveal \
+            changes. The presenter also gets a `reactions` event carrying a ReactionWindow after \
+            every window in which the audience reacted to the live poll. Failures arrive as an \
+            `error` event carrying an ErrorBody",
+            content_type = "text/event-stream", body = LiveState),
+    )
+)]
+pub async fn stream_live_session(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    reaction_config: Data<ReactionConfig>,
+) -> impl Responder {
+    let session_id = session_id.into_inner();
+    let viewer = session_user(&session);
+    let request_id = request_id::current();
+    let mut interval = tokio::time::interval(Duration::from_secs(2));
+    let mut reactions_since = reaction_config.window_start(chrono::Utc::now());
+
+    let stream = async_stream::stream! {
+        let mut last: Option<String> = None;
+        loop {
+            interval.tick().await;
+
+            let live = match live::get_live_session(&pool, session_id).await {
+                Ok(live) => live,
+                Err(sqlx::Error::RowNotFound) => {
+                    yield Result::<web::Bytes, Box<dyn std::error::Error>>::Ok(sse_error(Error::LiveSessionNotFound, request_id));
+                    return;
+                }
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
+                    return;
+                }
+            };
+            // Only the presenter hears the audience's reactions
+            let presented = live.current_poll_id.filter(|_| viewer == Some(live.user_id));
+            if let Some(poll_id) = presented {
+                match reaction_frames(&pool, poll_id, &reaction_config, &mut reactions_since).await {
+                    Ok(frames) => {
+                        for frame in frames {
+                            yield Ok(frame);
+                        }
+                    }
+                    Err(e) => {
+                        yield Ok(sse_error(Error::Database(e), request_id));
+                        return;
+                    }
+                }
+            }
+            let state = match live_state(&pool, live, viewer).await {
+                Ok(state) => state,
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
+                    return;
+                }
+            };
+            // Audiences only hear about changes
+            let data = serde_json::to_string(&state).unwrap();
+            if last.as_ref() == Some(&data) {
+                continue;
+            }
+            yield Ok(web::Bytes::from(format!("data: {}\n\n", data)));
+            last = Some(data);
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(stream)
+}
@@ -1,9 +1,26 @@
 use crate::{
+    api::request_id,
     auth::{
-        error::{Error, WebResult},
-        validate_session::validate_session,
+        error::{Error, ErrorBody, WebResult},
+        validate_session::{session_user, validate_session},
+    },
+    db::{
+        answers, comments, polls, surveys,
+        webhooks::WebhookEvent,
+        weights::{self, Electorate},
+    },
+    polls::{
+        comments::comment_frames,
+        options::{double_option, option_write_error, PollOptionInput},
+        questions::{self, AnswerSummary, Question},
+        quiz::{self, QuizInfo, QuizInput},
+        reactions::{reaction_frames, ReactionConfig},
+        recurrence::Recurrence,
+        results::{self, ResultStats},
+        validation::{PollLimits, Validator},
+        visibility::can_view_results,
+        webhooks::{self, VoteInfo},
     },
-    db::polls,
 };
 use actix_session::Session;
 use actix_web::HttpResponse;
@@ -12,45 +29,203 @@ use actix_web::{
     Responder,
 };
 use async_stream;
-use futures_util::stream::{Stream, StreamExt};
-use log::{info, warn};
-use serde::{de, Deserialize, Serialize};
-use serde_json::{json, Value};
-use sqlx::PgPool;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
 use std::time::Duration;
+use utoipa::{IntoParams, ToSchema};
 use webauthn_rs::prelude::*;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreatePollRequest {
-    poll_name: String,
-    poll_description: String,
-    poll_options: Vec<String>,
+    pub poll_name: String,
+    pub poll_description: String,
+    /// Options to vote on; must be empty for questions other than `choice`
+    #[serde(default)]
+    pub poll_options: Vec<PollOptionInput>,
+    /// What the poll asks for, a choice among its options unless set
+    #[serde(default, skip_serializing_if = "Question::is_choice")]
+    pub question: Question,
+    /// Hide who voted for what, including from the owner's exports
+    #[serde(default)]
+    pub secret_ballot: bool,
+    #[serde(default)]
+    pub results_visibility: polls::ResultsVisibility,
+    /// `daily`, `weekly` or a cron expression; the next instance is created automatically
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<String>,
+    /// Decide by the summed weight of each option's voters instead of headcount
+    #[serde(default)]
+    pub weighted: bool,
+    /// Weight of voters without a role or personal weight, 1 unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_weight: Option<f64>,
+    /// Only participants may vote; they form the electorate `quorum_percent` refers to
+    #[serde(default)]
+    pub participants_only: bool,
+    /// Minimum number of voters for the result to count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quorum_votes: Option<i32>,
+    /// Minimum percentage of participants who have to vote; needs `participants_only`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quorum_percent: Option<f64>,
+    #[serde(default)]
+    pub pass_threshold: polls::PassThreshold,
+    /// Close the poll as soon as a vote locks in its outcome
+    #[serde(default)]
+    pub auto_close: bool,
+    /// Score votes against correct options, turning the poll into a quiz question
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiz: Option<QuizInput>,
+}
+
+impl CreatePollRequest {
+    pub fn rules(&self) -> polls::OutcomeRules {
+        polls::OutcomeRules {
+            participants_only: self.participants_only,
+            quorum_votes: self.quorum_votes,
+            quorum_percent: self.quorum_percent,
+            pass_threshold: self.pass_threshold,
+            auto_close: self.auto_close,
+        }
+    }
+
+    pub fn validate(&self, limits: &PollLimits) -> Result<(), Error> {
+        let mut v = Validator::new();
+        v.text("poll_name", &self.poll_name, limits.max_title_len);
+        v.optional_text(
+            "poll_description",
+            &self.poll_description,
+            limits.max_description_len,
+        );
+        self.question
+            .validate_options(&mut v, "poll_options", &self.poll_options, limits);
+        self.question.validate(&mut v, "question", limits);
+        if let Some(recurrence) = &self.recurrence {
+            v.recurrence("recurrence", recurrence);
+        }
+        if let Some(default_weight) = self.default_weight {
+            v.weight("default_weight", default_weight);
+        }
+        v.outcome_rules(&self.rules(), self.question.settings().kind);
+        if let Some(quiz) = &self.quiz {
+            quiz.validate(
+                &mut v,
+                "quiz",
+                &self.question,
+                self.poll_options.len(),
+                self.secret_ballot,
+            );
+        }
+        v.finish()
+    }
+}
+
+/**
+When a new recurring series with an already validated `recurrence` first rolls over.
+*/
+fn first_run(recurrence: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let now = chrono::Utc::now();
+    recurrence.parse::<Recurrence>().ok()?.next_run(now, now)
+}
+
+/**
+Validates `req` and creates the poll it describes, owned by `user_id`, queueing its
+`poll_created` event with it. Shared by every route that ends up creating a poll.
+*/
+pub async fn insert_poll(
+    pool: &PgPool,
+    user_id: Uuid,
+    req: &CreatePollRequest,
+    limits: &PollLimits,
+) -> WebResult<PollData> {
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let poll = create_poll_on(&mut tx, user_id, req, limits).await?;
+    webhooks::notify(&mut tx, poll.id(), WebhookEvent::Created, None)
+        .await
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
+    Ok(poll)
+}
+
+/**
+Like [insert_poll], but on `conn` and without queueing the `poll_created` event, for
+callers that finish setting the poll up first.
+*/
+pub async fn create_poll_on(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    req: &CreatePollRequest,
+    limits: &PollLimits,
+) -> WebResult<PollData> {
+    req.validate(limits)?;
+    let poll_name = req.poll_name.trim().to_string();
+    let poll_description = req.poll_description.trim().to_string();
+    let mut poll_options: Vec<polls::NewPollOption> = req
+        .poll_options
+        .iter()
+        .cloned()
+        .map(PollOptionInput::into_new_option)
+        .collect();
+    if let Some(quiz) = &req.quiz {
+        for &i in &quiz.correct_options {
+            poll_options[i].is_correct = true;
+        }
+    }
+
+    let recurrence = req.recurrence.as_deref().map(str::trim);
+    let poll_id = Uuid::new_v4();
+    let (poll, options) = polls::create_poll_with_options(
+        &mut *conn,
+        poll_id,
+        user_id,
+        &poll_name,
+        &poll_description,
+        &polls::PollSettings {
+            secret_ballot: req.secret_ballot,
+            results_visibility: req.results_visibility,
+            recurrence: recurrence.map(str::to_string),
+            next_run_at: recurrence.and_then(first_run),
+            weighted: req.weighted,
+            default_weight: req.default_weight,
+            rules: req.rules(),
+            question: req.question.settings(),
+            quiz: req.quiz.as_ref().map(QuizInput::settings),
+            ..Default::default()
+        },
+        &poll_options,
+    )
+    .await
+    .map_err(|e| option_write_error(e, "poll_options"))?;
+    let electorate = results::electorate(&mut *conn, &poll)
+        .await
+        .map_err(Error::Database)?;
+    let answers = questions::summarize(conn, &poll)
+        .await
+        .map_err(Error::Database)?;
+    Ok(PollData::new(poll, options, electorate, answers))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls",
+    tag = "polls",
+    request_body = CreatePollRequest,
+    responses(
+        (status = 201, description = "The created poll with its options", body = PollData),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 422, description = "Title, description or options failed validation", body = ErrorBody),
+    )
+)]
 pub async fn create_poll(
     req: Json<CreatePollRequest>,
     session: Session,
     pool: Data<PgPool>,
+    limits: Data<PollLimits>,
 ) -> WebResult<HttpResponse> {
     let user_id = validate_session(&session)?;
-    let poll_name = req.poll_name.clone();
-    let poll_description = req.poll_description.clone();
-    let poll_options = req.poll_options.clone();
-
-    if poll_options.len() < 2 {
-        return Err(Error::InvalidPollOptions);
-    }
-    let poll_id = Uuid::new_v4();
-    let _ = polls::create_poll(&pool, poll_id, user_id, &poll_name, &poll_description)
-        .await
-        .map_err(|_| Error::DatabaseError);
-    for option in poll_options {
-        let option_id = Uuid::new_v4();
-        let _ = polls::create_option(&pool, option_id, poll_id, option)
-            .await
-            .map_err(Error::DatabaseError);
-    }
-    Ok(HttpResponse::Created().json(poll_id))
+    let poll = insert_poll(&pool, user_id, &req, &limits).await?;
+    Ok(HttpResponse::Created().json(poll))
 }
 
 // #[derive(Deserialize)]
@@ -83,7 +258,7 @@ pub async fn create_poll(
 //         .await
 //         .map_err(|e| match e {
 //             Error::PollNotFound => Error::PollNotFound,
-//             _ => Error::DatabaseError(e),
+//             _ => Error::Database(e),
 //         })?;
 //     // Update poll title and description if provided
 //     if let Some(name) = &req.poll_name {
@@ -96,7 +271,7 @@ pub async fn create_poll(
 
 //     polls::update_poll(&pool, poll_id, &poll.title, &poll.description)
 //         .await
-//         .map_err(|_| Error::DatabaseError)?;
+//         .map_err(|_| Error::Database)?;
 
 //     // Update poll options if provided
 //     if let Some(options) = &req.poll_options {
@@ -111,7 +286,7 @@ pub async fn create_poll(
 //         )
 //         .fetch_all(&pool)
 //         .await
-//         .map_err(|_| Error::DatabaseError)?;
+//         .map_err(|_| Error::Database)?;
 
 //         let new_options_set: HashSet<_> = options.iter().cloned().collect();
 //         let existing_options_set: HashSet<_> = existing_options.iter().map(|(_, text)| text.clone()).collect();
@@ -134,7 +309,7 @@ pub async fn create_poll(
 //         }
 
 //         // Execute delete and insert operations in a transaction
-//         let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+//         let mut tx = pool.begin().await.map_err(|_| Error::Database)?;
 
 //         for id in delete_ids {
 //             sqlx::query!(
@@ -143,7 +318,7 @@ pub async fn create_poll(
 //             )
 //             .execute(&mut tx)
 //             .await
-//             .map_err(|_| Error::DatabaseError)?;
+//             .map_err(|_| Error::Database)?;
 //         }
 
 //         for (option_id, option) in insert_options {
@@ -155,10 +330,10 @@ pub async fn create_poll(
 //             )
 //             .execute(&mut tx)
 //             .await
-//             .map_err(|_| Error::DatabaseError)?;
+//             .map_err(|_| Error::Database)?;
 //         }
 
-//         tx.commit().await.map_err(|_| Error::DatabaseError)?;
+//         tx.commit().await.map_err(|_| Error::Database)?;
 //     }
 
 //     // Close the poll if requested
@@ -172,7 +347,7 @@ pub async fn create_poll(
 //             )
 //             .execute(&pool)
 //             .await
-//             .map_err(|_| Error::DatabaseError)?;
+//             .map_err(|_| Error::Database)?;
 //         }
 //     }
 
@@ -187,13 +362,24 @@ pub async fn create_poll(
 //             )
 //             .execute(&pool)
 //             .await
-//             .map_err(|_| Error::DatabaseError)?;
+//             .map_err(|_| Error::Database)?;
 //         }
 //     }
 
 //     Ok(HttpResponse::Ok().finish())
 // }
 
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 204, description = "Poll deleted"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
 pub async fn delete_poll(
     poll_id: Path<Uuid>,
     session: Session,
@@ -203,28 +389,207 @@ pub async fn delete_poll(
 
     poll_valid_owner_authorized(poll_id, session, &pool).await?;
 
-    // Delete poll options and votes first (cascade delete)
-    let _ = polls::delete_votes(&pool, poll_id)
+    // Delete votes and poll options first, then the poll itself, all or nothing
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    polls::delete_votes(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    polls::delete_poll_options(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    polls::delete_poll(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/**
+Fields left out are unchanged; `recurrence: null` stops a recurring series.
+*/
+#[derive(Deserialize, ToSchema)]
+pub struct UpdatePollRequest {
+    is_active: Option<bool>,
+    results_visibility: Option<polls::ResultsVisibility>,
+    #[serde(default, deserialize_with = "double_option")]
+    recurrence: Option<Option<String>>,
+    /// Applies to votes cast from now on; earlier votes keep their weight
+    weighted: Option<bool>,
+    default_weight: Option<f64>,
+    participants_only: Option<bool>,
+    #[serde(default, deserialize_with = "double_option")]
+    quorum_votes: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "double_option")]
+    quorum_percent: Option<Option<f64>>,
+    pass_threshold: Option<polls::PassThreshold>,
+    auto_close: Option<bool>,
+}
+
+impl UpdatePollRequest {
+    /// `rules` with the changes from this request applied, or `None` when it changes none
+    fn apply_rules(&self, mut rules: polls::OutcomeRules) -> Option<polls::OutcomeRules> {
+        if self.participants_only.is_none()
+            && self.quorum_votes.is_none()
+            && self.quorum_percent.is_none()
+            && self.pass_threshold.is_none()
+            && self.auto_close.is_none()
+        {
+            return None;
+        }
+        if let Some(participants_only) = self.participants_only {
+            rules.participants_only = participants_only;
+        }
+        if let Some(quorum_votes) = self.quorum_votes {
+            rules.quorum_votes = quorum_votes;
+        }
+        if let Some(quorum_percent) = self.quorum_percent {
+            rules.quorum_percent = quorum_percent;
+        }
+        if let Some(pass_threshold) = self.pass_threshold {
+            rules.pass_threshold = pass_threshold;
+        }
+        if let Some(auto_close) = self.auto_close {
+            rules.auto_close = auto_close;
+        }
+        Some(rules)
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/polls/{poll_id}",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = UpdatePollRequest,
+    responses(
+        (status = 204, description = "Poll updated"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "Recurrence rule, default weight or quorum is not valid", body = ErrorBody),
+    )
+)]
+pub async fn update_poll(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<UpdatePollRequest>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    // Check if the poll exists and if the user is the owner
+    poll_valid_owner_authorized(poll_id, session, &pool)
         .await
-        .map_err(|_| Error::DatabaseError);
+        .map_err(|e| match e {
+            Error::PollNotFound => Error::PollNotFound,
+            _ => Error::Unauthorized,
+        })?;
 
-    let _ = polls::delete_poll_options(&pool, poll_id)
+    // Every field is checked before anything is written, and the changes are then made
+    // together, so a rejected request leaves the poll as it was
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let poll = polls::get_poll(&mut *tx, poll_id)
         .await
-        .map_err(|_| Error::DatabaseError);
+        .map_err(Error::Database)?;
+    let rules = req.apply_rules(polls::OutcomeRules::from(&poll));
+    let recurrence = req
+        .recurrence
+        .as_ref()
+        .map(|recurrence| recurrence.as_deref().map(str::trim));
+
+    let mut v = Validator::new();
+    if let Some(default_weight) = req.default_weight {
+        v.weight("default_weight", default_weight);
+    }
+    if let Some(rules) = &rules {
+        v.outcome_rules(rules, poll.kind);
+    }
+    if let Some(Some(recurrence)) = recurrence {
+        v.recurrence("recurrence", recurrence);
+    }
+    v.finish()?;
+
+    match req.is_active {
+        Some(false) => {
+            let closed = polls::close_poll(&mut *tx, poll_id)
+                .await
+                .map_err(Error::Database)?;
+            if closed {
+                webhooks::notify(&mut tx, poll_id, WebhookEvent::Closed, None)
+                    .await
+                    .map_err(Error::Database)?;
+            }
+        }
+        Some(true) => {
+            let reopened = polls::reopen_poll(&mut *tx, poll_id)
+                .await
+                .map_err(Error::Database)?;
+            if reopened {
+                webhooks::notify(&mut tx, poll_id, WebhookEvent::Reopened, None)
+                    .await
+                    .map_err(Error::Database)?;
+            }
+        }
+        None => {}
+    }
+
+    if let Some(rules) = &rules {
+        polls::set_outcome_rules(&mut *tx, poll_id, rules)
+            .await
+            .map_err(Error::Database)?;
+    }
+
+    if req.weighted.is_some() || req.default_weight.is_some() {
+        polls::set_weighting(&mut *tx, poll_id, req.weighted, req.default_weight)
+            .await
+            .map_err(Error::Database)?;
+    }
 
-    // Delete the poll itself
-    let _ = polls::delete_poll(&pool, poll_id)
+    if let Some(visibility) = req.results_visibility {
+        polls::set_results_visibility(&mut *tx, poll_id, visibility)
+            .await
+            .map_err(Error::Database)?;
+    }
+
+    if let Some(recurrence) = recurrence {
+        polls::set_recurrence(
+            &mut *tx,
+            poll_id,
+            recurrence,
+            recurrence.and_then(first_run),
+        )
         .await
-        .map_err(|_| Error::DatabaseError);
+        .map_err(Error::Database)?;
+    }
+    tx.commit().await.map_err(Error::Database)?;
+
+    // Turning auto_close on may find the outcome already settled
+    if rules.is_some_and(|rules| rules.auto_close) && req.is_active.is_none() {
+        close_if_locked(&pool, poll_id).await?;
+    }
 
     Ok(HttpResponse::NoContent().finish())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct VoteRequest {
     option_id: Uuid,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/{poll_id}/votes",
+    tag = "votes",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = VoteRequest,
+    responses(
+        (status = 204, description = "Vote recorded"),
+        (status = 400, description = "Poll is closed, its quiz answers are revealed, or the user already voted", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 403, description = "The poll is limited to participants and the caller is not one", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The option does not belong to this poll", body = ErrorBody),
+    )
+)]
 pub async fn vote_poll(
     poll_id: Path<Uuid>,
     session: Session,
@@ -236,23 +601,45 @@ pub async fn vote_poll(
     let poll_id = poll_id.into_inner();
     println!("user_id while voting: {:?}", user_id);
 
-    // Check if poll is active
-    let poll = polls::is_poll_active(&pool, poll_id)
+    // The poll's row stays locked until the vote is recorded, so concurrent ballots of
+    // the same user cannot both pass the check for an existing vote
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let poll = polls::lock_poll(&mut *tx, poll_id)
         .await
         .map_err(|e| match e {
             sqlx::Error::RowNotFound => Error::PollNotFound,
-            _ => Error::DatabaseError(e),
+            _ => Error::Database(e),
         })?;
 
-    if !poll {
+    if !poll.is_active || quiz::ballots_locked(&poll) {
         return Err(Error::PollClosed);
     }
+    not_survey_question(&pool, poll_id).await?;
 
-    let option_id = req.option_id; // for simplicity, just an example, replace with the actual ID passed
-                                   // Check if user has already voted
-    let existing_vote = polls::has_user_voted(&pool, option_id, user_id, poll_id)
+    if poll.participants_only
+        && !weights::is_participant(&pool, poll_id, user_id)
+            .await
+            .map_err(Error::Database)?
+    {
+        return Err(Error::NotEligible);
+    }
+
+    let option_id = req.option_id;
+    // The option must belong to the poll in the URL, otherwise a vote on an open poll
+    // could be used to increment an option of any other poll
+    let in_poll = polls::is_option_in_poll(pool.get_ref(), option_id, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    if !in_poll {
+        let mut v = Validator::new();
+        v.add("option_id", "does not belong to this poll");
+        v.finish()?;
+    }
+
+    // Check if user has already voted
+    let existing_vote = polls::has_user_voted(&mut *tx, user_id, poll_id)
         .await
-        .map_err(Error::DatabaseError)?;
+        .map_err(Error::Database)?;
 
     if existing_vote {
         return Err(Error::AlreadyVoted);
@@ -260,63 +647,229 @@ pub async fn vote_poll(
 
     // Get the selected option (you should pass option_id in the request body)
     let vote_id = Uuid::new_v4();
-    // Insert the vote into the database
-    let _ = polls::vote(&pool, option_id, user_id, vote_id)
+    // Insert the vote into the database, weighed by the voter's role in the poll
+    let weight = polls::vote(&mut *tx, option_id, user_id, vote_id)
+        .await
+        .map_err(Error::Database)?;
+    polls::increase_vote_count(&mut *tx, option_id, weight)
         .await
-        .map_err(Error::DatabaseError);
+        .map_err(Error::Database)?;
+    let vote = VoteInfo {
+        option_id: Some(option_id),
+        user_id: (!poll.secret_ballot).then_some(user_id),
+    };
+    webhooks::notify(&mut tx, poll_id, WebhookEvent::Voted, Some(vote))
+        .await
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
+
+    if poll.auto_close {
+        close_if_locked(&pool, poll_id).await?;
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
 
-    // // Optionally, update the vote count for the selected option
-    let _ = polls::increase_vote_count(&pool, option_id)
+/**
+Rejects ballots cast on a survey question directly, which would bypass the survey's
+single submission and its required questions.
+*/
+pub async fn not_survey_question(pool: &PgPool, poll_id: Uuid) -> WebResult<()> {
+    let survey = surveys::survey_of_poll(pool, poll_id)
         .await
-        .map_err(Error::DatabaseError);
+        .map_err(Error::Database)?;
+    match survey {
+        Some(_) => Err(Error::SurveyQuestion),
+        None => Ok(()),
+    }
+}
 
-    Ok(HttpResponse::Ok().finish())
+/**
+Closes an `auto_close` poll once its outcome can no longer change.
+*/
+async fn close_if_locked(pool: &PgPool, poll_id: Uuid) -> WebResult<()> {
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let poll = polls::get_poll(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    let options = polls::get_poll_options_data(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    let electorate = results::electorate(&mut *tx, &poll)
+        .await
+        .map_err(Error::Database)?;
+    if poll.is_active
+        && ResultStats::compute(&poll, &options, electorate)
+            .outcome
+            .locked
+    {
+        let closed = polls::close_poll(&mut *tx, poll_id)
+            .await
+            .map_err(Error::Database)?;
+        if closed {
+            webhooks::notify(&mut tx, poll_id, WebhookEvent::Closed, None)
+                .await
+                .map_err(Error::Database)?;
+        }
+    }
+    tx.commit().await.map_err(Error::Database)?;
+    Ok(())
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}/votes/me",
+    tag = "votes",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 204, description = "The caller's vote was removed"),
+        (status = 400, description = "The poll is closed or its quiz answers are revealed", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "Poll not found, or the caller has not voted in it", body = ErrorBody),
+    )
+)]
 pub async fn remove_vote(
     poll_id: Path<Uuid>,
     session: Session,
     pool: Data<PgPool>,
-    req: Json<VoteRequest>,
 ) -> WebResult<HttpResponse> {
     let user_id = validate_session(&session)?;
     let poll_id = poll_id.into_inner();
-    let poll_option_id = req.option_id;
-    // Check if user has voted on this poll
-    let vote = polls::has_user_voted(&pool, poll_option_id, user_id, poll_id)
+    let poll = polls::get_poll(pool.get_ref(), poll_id)
         .await
-        .map_err(Error::DatabaseError)?;
-    if !vote {
-        return Err(Error::VoteNotFound);
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::PollNotFound,
+            _ => Error::Database(e),
+        })?;
+    // Ballots of a closed poll, or of a quiz whose answers are out, are final
+    if !poll.is_active || quiz::ballots_locked(&poll) {
+        return Err(Error::PollClosed);
     }
+    not_survey_question(&pool, poll_id).await?;
+    // Find the option the user voted for on this poll
+    let poll_option_id = polls::get_user_vote_option(&pool, user_id, poll_id)
+        .await
+        .map_err(Error::Database)?
+        .ok_or(Error::VoteNotFound)?;
 
-    let _ = polls::delete_vote(&pool, poll_option_id, user_id)
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let weight = polls::delete_vote(&mut *tx, poll_option_id, user_id)
         .await
-        .map_err(Error::DatabaseError);
+        .map_err(Error::Database)?
+        .ok_or(Error::VoteNotFound)?;
 
     // Decrement the vote count for the option
-    let _ = polls::decrease_vote_count(&pool, poll_option_id)
+    polls::decrease_vote_count(&mut *tx, poll_option_id, weight)
         .await
-        .map_err(Error::DatabaseError);
+        .map_err(Error::Database)?;
+    let vote = VoteInfo {
+        option_id: Some(poll_option_id),
+        user_id: (!poll.secret_ballot).then_some(user_id),
+    };
+    webhooks::notify(&mut tx, poll_id, WebhookEvent::VoteRemoved, Some(vote))
+        .await
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::NoContent().finish())
 }
-#[derive(Serialize, Debug)]
+
+#[derive(Serialize, Debug, ToSchema)]
 pub struct PollData {
+    id: Uuid,
     title: String,
     description: String,
     is_active: bool,
     options: Vec<polls::PollOption>,
     user_id: Uuid,
     created_at: chrono::DateTime<chrono::Utc>,
+    secret_ballot: bool,
+    recurrence: Option<String>,
+    next_run_at: Option<chrono::DateTime<chrono::Utc>>,
+    previous_poll_id: Option<Uuid>,
+    results_visibility: polls::ResultsVisibility,
+    weighted: bool,
+    default_weight: f64,
+    participants_only: bool,
+    quorum_votes: Option<i32>,
+    quorum_percent: Option<f64>,
+    pass_threshold: polls::PassThreshold,
+    auto_close: bool,
+    question: Question,
+    /// Scoring of a quiz poll; absent for plain polls
+    quiz: Option<QuizInfo>,
+    /// Absent, along with every option's tallies, while the caller may not see results
+    results: Option<ResultStats>,
+    /// Aggregated answers of scale, number and text polls, absent like `results`
+    answers: Option<AnswerSummary>,
+}
+
+impl PollData {
+    pub fn new(
+        poll: polls::Poll,
+        mut options: Vec<polls::PollOption>,
+        electorate: Option<Electorate>,
+        answers: Option<AnswerSummary>,
+    ) -> Self {
+        if !poll.quiz {
+            quiz::hide_answers(&mut options);
+        }
+        PollData {
+            question: Question::from(&poll),
+            quiz: QuizInfo::of(&poll),
+            results: Some(ResultStats::compute(&poll, &options, electorate)),
+            id: poll.id,
+            title: poll.title,
+            description: poll.description,
+            is_active: poll.is_active,
+            user_id: poll.user_id,
+            created_at: poll.created_at,
+            secret_ballot: poll.secret_ballot,
+            recurrence: poll.recurrence,
+            next_run_at: poll.next_run_at,
+            previous_poll_id: poll.previous_poll_id,
+            results_visibility: poll.results_visibility,
+            weighted: poll.weighted,
+            default_weight: poll.default_weight,
+            participants_only: poll.participants_only,
+            quorum_votes: poll.quorum_votes,
+            quorum_percent: poll.quorum_percent,
+            pass_threshold: poll.pass_threshold,
+            auto_close: poll.auto_close,
+            options,
+            answers,
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Strips the correct answers of a quiz for callers who may not see them yet
+    pub fn hide_answers(mut self) -> Self {
+        quiz::hide_answers(&mut self.options);
+        self
+    }
+
+    /// Strips every tally for callers who may not see results yet
+    pub fn hide_results(mut self) -> Self {
+        self.results = None;
+        self.answers = None;
+        for option in &mut self.options {
+            option.votes_count = None;
+            option.weighted_votes = None;
+        }
+        self
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 pub struct QueryParams {
+    /// Only return polls created by this user
     creator: Option<Uuid>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct PollsBrief {
     pub id: Uuid,
     pub title: String,
@@ -324,72 +877,109 @@ pub struct PollsBrief {
     pub is_active: bool,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
+
+impl From<polls::Poll> for PollsBrief {
+    fn from(poll: polls::Poll) -> Self {
+        PollsBrief {
+            id: poll.id,
+            title: poll.title,
+            description: poll.description,
+            is_active: poll.is_active,
+            created_at: poll.created_at,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls",
+    tag = "polls",
+    params(QueryParams),
+    responses((status = 200, description = "Poll summaries", body = [PollsBrief]))
+)]
 pub async fn get_polls_brief(
     pool: Data<PgPool>,
     query: web::Query<QueryParams>,
 ) -> WebResult<HttpResponse> {
-    let mut polls = Vec::new();
-    if query.creator.is_some() {
-        let user_id = query.creator.unwrap();
-        polls = polls::get_user_polls_brief(&pool, user_id)
+    let polls = match query.creator {
+        Some(user_id) => polls::get_user_polls_brief(&pool, user_id)
             .await
-            .map_err(Error::DatabaseError)?;
-    } else {
-        polls = polls::get_all_polls(&pool)
-            .await
-            .map_err(Error::DatabaseError)?;
-    }
-    Ok(HttpResponse::Ok().json(polls))
-}
-
-pub async fn get_poll(poll_id: Path<Uuid>, pool: Data<PgPool>) -> WebResult<HttpResponse> {
-    let poll_id = poll_id.into_inner();
-
-    // Retrieve poll details
-    let poll = polls::get_poll(&pool, poll_id)
-        .await
-        .map_err(|_| Error::PollNotFound)?;
-
-    // Retrieve poll options and their vote counts
-    let options = polls::get_poll_options_data(&pool, poll_id)
-        .await
-        .map_err(Error::DatabaseError)?;
-    let res: PollData = PollData {
-        title: poll.title,
-        description: poll.description,
-        is_active: poll.is_active,
-        user_id: poll.user_id,
-        created_at: poll.created_at,
-        options,
+            .map_err(Error::Database)?,
+        None => polls::get_all_polls(&pool).await.map_err(Error::Database)?,
     };
-    println!("asdasd:   {:?}", res);
-    Ok(HttpResponse::Ok().json(res))
+    let polls: Vec<PollsBrief> = polls.into_iter().map(PollsBrief::from).collect();
+    Ok(HttpResponse::Ok().json(polls))
 }
 
-pub async fn close_poll(
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 200, description = "Poll with its options; tallies are left out while the \
+            poll's results_visibility hides them from the caller", body = PollData),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn get_poll(
     poll_id: Path<Uuid>,
     session: Session,
     pool: Data<PgPool>,
 ) -> WebResult<HttpResponse> {
-    warn!("checking in close_poll : {:?}", session.entries());
-    // let user_id = validate_session(&session)?;
     let poll_id = poll_id.into_inner();
-    // Check if the poll exists and if the user is the owner
-    poll_valid_owner_authorized(poll_id, session, &pool)
+
+    // Retrieve poll details
+    let poll = polls::get_poll(pool.get_ref(), poll_id)
         .await
         .map_err(|e| match e {
-            Error::PollNotFound => Error::PollNotFound,
-            _ => Error::Unauthorized,
+            sqlx::Error::RowNotFound => Error::PollNotFound,
+            _ => Error::Database(e),
         })?;
 
-    // Close the poll
-    polls::close_poll(&pool, poll_id)
+    let mut conn = pool.acquire().await.map_err(Error::Database)?;
+    let res = poll_data(&mut conn, poll, session_user(&session))
         .await
-        .map_err(Error::DatabaseError)?;
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(res))
+}
 
-    Ok(HttpResponse::Ok().finish())
+/**
+Loads the options and results of `poll` as `viewer` may see them.
+*/
+pub async fn poll_data(
+    conn: &mut PgConnection,
+    poll: polls::Poll,
+    viewer: Option<Uuid>,
+) -> Result<PollData, sqlx::Error> {
+    // Retrieve poll options and their vote counts
+    let options = polls::get_poll_options_data(&mut *conn, poll.id).await?;
+    let visible = can_view_results(&mut *conn, &poll, viewer).await?;
+    let answers_visible = quiz::answers_visible(&poll, viewer);
+    let electorate = results::electorate(&mut *conn, &poll).await?;
+    let answers = questions::summarize(&mut *conn, &poll).await?;
+    let mut res = PollData::new(poll, options, electorate, answers);
+    if !answers_visible {
+        res = res.hide_answers();
+    }
+    if visible {
+        Ok(res)
+    } else {
+        Ok(res.hide_results())
+    }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}/votes",
+    tag = "votes",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 204, description = "All votes on the poll were removed"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
 pub async fn reset_poll(
     poll_id: Path<Uuid>,
     session: Session,
@@ -405,13 +995,21 @@ pub async fn reset_poll(
         })?;
 
     // Reset the votes
-    polls::delete_votes(&pool, poll_id)
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    polls::delete_votes(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    polls::reset_votes_count(&mut *tx, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    answers::delete_answers(&mut *tx, poll_id)
         .await
-        .map_err(Error::DatabaseError)?;
-    polls::reset_votes_count(&pool, poll_id)
+        .map_err(Error::Database)?;
+    webhooks::notify(&mut tx, poll_id, WebhookEvent::Reset, None)
         .await
-        .map_err(Error::DatabaseError)?;
-    Ok(HttpResponse::Ok().finish())
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 pub async fn poll_valid_owner_authorized(
@@ -422,11 +1020,11 @@ pub async fn poll_valid_owner_authorized(
     let user_id = validate_session(&session)?;
 
     // Check if the poll exists and if the user is the owner
-    let user = polls::does_poll_exist(&pool, poll_id)
+    let user = polls::does_poll_exist(pool, poll_id)
         .await
         .map_err(|e| match e {
             sqlx::Error::RowNotFound => Error::PollNotFound,
-            _ => Error::DatabaseError(e),
+            _ => Error::Database(e),
         })?;
 
     if user != user_id {
@@ -436,74 +1034,177 @@ pub async fn poll_valid_owner_authorized(
     Ok(())
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{user_id}/polls",
+    tag = "polls",
+    params(("user_id" = Uuid, Path, description = "Creator of the polls")),
+    responses((status = 200, description = "Poll summaries", body = [PollsBrief]))
+)]
 pub async fn get_user_polls(user_id: Path<Uuid>, pool: Data<PgPool>) -> WebResult<HttpResponse> {
     let user_id = user_id.into_inner();
-    let polls = polls::get_user_polls_brief(&pool, user_id)
+    let polls: Vec<PollsBrief> = polls::get_user_polls_brief(&pool, user_id)
         .await
-        .map_err(Error::DatabaseError)?;
+        .map_err(Error::Database)?
+        .into_iter()
+        .map(PollsBrief::from)
+        .collect();
     Ok(HttpResponse::Ok().json(polls))
 }
 
-pub async fn get_poll_results(poll_id: Path<Uuid>, pool: Data<PgPool>) -> impl Responder {
+#[derive(Serialize, ToSchema)]
+pub struct PollResults {
+    poll: String,
+    total_votes: i64,
+    stats: ResultStats,
+    options: Vec<polls::PollOption>,
+    /// Aggregated answers of scale, number and text polls
+    answers: Option<AnswerSummary>,
+}
+
+/**
+Formats an [Error] as an SSE `error` event carrying the usual JSON error body.
+The stream outlives the request-id scope, so the id is captured by the caller.
+*/
+pub fn sse_error(err: Error, request_id: Option<Uuid>) -> web::Bytes {
+    let mut body = err.body();
+    body.request_id = request_id;
+    web::Bytes::from(format!(
+        "event: error\ndata: {}\n\n",
+        serde_json::to_string(&body).unwrap()
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/results",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 200, description = "Server-sent events; every `data:` frame is a PollResults document, \
+            failures arrive as an `error` event carrying an ErrorBody. While the poll's results_visibility \
+            hides results from the caller, a `hidden` event carrying `{\"results_visibility\": ...}` is \
+            sent instead, and data frames follow as soon as the condition is met. Changes to the poll's \
+            comments arrive as `comment` events carrying a CommentEventData, whatever the results_visibility. \
+            The poll's owner also gets a `reactions` event carrying a ReactionWindow after every window \
+            in which the audience reacted",
+            content_type = "text/event-stream", body = PollResults),
+    )
+)]
+pub async fn get_poll_results(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    reaction_config: Data<ReactionConfig>,
+) -> impl Responder {
     let poll_id = poll_id.into_inner();
+    let viewer = session_user(&session);
+    let request_id = request_id::current();
     let mut interval = tokio::time::interval(Duration::from_secs(2));
+    let mut reactions_since = reaction_config.window_start(chrono::Utc::now());
 
     let stream = async_stream::stream! {
-    loop {
-        interval.tick().await;
-
-        let poll = match polls::get_poll(&pool, poll_id).await {
-            Ok(poll) => poll,
-            Err(_) => {
-                yield Result::<web::Bytes, Box<dyn std::error::Error>>::Ok(web::Bytes::from("Poll not found"));
+        // Only comment changes made after connecting are pushed
+        let mut last_comment_event = match comments::last_comment_event_id(&pool, poll_id).await {
+            Ok(id) => id,
+            Err(e) => {
+                yield Result::<web::Bytes, Box<dyn std::error::Error>>::Ok(sse_error(Error::Database(e), request_id));
                 return;
             }
         };
+        loop {
+            interval.tick().await;
+
+            let poll = match polls::get_poll(pool.get_ref(), poll_id).await {
+                Ok(poll) => poll,
+                Err(sqlx::Error::RowNotFound) => {
+                    yield Ok(sse_error(Error::PollNotFound, request_id));
+                    return;
+                }
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
+                    return;
+                }
+            };
 
-            let options = match polls::get_poll_options_data(&pool, poll_id).await {
+            match comment_frames(&pool, &poll, viewer, &mut last_comment_event).await {
+                Ok(frames) => {
+                    for frame in frames {
+                        yield Ok(frame);
+                    }
+                }
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
+                    return;
+                }
+            }
+
+            if viewer == Some(poll.user_id) {
+                match reaction_frames(&pool, poll_id, &reaction_config, &mut reactions_since).await {
+                    Ok(frames) => {
+                        for frame in frames {
+                            yield Ok(frame);
+                        }
+                    }
+                    Err(e) => {
+                        yield Ok(sse_error(Error::Database(e), request_id));
+                        return;
+                    }
+                }
+            }
+
+            match can_view_results(pool.get_ref(), &poll, viewer).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let hidden = serde_json::json!({ "results_visibility": poll.results_visibility });
+                    yield Ok(web::Bytes::from(format!("event: hidden\ndata: {}\n\n", hidden)));
+                    continue;
+                }
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
+                    return;
+                }
+            }
+
+            let mut options = match polls::get_poll_options_data(pool.get_ref(), poll_id).await {
                 Ok(options) => options,
-                Err(_) => {
-                    yield Ok(web::Bytes::from("Database error"));
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
+                    return;
+                }
+            };
+
+            let electorate = match results::electorate(pool.get_ref(), &poll).await {
+                Ok(electorate) => electorate,
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
+                    return;
+                }
+            };
+
+            let answers = match pool.acquire().await {
+                Ok(mut conn) => questions::summarize(&mut conn, &poll).await,
+                Err(e) => Err(e),
+            };
+            let answers = match answers {
+                Ok(answers) => answers,
+                Err(e) => {
+                    yield Ok(sse_error(Error::Database(e), request_id));
                     return;
                 }
             };
 
-            let total_votes: i32 = options
-                .iter()
-                .map(|option| option.votes_count.unwrap_or(0))
-                .sum();
-            let option_percentage: Vec<_> = options
-                .iter()
-                .map(|option| {
-                    let percentage = if total_votes > 0 {
-                        (option.votes_count.unwrap_or(0) as f64 / total_votes as f64) * 100.0
-                    } else {
-                        0.0
-                    };
-                    (option.id, percentage)
-                })
-                .collect();
-
-            let winner = option_percentage
-                .iter()
-                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or( std::cmp::Ordering::Equal))
-                .map(|(option, _)| option);
-
-            let runner_up = option_percentage
-                .iter()
-                .filter(|(option, _)| option != winner.unwrap())
-                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or( std::cmp::Ordering::Equal))
-                .map(|(option, _)| option);
-
-            let res = json!({
-                "poll": poll.title,
-                "total_votes": total_votes,
-                "winner": winner.unwrap(),
-                "runner_up": runner_up.unwrap(),
-                "percentage": option_percentage,
-                "options": options,
-            });
-            println!("asdasd:   {:?}", res);
+            let stats = ResultStats::compute(&poll, &options, electorate);
+            if !quiz::answers_visible(&poll, viewer) {
+                quiz::hide_answers(&mut options);
+            }
+            let res = PollResults {
+                poll: poll.title,
+                total_votes: stats.total_votes,
+                stats,
+                options,
+                answers,
+            };
             // Send the data as an SSE message
             yield Ok(web::Bytes::from(format!("data: {}\n\n", serde_json::to_string(&res).unwrap())));
         }
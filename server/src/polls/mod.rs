@@ -1 +1,20 @@
-pub mod manage_polls;
\ No newline at end of file
+pub mod audience_questions;
+pub mod comments;
+pub mod definition;
+pub mod export;
+pub mod history;
+pub mod join;
+pub mod live;
+pub mod manage_polls;
+pub mod options;
+pub mod questions;
+pub mod quiz;
+pub mod reactions;
+pub mod recurrence;
+pub mod results;
+pub mod surveys;
+pub mod templates;
+pub mod validation;
+pub mod visibility;
+pub mod webhooks;
+pub mod weights;
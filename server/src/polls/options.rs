@@ -0,0 +1,288 @@
+use crate::{
+    auth::error::{Error, ErrorBody, FieldError, WebResult},
+    db::polls::{self, NewPollOption, PollOption, PollOptionChanges},
+    polls::{
+        manage_polls::poll_valid_owner_authorized,
+        validation::{PollLimits, Validator},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpResponse,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::*;
+
+/**
+An option in a create request: either just its text, or its text plus presentation data.
+*/
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+#[serde(untagged)]
+pub enum PollOptionInput {
+    Text(String),
+    Detailed(PollOptionDetails),
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct PollOptionDetails {
+    option_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_asset_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+}
+
+impl PollOptionInput {
+    pub fn text(&self) -> &str {
+        match self {
+            PollOptionInput::Text(text) => text,
+            PollOptionInput::Detailed(details) => &details.option_text,
+        }
+    }
+
+    /// Validates the metadata of the option at `index`; the text is checked by
+    /// [Validator::options] together with the rest of the list.
+    pub fn validate(&self, v: &mut Validator, field: &str, index: usize, limits: &PollLimits) {
+        if let PollOptionInput::Detailed(details) = self {
+            validate_metadata(
+                v,
+                &format!("{}[{}].", field, index),
+                details.description.as_deref(),
+                details.image_url.as_deref(),
+                details.color.as_deref(),
+                limits,
+            );
+        }
+    }
+
+    pub fn into_new_option(self) -> NewPollOption {
+        match self {
+            PollOptionInput::Text(text) => NewPollOption {
+                option_text: text.trim().to_string(),
+                ..Default::default()
+            },
+            PollOptionInput::Detailed(details) => NewPollOption {
+                option_text: details.option_text.trim().to_string(),
+                description: details.description.map(|d| d.trim().to_string()),
+                image_url: details.image_url,
+                image_asset_id: details.image_asset_id,
+                color: details.color,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/**
+Describes a stored option the way it would be sent to create it; options without any
+presentation data collapse to their plain text.
+*/
+impl From<PollOption> for PollOptionInput {
+    fn from(option: PollOption) -> Self {
+        if option.description.is_none()
+            && option.image_url.is_none()
+            && option.image_asset_id.is_none()
+            && option.color.is_none()
+        {
+            return PollOptionInput::Text(option.option_text);
+        }
+        PollOptionInput::Detailed(PollOptionDetails {
+            option_text: option.option_text,
+            description: option.description,
+            image_url: option.image_url,
+            image_asset_id: option.image_asset_id,
+            color: option.color,
+        })
+    }
+}
+
+/// `prefix` is prepended to the reported field names, e.g. `poll_options[2].`
+fn validate_metadata(
+    v: &mut Validator,
+    prefix: &str,
+    description: Option<&str>,
+    image_url: Option<&str>,
+    color: Option<&str>,
+    limits: &PollLimits,
+) {
+    if let Some(description) = description {
+        v.optional_text(
+            &format!("{}description", prefix),
+            description,
+            limits.max_option_description_len,
+        );
+    }
+    if let Some(image_url) = image_url {
+        v.url(
+            &format!("{}image_url", prefix),
+            image_url,
+            limits.max_url_len,
+        );
+    }
+    if let Some(color) = color {
+        v.color(&format!("{}color", prefix), color);
+    }
+}
+
+/**
+Maps a write error to a response error; an `image_asset_id` that does not name an
+uploaded asset trips the foreign key and is reported against `field`.
+*/
+pub fn option_write_error(e: sqlx::Error, field: &str) -> Error {
+    match e {
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => {
+            Error::Validation(vec![FieldError::new(field, "references an unknown asset")])
+        }
+        sqlx::Error::RowNotFound => Error::OptionNotFound,
+        _ => Error::Database(e),
+    }
+}
+
+/**
+Distinguishes a missing field (`None`) from an explicit `null` (`Some(None)`).
+*/
+pub fn double_option<'de, T, D>(de: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Deserialize::deserialize(de).map(Some)
+}
+
+/**
+Fields left out are unchanged; fields sent as `null` are cleared.
+*/
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateOptionRequest {
+    option_text: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    image_url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    image_asset_id: Option<Option<Uuid>>,
+    #[serde(default, deserialize_with = "double_option")]
+    color: Option<Option<String>>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/polls/{poll_id}/options/{option_id}",
+    tag = "polls",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("option_id" = Uuid, Path, description = "Option id"),
+    ),
+    request_body = UpdateOptionRequest,
+    responses(
+        (status = 200, description = "The updated option", body = polls::PollOption),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll or option not found", body = ErrorBody),
+        (status = 422, description = "Option fields failed validation", body = ErrorBody),
+    )
+)]
+pub async fn update_option(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<UpdateOptionRequest>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, option_id) = path.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+
+    let req = req.into_inner();
+    let mut v = Validator::new();
+    if let Some(text) = &req.option_text {
+        let options = polls::get_poll_options_data(pool.get_ref(), poll_id)
+            .await
+            .map_err(Error::Database)?;
+        if !options.iter().any(|o| o.id == option_id) {
+            return Err(Error::OptionNotFound);
+        }
+        let others: Vec<&str> = options
+            .iter()
+            .filter(|o| o.id != option_id)
+            .map(|o| o.option_text.as_str())
+            .collect();
+        v.renamed_option("option_text", text, &others, limits.max_option_len);
+    }
+    validate_metadata(
+        &mut v,
+        "",
+        req.description.as_ref().and_then(|d| d.as_deref()),
+        req.image_url.as_ref().and_then(|u| u.as_deref()),
+        req.color.as_ref().and_then(|c| c.as_deref()),
+        &limits,
+    );
+    v.finish()?;
+
+    let changes = PollOptionChanges {
+        option_text: req.option_text.map(|text| text.trim().to_string()),
+        description: req.description,
+        image_url: req.image_url,
+        image_asset_id: req.image_asset_id,
+        color: req.color,
+    };
+    let option = polls::update_option(&pool, poll_id, option_id, &changes)
+        .await
+        .map_err(|e| option_write_error(e, "image_asset_id"))?;
+    Ok(HttpResponse::Ok().json(option))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ReorderOptionsRequest {
+    /// Every option id of the poll, in the new display order
+    option_ids: Vec<Uuid>,
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/polls/{poll_id}/options/order",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = ReorderOptionsRequest,
+    responses(
+        (status = 204, description = "Options reordered"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The ids are not exactly the poll's options", body = ErrorBody),
+    )
+)]
+pub async fn reorder_options(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<ReorderOptionsRequest>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+
+    let existing: HashSet<Uuid> = polls::get_poll_option_ids(&pool, poll_id)
+        .await
+        .map_err(Error::Database)?
+        .into_iter()
+        .collect();
+    let requested: HashSet<Uuid> = req.option_ids.iter().copied().collect();
+    if requested.len() != req.option_ids.len() || requested != existing {
+        let mut v = Validator::new();
+        v.add(
+            "option_ids",
+            "must list every option of the poll exactly once",
+        );
+        v.finish()?;
+    }
+
+    polls::reorder_options(&pool, poll_id, &req.option_ids)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
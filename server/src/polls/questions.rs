@@ -0,0 +1,659 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, WebResult},
+        validate_session::{session_user, validate_session},
+    },
+    db::{
+        answers,
+        polls::{self, Poll, QuestionKind, QuestionSettings},
+        webhooks::WebhookEvent,
+        weights,
+    },
+    polls::{
+        manage_polls::not_survey_question,
+        options::PollOptionInput,
+        validation::{PollLimits, Validator},
+        visibility::can_view_results,
+        webhooks::{self, VoteInfo},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json, Path, Query},
+    HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+/// Words too common to say anything about a text answer
+const IGNORED_WORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was",
+    "one", "our", "out", "has", "have", "him", "his", "how", "its", "were", "what", "when", "who",
+    "why", "with", "this", "that", "they", "them", "then", "there", "from", "been", "into", "too",
+    "very", "just", "some", "more", "than", "also",
+];
+/// Shortest word counted in text summaries
+const MIN_WORD_LENGTH: i32 = 3;
+/// Words listed in a text summary
+const TOP_WORDS: i64 = 50;
+
+/**
+What the poll asks for. Choice polls use their options; the other kinds take an answer
+through `/answers` and have no options.
+*/
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Question {
+    #[default]
+    Choice,
+    /// A whole number from 1 to `max`
+    Scale { max: i32 },
+    /// Any number, optionally bounded
+    Number {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+    },
+    /// A short free-text answer; `max_length` defaults to the server's limit
+    Text {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_length: Option<i32>,
+    },
+}
+
+impl Question {
+    pub fn is_choice(&self) -> bool {
+        *self == Question::Choice
+    }
+
+    /// Checks the options given with the question: a choice needs a valid option set,
+    /// every other kind takes none
+    pub fn validate_options(
+        &self,
+        v: &mut Validator,
+        field: &str,
+        options: &[PollOptionInput],
+        limits: &PollLimits,
+    ) {
+        if self.is_choice() {
+            let texts: Vec<&str> = options.iter().map(|o| o.text()).collect();
+            v.options(field, &texts, limits);
+            for (i, option) in options.iter().enumerate() {
+                option.validate(v, field, i, limits);
+            }
+        } else if !options.is_empty() {
+            v.add(field, "must be empty unless the question is a choice");
+        }
+    }
+
+    /// Checks the question's settings, reported under `field`
+    pub fn validate(&self, v: &mut Validator, field: &str, limits: &PollLimits) {
+        match *self {
+            Question::Choice => {}
+            Question::Scale { max } => {
+                if max < 2 || max as usize > limits.max_scale {
+                    v.add(
+                        format!("{}.max", field),
+                        format!("must be between 2 and {}", limits.max_scale),
+                    );
+                }
+            }
+            Question::Number { min, max } => {
+                if min.is_some_and(|min| !min.is_finite()) {
+                    v.add(format!("{}.min", field), "must be a number");
+                }
+                if max.is_some_and(|max| !max.is_finite()) {
+                    v.add(format!("{}.max", field), "must be a number");
+                }
+                if let (Some(min), Some(max)) = (min, max) {
+                    if min >= max {
+                        v.add(format!("{}.max", field), "must be greater than min");
+                    }
+                }
+            }
+            Question::Text { max_length } => {
+                if let Some(max_length) = max_length {
+                    if max_length < 1 || max_length as usize > limits.max_answer_len {
+                        v.add(
+                            format!("{}.max_length", field),
+                            format!("must be between 1 and {}", limits.max_answer_len),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn settings(&self) -> QuestionSettings {
+        match *self {
+            Question::Choice => QuestionSettings::default(),
+            Question::Scale { max } => QuestionSettings {
+                kind: QuestionKind::Scale,
+                scale_max: Some(max),
+                ..Default::default()
+            },
+            Question::Number { min, max } => QuestionSettings {
+                kind: QuestionKind::Number,
+                number_min: min,
+                number_max: max,
+                ..Default::default()
+            },
+            Question::Text { max_length } => QuestionSettings {
+                kind: QuestionKind::Text,
+                text_max_length: max_length,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl From<&Poll> for Question {
+    fn from(poll: &Poll) -> Self {
+        match poll.kind {
+            QuestionKind::Choice => Question::Choice,
+            QuestionKind::Scale => Question::Scale {
+                max: poll.scale_max.unwrap_or(5),
+            },
+            QuestionKind::Number => Question::Number {
+                min: poll.number_min,
+                max: poll.number_max,
+            },
+            QuestionKind::Text => Question::Text {
+                max_length: poll.text_max_length,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ScaleBucket {
+    pub value: i32,
+    pub count: i64,
+}
+
+/**
+Aggregated answers of a scale, number or text poll
+*/
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AnswerSummary {
+    Scale {
+        count: i64,
+        mean: Option<f64>,
+        median: Option<f64>,
+        /// One bucket per value from 1 to the top of the scale
+        histogram: Vec<ScaleBucket>,
+    },
+    Number {
+        count: i64,
+        mean: Option<f64>,
+        median: Option<f64>,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Text {
+        count: i64,
+        /// Most frequent words, common ones left out
+        top_words: Vec<answers::WordCount>,
+    },
+}
+
+/**
+Summarizes the answers of a scale, number or text poll; `None` for choice polls.
+*/
+pub async fn summarize(
+    conn: &mut PgConnection,
+    poll: &Poll,
+) -> Result<Option<AnswerSummary>, sqlx::Error> {
+    let summary = match poll.kind {
+        QuestionKind::Choice => return Ok(None),
+        QuestionKind::Scale => {
+            let stats = answers::get_numeric_stats(&mut *conn, poll.id).await?;
+            let counts = answers::get_value_counts(&mut *conn, poll.id).await?;
+            let histogram = (1..=poll.scale_max.unwrap_or(5))
+                .map(|value| ScaleBucket {
+                    value,
+                    count: counts
+                        .iter()
+                        .find(|(answer, _)| *answer == value as f64)
+                        .map(|(_, count)| *count)
+                        .unwrap_or(0),
+                })
+                .collect();
+            AnswerSummary::Scale {
+                count: stats.count,
+                mean: stats.mean,
+                median: stats.median,
+                histogram,
+            }
+        }
+        QuestionKind::Number => {
+            let stats = answers::get_numeric_stats(&mut *conn, poll.id).await?;
+            AnswerSummary::Number {
+                count: stats.count,
+                mean: stats.mean,
+                median: stats.median,
+                min: stats.min,
+                max: stats.max,
+            }
+        }
+        QuestionKind::Text => AnswerSummary::Text {
+            count: answers::count_text_answers(&mut *conn, poll.id).await?,
+            top_words: answers::get_word_counts(
+                &mut *conn,
+                poll.id,
+                MIN_WORD_LENGTH,
+                IGNORED_WORDS,
+                TOP_WORDS,
+            )
+            .await?,
+        },
+    };
+    Ok(Some(summary))
+}
+
+/**
+An answer to a scale or number poll sets `value`, an answer to a text poll sets `text`.
+*/
+#[derive(Deserialize, ToSchema)]
+pub struct AnswerRequest {
+    value: Option<f64>,
+    text: Option<String>,
+}
+
+/**
+Checks an answer against the poll's question, reporting under `prefix` followed by
+`value` or `text`.
+*/
+pub fn check_answer(
+    v: &mut Validator,
+    prefix: &str,
+    poll: &Poll,
+    value: Option<f64>,
+    text: Option<&str>,
+    limits: &PollLimits,
+) {
+    let value_field = format!("{}value", prefix);
+    match Question::from(poll) {
+        Question::Choice => v.add(value_field, "this poll is answered by voting for an option"),
+        Question::Scale { max } => match value {
+            Some(value) if value.fract() == 0.0 && value >= 1.0 && value <= max as f64 => {}
+            Some(_) => v.add(
+                value_field,
+                format!("must be a whole number from 1 to {}", max),
+            ),
+            None => v.add(value_field, "is required"),
+        },
+        Question::Number { min, max } => match value {
+            Some(value) if !value.is_finite() => v.add(value_field, "must be a number"),
+            Some(value) if min.is_some_and(|min| value < min) => v.add(
+                value_field,
+                format!("must be at least {}", min.unwrap_or_default()),
+            ),
+            Some(value) if max.is_some_and(|max| value > max) => v.add(
+                value_field,
+                format!("must be at most {}", max.unwrap_or_default()),
+            ),
+            Some(_) => {}
+            None => v.add(value_field, "is required"),
+        },
+        Question::Text { max_length } => match text {
+            Some(text) => {
+                let max = max_length
+                    .map(|max| max as usize)
+                    .unwrap_or(limits.max_answer_len);
+                v.text(&format!("{}text", prefix), text, max);
+            }
+            None => v.add(format!("{}text", prefix), "is required"),
+        },
+    }
+}
+
+/**
+Stores an answer already accepted by [check_answer].
+*/
+pub async fn record_answer<'e, E>(
+    executor: E,
+    poll: &Poll,
+    user_id: Uuid,
+    value: Option<f64>,
+    text: Option<&str>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    match (poll.kind, text) {
+        (QuestionKind::Text, Some(text)) => {
+            answers::insert_text_answer(executor, Uuid::new_v4(), poll.id, user_id, text.trim())
+                .await
+        }
+        _ => {
+            answers::insert_numeric_answer(executor, poll.id, user_id, value.unwrap_or_default())
+                .await
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/{poll_id}/answers",
+    tag = "votes",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = AnswerRequest,
+    responses(
+        (status = 204, description = "Answer recorded"),
+        (status = 400, description = "Poll is closed or the user already answered", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 403, description = "The poll is limited to participants and the caller is not one", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The answer does not fit the question", body = ErrorBody),
+    )
+)]
+pub async fn submit_answer(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<AnswerRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let poll_id = poll_id.into_inner();
+    let poll = polls::get_poll(pool.get_ref(), poll_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::PollNotFound,
+            _ => Error::Database(e),
+        })?;
+    if !poll.is_active {
+        return Err(Error::PollClosed);
+    }
+    not_survey_question(&pool, poll_id).await?;
+    if poll.participants_only
+        && !weights::is_participant(&pool, poll_id, user_id)
+            .await
+            .map_err(Error::Database)?
+    {
+        return Err(Error::NotEligible);
+    }
+    let mut v = Validator::new();
+    check_answer(&mut v, "", &poll, req.value, req.text.as_deref(), &limits);
+    v.finish()?;
+
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    record_answer(&mut *tx, &poll, user_id, req.value, req.text.as_deref())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => Error::AlreadyVoted,
+            _ => Error::Database(e),
+        })?;
+    let vote = VoteInfo {
+        option_id: None,
+        user_id: (!poll.secret_ballot).then_some(user_id),
+    };
+    webhooks::notify(&mut tx, poll_id, WebhookEvent::Voted, Some(vote))
+        .await
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}/answers/me",
+    tag = "votes",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 204, description = "The caller's answer was removed"),
+        (status = 400, description = "Poll is closed", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "The caller has not answered this poll", body = ErrorBody),
+    )
+)]
+pub async fn remove_answer(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let poll_id = poll_id.into_inner();
+    let poll = polls::get_poll(pool.get_ref(), poll_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::PollNotFound,
+            _ => Error::Database(e),
+        })?;
+    if !poll.is_active {
+        return Err(Error::PollClosed);
+    }
+    not_survey_question(&pool, poll_id).await?;
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let removed = answers::delete_user_answer(&mut *tx, poll_id, user_id)
+        .await
+        .map_err(Error::Database)?;
+    if !removed {
+        return Err(Error::VoteNotFound);
+    }
+    let vote = VoteInfo {
+        option_id: None,
+        user_id: (!poll.secret_ballot).then_some(user_id),
+    };
+    webhooks::notify(&mut tx, poll_id, WebhookEvent::VoteRemoved, Some(vote))
+        .await
+        .map_err(Error::Database)?;
+    tx.commit().await.map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AnswerPageQuery {
+    /// Page number, starting at 1
+    #[serde(default = "default_page")]
+    page: i64,
+    /// Answers per page, at most 100
+    #[serde(default = "default_per_page")]
+    per_page: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TextAnswerResponse {
+    pub id: Uuid,
+    /// Left out for secret ballot polls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub answer: String,
+    pub answered_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TextAnswerPage {
+    pub page: i64,
+    pub per_page: i64,
+    /// Answers across all pages
+    pub total: i64,
+    pub answers: Vec<TextAnswerResponse>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/answers",
+    tag = "polls",
+    params(("poll_id" = Uuid, Path, description = "Poll id"), AnswerPageQuery),
+    responses(
+        (status = 200, description = "The text poll's answers, newest first", body = TextAnswerPage),
+        (status = 403, description = "The poll's results_visibility hides results from the caller", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The poll does not take text answers, or the page is out of range", body = ErrorBody),
+    )
+)]
+pub async fn list_answers(
+    poll_id: Path<Uuid>,
+    query: Query<AnswerPageQuery>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    let poll = polls::get_poll(pool.get_ref(), poll_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::PollNotFound,
+            _ => Error::Database(e),
+        })?;
+
+    let mut v = Validator::new();
+    if poll.kind != QuestionKind::Text {
+        v.add("poll_id", "only text polls have an answer list");
+    }
+    if query.page < 1 {
+        v.add("page", "must be at least 1");
+    }
+    if !(1..=100).contains(&query.per_page) {
+        v.add("per_page", "must be between 1 and 100");
+    }
+    v.finish()?;
+    if !can_view_results(pool.get_ref(), &poll, session_user(&session))
+        .await
+        .map_err(Error::Database)?
+    {
+        return Err(Error::ResultsHidden);
+    }
+
+    let total = answers::count_text_answers(pool.get_ref(), poll_id)
+        .await
+        .map_err(Error::Database)?;
+    let answers = answers::get_text_answers(
+        &pool,
+        poll_id,
+        query.per_page,
+        (query.page - 1) * query.per_page,
+    )
+    .await
+    .map_err(Error::Database)?
+    .into_iter()
+    .map(|answer| TextAnswerResponse {
+        id: answer.id,
+        user_id: (!poll.secret_ballot).then_some(answer.user_id),
+        username: (!poll.secret_ballot).then_some(answer.username),
+        answer: answer.answer,
+        answered_at: answer.answered_at,
+    })
+    .collect();
+    Ok(HttpResponse::Ok().json(TextAnswerPage {
+        page: query.page,
+        per_page: query.per_page,
+        total,
+        answers,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::error::Error;
+
+    /// Fields the answer was rejected for
+    fn rejected(poll: &Poll, value: Option<f64>, text: Option<&str>) -> Vec<String> {
+        let mut v = Validator::new();
+        check_answer(&mut v, "", poll, value, text, &PollLimits::from_env());
+        match v.finish() {
+            Ok(()) => Vec::new(),
+            Err(Error::Validation(errors)) => errors.into_iter().map(|e| e.field).collect(),
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    fn poll(kind: QuestionKind) -> Poll {
+        Poll {
+            kind,
+            ..Poll::fixture()
+        }
+    }
+
+    #[test]
+    fn choice_polls_take_no_answers() {
+        assert_eq!(
+            rejected(&poll(QuestionKind::Choice), Some(1.0), None),
+            ["value"]
+        );
+    }
+
+    #[test]
+    fn scale_answers_are_whole_numbers_in_range() {
+        let scale = Poll {
+            scale_max: Some(7),
+            ..poll(QuestionKind::Scale)
+        };
+        assert!(rejected(&scale, Some(1.0), None).is_empty());
+        assert!(rejected(&scale, Some(7.0), None).is_empty());
+        assert_eq!(rejected(&scale, Some(0.0), None), ["value"]);
+        assert_eq!(rejected(&scale, Some(8.0), None), ["value"]);
+        assert_eq!(rejected(&scale, Some(2.5), None), ["value"]);
+        assert_eq!(rejected(&scale, None, None), ["value"]);
+    }
+
+    #[test]
+    fn scale_defaults_to_five_points() {
+        let scale = poll(QuestionKind::Scale);
+        assert!(rejected(&scale, Some(5.0), None).is_empty());
+        assert_eq!(rejected(&scale, Some(6.0), None), ["value"]);
+    }
+
+    #[test]
+    fn number_answers_respect_bounds() {
+        let number = Poll {
+            number_min: Some(-1.5),
+            number_max: Some(10.0),
+            ..poll(QuestionKind::Number)
+        };
+        assert!(rejected(&number, Some(-1.5), None).is_empty());
+        assert!(rejected(&number, Some(3.25), None).is_empty());
+        assert_eq!(rejected(&number, Some(-2.0), None), ["value"]);
+        assert_eq!(rejected(&number, Some(10.5), None), ["value"]);
+        assert_eq!(rejected(&number, Some(f64::NAN), None), ["value"]);
+        let unbounded = poll(QuestionKind::Number);
+        assert!(rejected(&unbounded, Some(-1e9), None).is_empty());
+        assert_eq!(rejected(&unbounded, Some(f64::INFINITY), None), ["value"]);
+    }
+
+    #[test]
+    fn text_answers_are_required_and_limited() {
+        let text = Poll {
+            text_max_length: Some(5),
+            ..poll(QuestionKind::Text)
+        };
+        assert!(rejected(&text, None, Some("hello")).is_empty());
+        assert_eq!(rejected(&text, None, Some("hello!")), ["text"]);
+        assert_eq!(rejected(&text, None, Some("   ")), ["text"]);
+        assert_eq!(rejected(&text, Some(1.0), None), ["text"]);
+    }
+
+    #[test]
+    fn fields_carry_the_prefix() {
+        let mut v = Validator::new();
+        let limits = PollLimits::from_env();
+        check_answer(
+            &mut v,
+            "answers[2].",
+            &poll(QuestionKind::Scale),
+            None,
+            None,
+            &limits,
+        );
+        match v.finish() {
+            Err(Error::Validation(errors)) => assert_eq!(errors[0].field, "answers[2].value"),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use crate::{
+    api::request_id,
+    auth::{
+        error::{Error, ErrorBody, WebResult},
+        validate_session::session_user,
+    },
+    db::{
+        live,
+        polls::{self, Poll, PollOption, QuizSettings},
+        quiz::{self, QuizScore},
+    },
+    polls::{
+        manage_polls::{poll_valid_owner_authorized, sse_error},
+        questions::Question,
+        validation::Validator,
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{self, Data, Json, Path},
+    HttpResponse, Responder,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::time::Duration;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+/// Most points a single quiz question, or its time bonus, may award
+const MAX_POINTS: i32 = 10_000;
+/// Longest window a time bonus may run for
+const MAX_BONUS_SECONDS: i32 = 3600;
+
+fn default_points() -> i32 {
+    100
+}
+
+/**
+Extra points for answering quickly: the full `points` right as the poll goes live,
+shrinking linearly to nothing after `seconds`.
+*/
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct TimeBonus {
+    pub points: i32,
+    pub seconds: i32,
+}
+
+/**
+Makes a new poll a quiz
+*/
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct QuizInput {
+    /// Indexes into `poll_options` of the correct answers
+    pub correct_options: Vec<usize>,
+    /// Awarded for a correct vote, 100 unless set
+    #[serde(default = "default_points")]
+    pub points: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_bonus: Option<TimeBonus>,
+}
+
+impl QuizInput {
+    /// Describes the quiz of a stored poll, `None` when it is not one
+    pub fn from_poll(poll: &Poll, options: &[PollOption]) -> Option<Self> {
+        let settings = QuizSettings::of(poll)?;
+        Some(QuizInput {
+            correct_options: options
+                .iter()
+                .enumerate()
+                .filter(|(_, option)| option.is_correct == Some(true))
+                .map(|(i, _)| i)
+                .collect(),
+            points: settings.points,
+            time_bonus: time_bonus(&settings),
+        })
+    }
+
+    /// Checks the quiz against the poll it is created with, reported under `field`
+    pub fn validate(
+        &self,
+        v: &mut Validator,
+        field: &str,
+        question: &Question,
+        option_count: usize,
+        secret_ballot: bool,
+    ) {
+        if !question.is_choice() {
+            v.add(field, "only choice questions can be quizzes");
+        }
+        if secret_ballot {
+            v.add(field, "cannot be combined with secret_ballot");
+        }
+        let correct_field = format!("{}.correct_options", field);
+        if self.correct_options.is_empty() {
+            v.add(&correct_field, "must not be empty");
+        }
+        for (i, &option) in self.correct_options.iter().enumerate() {
+            if option >= option_count {
+                v.add(
+                    format!("{}[{}]", correct_field, i),
+                    format!(
+                        "refers to option {} of a poll with {}",
+                        option, option_count
+                    ),
+                );
+            } else if self.correct_options[..i].contains(&option) {
+                v.add(
+                    format!("{}[{}]", correct_field, i),
+                    "lists the same option twice",
+                );
+            }
+        }
+        validate_scoring(v, field, self.points, self.time_bonus.as_ref());
+    }
+
+    pub fn settings(&self) -> QuizSettings {
+        scoring(self.points, self.time_bonus.as_ref())
+    }
+}
+
+fn validate_scoring(v: &mut Validator, field: &str, points: i32, time_bonus: Option<&TimeBonus>) {
+    if !(0..=MAX_POINTS).contains(&points) {
+        v.add(
+            format!("{}.points", field),
+            format!("must be between 0 and {}", MAX_POINTS),
+        );
+    }
+    if let Some(bonus) = time_bonus {
+        if !(1..=MAX_POINTS).contains(&bonus.points) {
+            v.add(
+                format!("{}.time_bonus.points", field),
+                format!("must be between 1 and {}", MAX_POINTS),
+            );
+        }
+        if !(1..=MAX_BONUS_SECONDS).contains(&bonus.seconds) {
+            v.add(
+                format!("{}.time_bonus.seconds", field),
+                format!("must be between 1 and {}", MAX_BONUS_SECONDS),
+            );
+        }
+    }
+}
+
+fn scoring(points: i32, time_bonus: Option<&TimeBonus>) -> QuizSettings {
+    QuizSettings {
+        points,
+        bonus_points: time_bonus.map(|bonus| bonus.points),
+        bonus_seconds: time_bonus.map(|bonus| bonus.seconds),
+    }
+}
+
+fn time_bonus(settings: &QuizSettings) -> Option<TimeBonus> {
+    Some(TimeBonus {
+        points: settings.bonus_points?,
+        seconds: settings.bonus_seconds?,
+    })
+}
+
+/**
+How a quiz poll is scored, as shown with the poll
+*/
+#[derive(Serialize, ToSchema, Debug)]
+pub struct QuizInfo {
+    points: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_bonus: Option<TimeBonus>,
+    /// When the time bonus started running, once the poll has been live
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether voters can see which options are correct
+    revealed: bool,
+}
+
+impl QuizInfo {
+    pub fn of(poll: &Poll) -> Option<Self> {
+        let settings = QuizSettings::of(poll)?;
+        Some(QuizInfo {
+            points: settings.points,
+            time_bonus: time_bonus(&settings),
+            started_at: poll.quiz_started_at,
+            revealed: poll.quiz_revealed,
+        })
+    }
+}
+
+/**
+Whether `viewer` may see which options of `poll` are correct: the owner always, everyone
+else once the answers are revealed. Polls that are not quizzes have no answers to show.
+*/
+pub fn answers_visible(poll: &Poll, viewer: Option<Uuid>) -> bool {
+    poll.quiz && (poll.quiz_revealed || viewer == Some(poll.user_id))
+}
+
+/**
+Whether ballots on `poll` are final because its answers are out: once voters know the
+correct options, changing a vote would score them.
+*/
+pub fn ballots_locked(poll: &Poll) -> bool {
+    poll.quiz && poll.quiz_revealed
+}
+
+pub fn hide_answers(options: &mut [PollOption]) {
+    for option in options {
+        option.is_correct = None;
+    }
+}
+
+/**
+Replaces the quiz settings of an existing poll as a whole
+*/
+#[derive(Deserialize, ToSchema)]
+pub struct SetQuizRequest {
+    /// Options of the poll that count as correct
+    correct_option_ids: Vec<Uuid>,
+    #[serde(default = "default_points")]
+    points: i32,
+    time_bonus: Option<TimeBonus>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RevealAnswersRequest {
+    revealed: bool,
+}
+
+async fn find_poll(pool: &PgPool, poll_id: Uuid) -> WebResult<Poll> {
+    polls::get_poll(pool, poll_id).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => Error::PollNotFound,
+        _ => Error::Database(e),
+    })
+}
+
+async fn find_quiz(pool: &PgPool, poll_id: Uuid) -> WebResult<Poll> {
+    let poll = find_poll(pool, poll_id).await?;
+    if !poll.quiz {
+        return Err(Error::NotAQuiz);
+    }
+    Ok(poll)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/polls/{poll_id}/quiz",
+    tag = "quiz",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = SetQuizRequest,
+    responses(
+        (status = 204, description = "The poll is a quiz with these answers; they are hidden \
+            from voters until revealed"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "Answers or scoring failed validation", body = ErrorBody),
+    )
+)]
+pub async fn set_quiz(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<SetQuizRequest>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    let poll = find_poll(&pool, poll_id).await?;
+    let option_ids = polls::get_poll_option_ids(&pool, poll_id)
+        .await
+        .map_err(Error::Database)?;
+
+    let mut v = Validator::new();
+    if !Question::from(&poll).is_choice() {
+        v.add("poll_id", "only choice questions can be quizzes");
+    }
+    if poll.secret_ballot {
+        v.add("poll_id", "a secret ballot cannot be a quiz");
+    }
+    if req.correct_option_ids.is_empty() {
+        v.add("correct_option_ids", "must not be empty");
+    }
+    for (i, option_id) in req.correct_option_ids.iter().enumerate() {
+        if !option_ids.contains(option_id) {
+            v.add(
+                format!("correct_option_ids[{}]", i),
+                "does not belong to this poll",
+            );
+        }
+    }
+    validate_scoring(&mut v, "quiz", req.points, req.time_bonus.as_ref());
+    v.finish()?;
+
+    let settings = scoring(req.points, req.time_bonus.as_ref());
+    polls::set_quiz(&pool, poll_id, Some(&settings), &req.correct_option_ids)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}/quiz",
+    tag = "quiz",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 204, description = "The poll is no longer a quiz and stops counting towards \
+            leaderboards"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn remove_quiz(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    polls::set_quiz(&pool, poll_id, None, &[])
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/polls/{poll_id}/quiz/reveal",
+    tag = "quiz",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = RevealAnswersRequest,
+    responses(
+        (status = 204, description = "Voters now see, or no longer see, the correct options, and \
+            the poll counts towards public leaderboards while they do"),
+        (status = 400, description = "The poll is not a quiz", body = ErrorBody),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn reveal_answers(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<RevealAnswersRequest>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    find_quiz(&pool, poll_id).await?;
+    polls::set_quiz_revealed(&pool, poll_id, req.revealed)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/quiz/scores",
+    tag = "quiz",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 200, description = "Every voter's score on this question, best first", body = [QuizScore]),
+        (status = 400, description = "The poll is not a quiz", body = ErrorBody),
+        (status = 403, description = "The answers are not revealed yet", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn get_quiz_scores(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let poll = find_quiz(&pool, poll_id.into_inner()).await?;
+    if !answers_visible(&poll, session_user(&session)) {
+        return Err(Error::ResultsHidden);
+    }
+    let scores = quiz::get_scores(pool.get_ref(), &[poll.id], true)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(scores))
+}
+
+/**
+Standings over the quiz polls of a live session
+*/
+#[derive(Serialize, ToSchema)]
+pub struct Leaderboard {
+    session_id: Uuid,
+    title: String,
+    /// Set when only polls with revealed answers count, as for everyone but the presenter
+    revealed_only: bool,
+    scores: Vec<QuizScore>,
+}
+
+async fn leaderboard(
+    pool: &PgPool,
+    session_id: Uuid,
+    viewer: Option<Uuid>,
+) -> WebResult<Leaderboard> {
+    let live = live::get_live_session(pool, session_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::LiveSessionNotFound,
+            _ => Error::Database(e),
+        })?;
+    let poll_ids = live::get_live_poll_ids(pool, live.id)
+        .await
+        .map_err(Error::Database)?;
+    let revealed_only = viewer != Some(live.user_id);
+    let scores = quiz::get_scores(pool, &poll_ids, !revealed_only)
+        .await
+        .map_err(Error::Database)?;
+    Ok(Leaderboard {
+        session_id: live.id,
+        title: live.title,
+        revealed_only,
+        scores,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live-sessions/{session_id}/leaderboard",
+    tag = "quiz",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "Scores summed over the session's quiz polls; the audience \
+            only sees polls whose answers are revealed", body = Leaderboard),
+        (status = 404, description = "Live session not found", body = ErrorBody),
+    )
+)]
+pub async fn get_leaderboard(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let board = leaderboard(&pool, session_id.into_inner(), session_user(&session)).await?;
+    Ok(HttpResponse::Ok().json(board))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live-sessions/{session_id}/leaderboard/stream",
+    tag = "quiz",
+    params(("session_id" = Uuid, Path, description = "Live session id")),
+    responses(
+        (status = 200, description = "Server-sent events; a `data:` frame carrying the Leaderboard \
+            is sent on connect and whenever the standings change. Failures arrive as an `error` \
+            event carrying an ErrorBody",
+            content_type = "text/event-stream", body = Leaderboard),
+    )
+)]
+pub async fn stream_leaderboard(
+    session_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> impl Responder {
+    let session_id = session_id.into_inner();
+    let viewer = session_user(&session);
+    let request_id = request_id::current();
+    let mut interval = tokio::time::interval(Duration::from_secs(2));
+
+    let stream = async_stream::stream! {
+        let mut last: Option<String> = None;
+        loop {
+            interval.tick().await;
+
+            let board = match leaderboard(&pool, session_id, viewer).await {
+                Ok(board) => board,
+                Err(e) => {
+                    yield Result::<web::Bytes, Box<dyn std::error::Error>>::Ok(sse_error(e, request_id));
+                    return;
+                }
+            };
+            let data = serde_json::to_string(&board).unwrap();
+            if last.as_ref() == Some(&data) {
+                continue;
+            }
+            yield Ok(web::Bytes::from(format!("data: {}\n\n", data)));
+            last = Some(data);
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revealed_quizzes_lock_their_ballots() {
+        let mut poll = Poll::fixture();
+        assert!(!ballots_locked(&poll));
+        poll.quiz_revealed = true;
+        assert!(
+            !ballots_locked(&poll),
+            "only quizzes have answers to reveal"
+        );
+        poll.quiz = true;
+        assert!(ballots_locked(&poll));
+        poll.quiz_revealed = false;
+        assert!(!ballots_locked(&poll));
+    }
+
+    #[test]
+    fn only_the_owner_sees_unrevealed_answers() {
+        let mut poll = Poll::fixture();
+        poll.quiz = true;
+        let voter = Some(Uuid::new_v4());
+        assert!(answers_visible(&poll, Some(poll.user_id)));
+        assert!(!answers_visible(&poll, voter));
+        assert!(!answers_visible(&poll, None));
+        poll.quiz_revealed = true;
+        assert!(answers_visible(&poll, voter));
+        assert!(answers_visible(&poll, None));
+    }
+}
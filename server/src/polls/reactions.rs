@@ -0,0 +1,355 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, WebResult},
+        validate_session::validate_session,
+    },
+    db::{
+        polls::{self, Poll},
+        reactions::{self, ReactionCount},
+    },
+    polls::validation::Validator,
+};
+use actix_session::Session;
+use actix_web::{
+    web::{self, Data, Json, Path, Query},
+    HttpResponse,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::env;
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+const DEFAULT_EMOJIS: &str = "👍,👏,❤️,😂,😮,🎉";
+
+/**
+Which emoji the audience may send, how reactions are counted and how many each user may
+send. Loaded once at startup from the environment.
+*/
+#[derive(Debug, Clone)]
+pub struct ReactionConfig {
+    /// Accepted emoji, in the order clients should offer them
+    pub emojis: Vec<String>,
+    /// Length of the windows reactions are counted and broadcast in
+    pub window_seconds: i64,
+    /// Reactions a user may send on one poll per `rate_seconds`
+    pub rate_limit: i64,
+    pub rate_seconds: i64,
+}
+
+fn positive_env(name: &str, default: i64) -> i64 {
+    match env::var(name) {
+        Ok(value) => match value.parse() {
+            Ok(value) if value > 0 => value,
+            _ => panic!("{} must be a positive number", name),
+        },
+        Err(_) => default,
+    }
+}
+
+impl ReactionConfig {
+    pub fn from_env() -> Self {
+        let emojis = env::var("REACTION_EMOJIS").unwrap_or_else(|_| DEFAULT_EMOJIS.to_string());
+        ReactionConfig {
+            emojis: emojis
+                .split(',')
+                .map(|emoji| emoji.trim().to_string())
+                .filter(|emoji| !emoji.is_empty())
+                .collect(),
+            window_seconds: positive_env("REACTION_WINDOW_SECONDS", 5),
+            rate_limit: positive_env("REACTION_RATE_LIMIT", 10),
+            rate_seconds: positive_env("REACTION_RATE_SECONDS", 10),
+        }
+    }
+
+    /// Start of the window `at` falls in
+    pub fn window_start(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let seconds = at.timestamp();
+        Utc.timestamp_opt(seconds - seconds.rem_euclid(self.window_seconds), 0)
+            .unwrap()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ReactRequest {
+    /// One of the configured emoji
+    emoji: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EmojiCount {
+    emoji: String,
+    count: i64,
+}
+
+/**
+The reactions a poll received during one time window
+*/
+#[derive(Serialize, ToSchema)]
+pub struct ReactionWindow {
+    poll_id: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    total: i64,
+    /// Most sent emoji first; emoji nobody sent are left out
+    counts: Vec<EmojiCount>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReactionSummary {
+    /// Emoji the audience may send
+    emojis: Vec<String>,
+    window_seconds: i64,
+    /// Oldest first; the last one is still running
+    windows: Vec<ReactionWindow>,
+}
+
+fn default_windows() -> i64 {
+    12
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ReactionWindowsQuery {
+    /// How many of the latest windows to return, at most 120
+    #[serde(default = "default_windows")]
+    windows: i64,
+}
+
+/**
+Groups per-emoji counts into the windows starting at each of `starts`. Windows without
+counts are kept empty.
+*/
+fn group_windows(
+    poll_id: Uuid,
+    config: &ReactionConfig,
+    starts: impl Iterator<Item = DateTime<Utc>>,
+    counts: Vec<ReactionCount>,
+) -> Vec<ReactionWindow> {
+    let length = chrono::Duration::seconds(config.window_seconds);
+    let mut windows: Vec<ReactionWindow> = starts
+        .map(|start| ReactionWindow {
+            poll_id,
+            start,
+            end: start + length,
+            total: 0,
+            counts: Vec::new(),
+        })
+        .collect();
+    for count in counts {
+        if let Some(window) = windows.iter_mut().find(|w| w.start == count.window_start) {
+            window.total += count.count;
+            window.counts.push(EmojiCount {
+                emoji: count.emoji,
+                count: count.count,
+            });
+        }
+    }
+    windows
+}
+
+async fn find_poll(pool: &PgPool, poll_id: Uuid) -> WebResult<Poll> {
+    polls::get_poll(pool, poll_id).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => Error::PollNotFound,
+        _ => Error::Database(e),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/{poll_id}/reactions",
+    tag = "reactions",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = ReactRequest,
+    responses(
+        (status = 204, description = "Reaction counted"),
+        (status = 400, description = "Poll is closed", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "The emoji is not one of the configured ones", body = ErrorBody),
+        (status = 429, description = "The caller sent too many reactions on this poll lately", body = ErrorBody),
+    )
+)]
+pub async fn react(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    config: Data<ReactionConfig>,
+    req: Json<ReactRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let poll = find_poll(&pool, poll_id.into_inner()).await?;
+
+    let emoji = req.emoji.trim();
+    let mut v = Validator::new();
+    if !config.emojis.iter().any(|allowed| allowed == emoji) {
+        v.add(
+            "emoji",
+            format!("must be one of {}", config.emojis.join(" ")),
+        );
+    }
+    v.finish()?;
+    if !poll.is_active {
+        return Err(Error::PollClosed);
+    }
+
+    let since = Utc::now() - chrono::Duration::seconds(config.rate_seconds);
+    let added = reactions::add_reaction(&pool, poll.id, user_id, emoji, config.rate_limit, since)
+        .await
+        .map_err(Error::Database)?;
+    if !added {
+        return Err(Error::RateLimited);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/reactions",
+    tag = "reactions",
+    params(("poll_id" = Uuid, Path, description = "Poll id"), ReactionWindowsQuery),
+    responses(
+        (status = 200, description = "Reaction counts of the latest windows", body = ReactionSummary),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "Too many windows requested", body = ErrorBody),
+    )
+)]
+pub async fn get_reactions(
+    poll_id: Path<Uuid>,
+    query: Query<ReactionWindowsQuery>,
+    session: Session,
+    pool: Data<PgPool>,
+    config: Data<ReactionConfig>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let poll = find_poll(&pool, poll_id.into_inner()).await?;
+    if poll.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    let mut v = Validator::new();
+    if !(1..=120).contains(&query.windows) {
+        v.add("windows", "must be between 1 and 120");
+    }
+    v.finish()?;
+
+    let now = Utc::now();
+    let length = chrono::Duration::seconds(config.window_seconds);
+    let from = config.window_start(now) - length * (query.windows - 1) as i32;
+    let counts =
+        reactions::get_reaction_counts(&pool, poll.id, config.window_seconds, from, now + length)
+            .await
+            .map_err(Error::Database)?;
+    let starts = (0..query.windows).map(|i| from + length * i as i32);
+    Ok(HttpResponse::Ok().json(ReactionSummary {
+        emojis: config.emojis.clone(),
+        window_seconds: config.window_seconds,
+        windows: group_windows(poll.id, &config, starts, counts),
+    }))
+}
+
+/**
+Builds a `reactions` SSE event for every window of the poll that ended since `since`
+and received reactions, then moves `since` to the start of the running window. Streams
+only send these to the poll's owner.
+*/
+pub async fn reaction_frames(
+    pool: &PgPool,
+    poll_id: Uuid,
+    config: &ReactionConfig,
+    since: &mut DateTime<Utc>,
+) -> Result<Vec<web::Bytes>, sqlx::Error> {
+    let until = config.window_start(Utc::now());
+    if until <= *since {
+        return Ok(Vec::new());
+    }
+    let counts =
+        reactions::get_reaction_counts(pool, poll_id, config.window_seconds, *since, until).await?;
+    let mut starts: Vec<DateTime<Utc>> = counts.iter().map(|count| count.window_start).collect();
+    starts.dedup();
+    *since = until;
+    Ok(group_windows(poll_id, config, starts.into_iter(), counts)
+        .into_iter()
+        .map(|window| {
+            web::Bytes::from(format!(
+                "event: reactions\ndata: {}\n\n",
+                serde_json::to_string(&window).unwrap()
+            ))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ReactionConfig {
+        ReactionConfig {
+            emojis: vec!["👍".to_string(), "🎉".to_string()],
+            window_seconds: 5,
+            rate_limit: 10,
+            rate_seconds: 10,
+        }
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn count(window_start: DateTime<Utc>, emoji: &str, count: i64) -> ReactionCount {
+        ReactionCount {
+            window_start,
+            emoji: emoji.to_string(),
+            count,
+        }
+    }
+
+    #[test]
+    fn window_start_aligns_to_the_epoch() {
+        let config = config();
+        assert_eq!(config.window_start(at(0)), at(0));
+        assert_eq!(config.window_start(at(4)), at(0));
+        assert_eq!(config.window_start(at(5)), at(5));
+        assert_eq!(config.window_start(at(-1)), at(-5));
+    }
+
+    #[test]
+    fn groups_counts_into_their_windows() {
+        let config = config();
+        let poll_id = Uuid::new_v4();
+        let counts = vec![
+            count(at(0), "👍", 3),
+            count(at(0), "🎉", 1),
+            count(at(10), "🎉", 2),
+        ];
+        let windows = group_windows(poll_id, &config, (0..3).map(|i| at(i * 5)), counts);
+
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[0].start, at(0));
+        assert_eq!(windows[0].end, at(5));
+        assert_eq!(windows[0].total, 4);
+        let emojis: Vec<(&str, i64)> = windows[0]
+            .counts
+            .iter()
+            .map(|c| (c.emoji.as_str(), c.count))
+            .collect();
+        assert_eq!(emojis, [("👍", 3), ("🎉", 1)]);
+        // Quiet windows are kept, empty
+        assert_eq!(windows[1].total, 0);
+        assert!(windows[1].counts.is_empty());
+        assert_eq!(windows[2].total, 2);
+        assert!(windows.iter().all(|window| window.poll_id == poll_id));
+    }
+
+    #[test]
+    fn drops_counts_outside_the_requested_windows() {
+        let windows = group_windows(
+            Uuid::new_v4(),
+            &config(),
+            std::iter::once(at(5)),
+            vec![count(at(0), "👍", 3), count(at(5), "👍", 1)],
+        );
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].total, 1);
+    }
+}
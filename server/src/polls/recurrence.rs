@@ -0,0 +1,239 @@
+use crate::{
+    db::{
+        polls::{self, PollSettings},
+        webhooks::WebhookEvent,
+        weights,
+    },
+    polls::webhooks,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use cron::Schedule;
+use log::{error, info, warn};
+use sqlx::PgPool;
+use std::{env, str::FromStr, time::Duration};
+use webauthn_rs::prelude::Uuid;
+
+/**
+How often a recurring poll is re-created: `daily`, `weekly`, or a cron expression.
+Cron expressions may have five fields (minute first) or the six/seven-field form with
+seconds, and are evaluated in UTC.
+*/
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    Cron(Box<Schedule>),
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        match value.to_ascii_lowercase().as_str() {
+            "daily" => return Ok(Recurrence::Daily),
+            "weekly" => return Ok(Recurrence::Weekly),
+            _ => {}
+        }
+        let expression = if value.split_whitespace().count() == 5 {
+            format!("0 {}", value)
+        } else {
+            value.to_string()
+        };
+        Schedule::from_str(&expression)
+            .map(|schedule| Recurrence::Cron(Box::new(schedule)))
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Recurrence {
+    /**
+    First run after `now`. Fixed intervals step from `anchor`, the previous scheduled run
+    (or the creation time), so a series keeps its time of day; missed runs are skipped.
+    */
+    pub fn next_run(&self, anchor: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let step = match self {
+            Recurrence::Daily => ChronoDuration::days(1),
+            Recurrence::Weekly => ChronoDuration::weeks(1),
+            Recurrence::Cron(schedule) => return schedule.after(&now).next(),
+        };
+        let mut next = anchor + step;
+        if next <= now {
+            let missed = (now - next).num_seconds() / step.num_seconds() + 1;
+            next += step * missed as i32;
+        }
+        Some(next)
+    }
+}
+
+/**
+Creates the next instance of the most overdue recurring poll and closes the current one,
+all in one transaction together with their webhook events. Returns the ids of the closed and created polls, or `None` when
+nothing is due.
+*/
+async fn roll_over_next(pool: &PgPool) -> Result<Option<(Uuid, Option<Uuid>)>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let Some(poll) = polls::lock_due_recurring_poll(&mut tx).await? else {
+        return Ok(None);
+    };
+    let poll_id = poll.id;
+    let rule = poll.recurrence.as_deref().unwrap_or_default();
+    let next_run_at = match rule.parse::<Recurrence>() {
+        Ok(recurrence) => {
+            recurrence.next_run(poll.next_run_at.unwrap_or(poll.created_at), Utc::now())
+        }
+        Err(e) => {
+            warn!(
+                "poll {} has an unreadable recurrence {:?}: {}",
+                poll_id, rule, e
+            );
+            None
+        }
+    };
+    let Some(next_run_at) = next_run_at else {
+        // The rule will never fire again; end the series here
+        if polls::end_recurring_instance(&mut tx, poll_id).await? {
+            webhooks::notify(&mut tx, poll_id, WebhookEvent::Closed, None).await?;
+        }
+        tx.commit().await?;
+        return Ok(Some((poll_id, None)));
+    };
+
+    let options = polls::get_poll_options_data(&mut *tx, poll_id)
+        .await?
+        .into_iter()
+        .map(|option| polls::NewPollOption {
+            option_text: option.option_text,
+            description: option.description,
+            image_url: option.image_url,
+            image_asset_id: option.image_asset_id,
+            color: option.color,
+            is_correct: option.is_correct.unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    let settings = PollSettings {
+        secret_ballot: poll.secret_ballot,
+        results_visibility: poll.results_visibility,
+        recurrence: poll.recurrence.clone(),
+        next_run_at: Some(next_run_at),
+        previous_poll_id: Some(poll_id),
+        weighted: poll.weighted,
+        default_weight: Some(poll.default_weight),
+        rules: polls::OutcomeRules::from(&poll),
+        question: polls::QuestionSettings::from(&poll),
+        quiz: polls::QuizSettings::of(&poll),
+    };
+    let next_id = Uuid::new_v4();
+    // An instance the owner already closed by hand had its event then
+    let closed = polls::end_recurring_instance(&mut tx, poll_id).await?;
+    polls::create_poll_with_options(
+        &mut *tx,
+        next_id,
+        poll.user_id,
+        &poll.title,
+        &poll.description,
+        &settings,
+        &options,
+    )
+    .await?;
+    weights::copy_weights(&mut tx, poll_id, next_id).await?;
+    if closed {
+        webhooks::notify(&mut tx, poll_id, WebhookEvent::Closed, None).await?;
+    }
+    webhooks::notify(&mut tx, next_id, WebhookEvent::Created, None).await?;
+    tx.commit().await?;
+    Ok(Some((poll_id, Some(next_id))))
+}
+
+/**
+How often the scheduler looks for due polls: `RECURRENCE_CHECK_SECS`, default 60.
+*/
+pub fn check_interval_from_env() -> Duration {
+    let seconds = env::var("RECURRENCE_CHECK_SECS")
+        .map(|value| {
+            value
+                .parse()
+                .expect("RECURRENCE_CHECK_SECS must be a number")
+        })
+        .unwrap_or(60);
+    Duration::from_secs(seconds)
+}
+
+/**
+Background task that rolls recurring polls over once their `next_run_at` has passed.
+*/
+pub async fn run_scheduler(pool: PgPool, check_interval: Duration) {
+    let mut interval = tokio::time::interval(check_interval);
+    loop {
+        interval.tick().await;
+        loop {
+            match roll_over_next(&pool).await {
+                Ok(Some((closed, Some(created)))) => {
+                    info!(
+                        "recurring poll {} closed, next instance is {}",
+                        closed, created
+                    );
+                }
+                Ok(Some((closed, None))) => {
+                    info!("recurring poll {} ended its series", closed);
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    error!("recurring poll rollover failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_named_rules_in_any_case() {
+        assert!(matches!("daily".parse(), Ok(Recurrence::Daily)));
+        assert!(matches!(" Weekly ".parse(), Ok(Recurrence::Weekly)));
+    }
+
+    #[test]
+    fn parses_cron_with_and_without_seconds() {
+        assert!(matches!("30 9 * * Mon".parse(), Ok(Recurrence::Cron(_))));
+        assert!(matches!("0 30 9 * * *".parse(), Ok(Recurrence::Cron(_))));
+    }
+
+    #[test]
+    fn rejects_unreadable_rules() {
+        assert!("hourly".parse::<Recurrence>().is_err());
+        assert!("61 * * * *".parse::<Recurrence>().is_err());
+        assert!("".parse::<Recurrence>().is_err());
+    }
+
+    #[test]
+    fn fixed_intervals_step_from_the_anchor() {
+        let daily = Recurrence::Daily;
+        assert_eq!(daily.next_run(at(1, 9, 0), at(1, 10, 0)), Some(at(2, 9, 0)));
+        let weekly = Recurrence::Weekly;
+        assert_eq!(weekly.next_run(at(1, 9, 0), at(3, 0, 0)), Some(at(8, 9, 0)));
+    }
+
+    #[test]
+    fn fixed_intervals_skip_missed_runs() {
+        let daily = Recurrence::Daily;
+        assert_eq!(daily.next_run(at(1, 9, 0), at(5, 12, 0)), Some(at(6, 9, 0)));
+        // A run due right now has already happened
+        assert_eq!(daily.next_run(at(1, 9, 0), at(2, 9, 0)), Some(at(3, 9, 0)));
+    }
+
+    #[test]
+    fn five_field_cron_runs_on_the_minute() {
+        let cron: Recurrence = "30 9 * * *".parse().unwrap();
+        assert_eq!(cron.next_run(at(1, 0, 0), at(1, 10, 0)), Some(at(2, 9, 30)));
+        assert_eq!(cron.next_run(at(1, 0, 0), at(1, 9, 0)), Some(at(1, 9, 30)));
+    }
+}
@@ -0,0 +1,535 @@
+use crate::db::{
+    polls::{PassThreshold, Poll, PollOption},
+    weights::{self, Electorate},
+};
+use serde::Serialize;
+use sqlx::PgExecutor;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+/// z-score of a two-sided 95% confidence level
+const Z_95: f64 = 1.959964;
+
+/**
+Range a share is expected to fall in, in percent
+*/
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq)]
+pub struct ShareInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ShareInterval {
+    /**
+    95% Wilson score interval for `votes` out of `total`. Unlike the normal approximation
+    it stays inside 0–100% and behaves for small polls; with no votes at all it spans
+    everything.
+    */
+    fn wilson(votes: i64, total: i64) -> Self {
+        if total == 0 {
+            return ShareInterval {
+                lower: 0.0,
+                upper: 100.0,
+            };
+        }
+        let n = total as f64;
+        let p = votes as f64 / n;
+        let z2 = Z_95 * Z_95;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let spread = Z_95 / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        // The bounds are exactly 0 and 100 at the extremes; avoid rounding residue there
+        ShareInterval {
+            lower: if votes == 0 {
+                0.0
+            } else {
+                ((center - spread) * 100.0).max(0.0)
+            },
+            upper: if votes == total {
+                100.0
+            } else {
+                ((center + spread) * 100.0).min(100.0)
+            },
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone)]
+pub struct OptionResult {
+    pub option_id: Uuid,
+    /// Headcount of voters who chose the option
+    pub votes: i64,
+    /// Summed weight of those voters, for weighted polls
+    pub weight: Option<f64>,
+    /// Percentage of all votes, by weight in weighted polls
+    pub share: f64,
+    /// 95% confidence interval of the headcount share
+    pub confidence_interval: ShareInterval,
+    /// 1 for the most votes (or weight); tied options share a rank
+    pub rank: usize,
+}
+
+/**
+Outcome of a poll, computed in one place for `get_poll`, the results stream and exports.
+*/
+#[derive(Serialize, ToSchema, Debug, Clone)]
+pub struct ResultStats {
+    /// Headcount of all voters
+    pub total_votes: i64,
+    /// Summed weight of all votes, for weighted polls; the outcome is decided by weight then
+    pub total_weight: Option<f64>,
+    /// One entry per option, in display order
+    pub options: Vec<OptionResult>,
+    /// Options with the most votes, empty until the first vote; more than one is a tie
+    pub leaders: Vec<Uuid>,
+    pub is_tie: bool,
+    /// The only leader, absent on a tie or without votes
+    pub winner: Option<Uuid>,
+    /// Options with the second most votes, once they have any
+    pub runners_up: Vec<Uuid>,
+    /// Votes (or weight) separating first and second place, 0 on a tie
+    pub margin: Option<f64>,
+    /// The margin in percentage points
+    pub margin_share: Option<f64>,
+    /// Voters allowed to take part, for polls that restrict who may vote
+    pub eligible_voters: Option<i64>,
+    /// Percentage of the eligible voters who voted
+    pub turnout: Option<f64>,
+    /// Whether the result counts under the poll's quorum and pass threshold
+    pub outcome: Outcome,
+}
+
+impl ResultStats {
+    /**
+    Weighted polls rank options by the summed weight of their votes instead of headcount;
+    everything else about the outcome follows from that tally. `electorate` is known for
+    polls only their participants may vote in, see [electorate].
+    */
+    pub fn compute(poll: &Poll, options: &[PollOption], electorate: Option<Electorate>) -> Self {
+        let weighted = poll.weighted;
+        let eligible_voters = electorate.map(|electorate| electorate.voters);
+        let votes: Vec<i64> = options
+            .iter()
+            .map(|option| option.votes_count.unwrap_or(0) as i64)
+            .collect();
+        let total_votes: i64 = votes.iter().sum();
+        let tallies: Vec<f64> = if weighted {
+            options
+                .iter()
+                .map(|option| option.weighted_votes.unwrap_or(0.0))
+                .collect()
+        } else {
+            votes.iter().map(|&count| count as f64).collect()
+        };
+        let total: f64 = tallies.iter().sum();
+        let share = |tally: f64| {
+            if total > 0.0 {
+                tally / total * 100.0
+            } else {
+                0.0
+            }
+        };
+
+        let mut distinct: Vec<f64> = tallies.clone();
+        distinct.sort_unstable_by(|a, b| b.total_cmp(a));
+        distinct.dedup();
+        let first = distinct.first().copied().filter(|&tally| tally > 0.0);
+        let second = distinct.get(1).copied().filter(|&tally| tally > 0.0);
+        let with_count = |tally: Option<f64>| -> Vec<Uuid> {
+            match tally {
+                Some(tally) => options
+                    .iter()
+                    .zip(&tallies)
+                    .filter(|(_, &t)| t == tally)
+                    .map(|(option, _)| option.id)
+                    .collect(),
+                None => Vec::new(),
+            }
+        };
+        let leaders = with_count(first);
+        let runners_up = with_count(second);
+        let is_tie = leaders.len() > 1;
+        let outcome = Outcome::evaluate(poll, options, &tallies, total_votes, electorate);
+
+        let margin = match (options.len(), first) {
+            (0 | 1, _) | (_, None) => None,
+            (_, Some(_)) if is_tie => Some(0.0),
+            (_, Some(top)) => Some(top - distinct.get(1).copied().unwrap_or(0.0)),
+        };
+
+        let results = options
+            .iter()
+            .zip(votes.iter().zip(&tallies))
+            .map(|(option, (&count, &tally))| OptionResult {
+                option_id: option.id,
+                votes: count,
+                weight: weighted.then_some(tally),
+                share: share(tally),
+                confidence_interval: ShareInterval::wilson(count, total_votes),
+                rank: 1 + tallies.iter().filter(|&&other| other > tally).count(),
+            })
+            .collect();
+
+        ResultStats {
+            total_votes,
+            total_weight: weighted.then_some(total),
+            options: results,
+            winner: if is_tie {
+                None
+            } else {
+                leaders.first().copied()
+            },
+            leaders,
+            is_tie,
+            runners_up,
+            margin_share: margin.map(share),
+            margin,
+            eligible_voters,
+            turnout: eligible_voters
+                .filter(|&eligible| eligible > 0)
+                .map(|eligible| total_votes as f64 / eligible as f64 * 100.0),
+            outcome,
+        }
+    }
+}
+
+/**
+The poll's electorate, for polls only their participants may vote in
+*/
+pub async fn electorate<'e, E>(executor: E, poll: &Poll) -> Result<Option<Electorate>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    if !poll.participants_only {
+        return Ok(None);
+    }
+    weights::get_electorate(executor, poll.id).await.map(Some)
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutcomeStatus {
+    /// Quorum is met and an option reaches the pass threshold
+    Decided,
+    /// Too few voters took part
+    FailedQuorum,
+    /// Quorum is met but no option reaches the pass threshold
+    NoMajority,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone)]
+pub struct Outcome {
+    pub status: OutcomeStatus,
+    pub pass_threshold: PassThreshold,
+    /// Voters needed for quorum, when the poll sets one
+    pub quorum: Option<i64>,
+    pub quorum_met: bool,
+    /// The option that passed
+    pub decided_option: Option<Uuid>,
+    /**
+    The status can no longer change: the poll is closed, or the electorate is known and
+    its remaining voters could not change it even by all voting alike. Cast votes are
+    assumed to stand.
+    */
+    pub locked: bool,
+}
+
+impl PassThreshold {
+    /// Whether `tally` out of `total` passes, `best_other` being the strongest other option
+    fn passes(self, tally: f64, total: f64, best_other: f64) -> bool {
+        if tally <= 0.0 {
+            return false;
+        }
+        match self {
+            PassThreshold::Plurality => tally > best_other,
+            PassThreshold::Majority => tally * 2.0 > total,
+            PassThreshold::TwoThirds => tally * 3.0 >= total * 2.0,
+            PassThreshold::Unanimous => tally >= total,
+        }
+    }
+}
+
+impl Outcome {
+    fn evaluate(
+        poll: &Poll,
+        options: &[PollOption],
+        tallies: &[f64],
+        total_votes: i64,
+        electorate: Option<Electorate>,
+    ) -> Self {
+        let threshold = poll.pass_threshold;
+        let total: f64 = tallies.iter().sum();
+        let best_other = |index: usize| {
+            tallies
+                .iter()
+                .enumerate()
+                .filter(|&(other, _)| other != index)
+                .map(|(_, &tally)| tally)
+                .fold(0.0, f64::max)
+        };
+
+        let from_share = poll.quorum_percent.map(|percent| {
+            let voters = electorate.map(|electorate| electorate.voters).unwrap_or(0);
+            (percent / 100.0 * voters as f64).ceil() as i64
+        });
+        let quorum = match (poll.quorum_votes.map(i64::from), from_share) {
+            (Some(votes), Some(share)) => Some(votes.max(share)),
+            (votes, share) => votes.or(share),
+        };
+        let quorum_met = quorum.is_none_or(|quorum| total_votes >= quorum);
+
+        let decided =
+            (0..tallies.len()).find(|&i| threshold.passes(tallies[i], total, best_other(i)));
+        let status = match (quorum_met, decided) {
+            (false, _) => OutcomeStatus::FailedQuorum,
+            (true, Some(_)) => OutcomeStatus::Decided,
+            (true, None) => OutcomeStatus::NoMajority,
+        };
+
+        let locked = match electorate {
+            _ if !poll.is_active => true,
+            None => false,
+            // Without options nothing is decided, however many voters remain
+            Some(_) if tallies.is_empty() => false,
+            Some(electorate) => {
+                let remaining = if poll.weighted {
+                    electorate.remaining_weight
+                } else {
+                    electorate.remaining_voters as f64
+                };
+                match (status, decided) {
+                    (OutcomeStatus::FailedQuorum, _) => quorum
+                        .is_some_and(|quorum| total_votes + electorate.remaining_voters < quorum),
+                    // The remaining votes all go to the strongest rival, or to no one that counts
+                    (OutcomeStatus::Decided, Some(i)) => {
+                        threshold.passes(tallies[i], total + remaining, best_other(i) + remaining)
+                    }
+                    // Even all remaining votes for one option would not carry it
+                    _ => (0..tallies.len()).all(|i| {
+                        !threshold.passes(tallies[i] + remaining, total + remaining, best_other(i))
+                    }),
+                }
+            }
+        };
+
+        Outcome {
+            status,
+            pass_threshold: threshold,
+            quorum,
+            quorum_met,
+            decided_option: decided.filter(|_| quorum_met).map(|i| options[i].id),
+            locked,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_interval(interval: ShareInterval, lower: f64, upper: f64) {
+        assert!(
+            (interval.lower - lower).abs() < 0.01 && (interval.upper - upper).abs() < 0.01,
+            "expected {}–{}, got {:?}",
+            lower,
+            upper,
+            interval
+        );
+    }
+
+    #[test]
+    fn wilson_without_votes_spans_everything() {
+        assert_interval(ShareInterval::wilson(0, 0), 0.0, 100.0);
+    }
+
+    #[test]
+    fn wilson_matches_known_intervals() {
+        assert_interval(ShareInterval::wilson(5, 10), 23.66, 76.34);
+        assert_interval(ShareInterval::wilson(1, 4), 4.56, 69.94);
+        assert_interval(ShareInterval::wilson(50, 100), 40.38, 59.62);
+    }
+
+    #[test]
+    fn wilson_is_exact_at_the_extremes() {
+        let none = ShareInterval::wilson(0, 10);
+        assert_eq!(none.lower, 0.0);
+        assert_interval(none, 0.0, 27.75);
+        let all = ShareInterval::wilson(10, 10);
+        assert_eq!(all.upper, 100.0);
+        assert_interval(all, 72.25, 100.0);
+    }
+
+    #[test]
+    fn wilson_stays_within_bounds() {
+        for total in 1..50 {
+            for votes in 0..=total {
+                let interval = ShareInterval::wilson(votes, total);
+                let share = votes as f64 / total as f64 * 100.0;
+                assert!(0.0 <= interval.lower && interval.lower <= share);
+                assert!(share <= interval.upper && interval.upper <= 100.0);
+            }
+        }
+    }
+
+    fn options(poll: &Poll, count: usize) -> Vec<PollOption> {
+        (0..count)
+            .map(|position| PollOption {
+                id: Uuid::new_v4(),
+                poll_id: poll.id,
+                option_text: format!("Option {}", position),
+                votes_count: Some(0),
+                weighted_votes: Some(0.0),
+                position: position as i32,
+                description: None,
+                image_url: None,
+                image_asset_id: None,
+                color: None,
+                is_correct: None,
+            })
+            .collect()
+    }
+
+    fn electorate(voters: i64, remaining_voters: i64) -> Option<Electorate> {
+        Some(Electorate {
+            voters,
+            remaining_voters,
+            remaining_weight: remaining_voters as f64,
+        })
+    }
+
+    fn evaluate(poll: &Poll, tallies: &[f64], electorate: Option<Electorate>) -> Outcome {
+        let options = options(poll, tallies.len());
+        let total_votes = tallies.iter().sum::<f64>() as i64;
+        Outcome::evaluate(poll, &options, tallies, total_votes, electorate)
+    }
+
+    #[test]
+    fn plurality_decides_for_the_leader() {
+        let poll = Poll::fixture();
+        let options = options(&poll, 2);
+        let outcome = Outcome::evaluate(&poll, &options, &[3.0, 1.0], 4, None);
+        assert_eq!(outcome.status, OutcomeStatus::Decided);
+        assert_eq!(outcome.decided_option, Some(options[0].id));
+        assert!(outcome.quorum_met);
+        assert!(!outcome.locked);
+    }
+
+    #[test]
+    fn a_tie_has_no_majority() {
+        let outcome = evaluate(&Poll::fixture(), &[2.0, 2.0], None);
+        assert_eq!(outcome.status, OutcomeStatus::NoMajority);
+        assert_eq!(outcome.decided_option, None);
+    }
+
+    #[test]
+    fn thresholds_above_plurality_need_their_share() {
+        let majority = Poll {
+            pass_threshold: PassThreshold::Majority,
+            ..Poll::fixture()
+        };
+        assert_eq!(
+            evaluate(&majority, &[3.0, 2.0, 1.0], None).status,
+            OutcomeStatus::NoMajority
+        );
+        assert_eq!(
+            evaluate(&majority, &[4.0, 2.0, 1.0], None).status,
+            OutcomeStatus::Decided
+        );
+        let two_thirds = Poll {
+            pass_threshold: PassThreshold::TwoThirds,
+            ..Poll::fixture()
+        };
+        assert_eq!(
+            evaluate(&two_thirds, &[5.0, 3.0], None).status,
+            OutcomeStatus::NoMajority
+        );
+        assert_eq!(
+            evaluate(&two_thirds, &[6.0, 3.0], None).status,
+            OutcomeStatus::Decided
+        );
+        let unanimous = Poll {
+            pass_threshold: PassThreshold::Unanimous,
+            ..Poll::fixture()
+        };
+        assert_eq!(
+            evaluate(&unanimous, &[5.0, 1.0], None).status,
+            OutcomeStatus::NoMajority
+        );
+        assert_eq!(
+            evaluate(&unanimous, &[5.0, 0.0], None).status,
+            OutcomeStatus::Decided
+        );
+    }
+
+    #[test]
+    fn a_missed_quorum_decides_nothing() {
+        let poll = Poll {
+            quorum_votes: Some(5),
+            ..Poll::fixture()
+        };
+        let outcome = evaluate(&poll, &[3.0, 1.0], None);
+        assert_eq!(outcome.status, OutcomeStatus::FailedQuorum);
+        assert_eq!(outcome.quorum, Some(5));
+        assert!(!outcome.quorum_met);
+        assert_eq!(outcome.decided_option, None);
+    }
+
+    #[test]
+    fn the_stricter_quorum_applies() {
+        let poll = Poll {
+            participants_only: true,
+            quorum_votes: Some(3),
+            quorum_percent: Some(50.0),
+            ..Poll::fixture()
+        };
+        assert_eq!(
+            evaluate(&poll, &[3.0, 1.0], electorate(10, 6)).quorum,
+            Some(5)
+        );
+        assert_eq!(
+            evaluate(&poll, &[3.0, 1.0], electorate(4, 0)).quorum,
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn closed_polls_are_locked() {
+        let poll = Poll {
+            is_active: false,
+            ..Poll::fixture()
+        };
+        assert!(evaluate(&poll, &[1.0, 1.0], None).locked);
+        assert!(evaluate(&poll, &[], None).locked);
+    }
+
+    #[test]
+    fn open_polls_lock_once_the_remaining_voters_cannot_change_the_status() {
+        let poll = Poll {
+            participants_only: true,
+            ..Poll::fixture()
+        };
+        // The leader keeps the lead even if the last voter backs the runner-up
+        assert!(evaluate(&poll, &[5.0, 1.0], electorate(7, 1)).locked);
+        assert!(!evaluate(&poll, &[2.0, 1.0], electorate(4, 1)).locked);
+        // A tie stays undecided only once nobody is left to break it
+        assert!(evaluate(&poll, &[2.0, 2.0], electorate(4, 0)).locked);
+        assert!(!evaluate(&poll, &[2.0, 2.0], electorate(5, 1)).locked);
+
+        let quorum = Poll {
+            quorum_votes: Some(5),
+            ..poll
+        };
+        assert!(evaluate(&quorum, &[2.0, 1.0], electorate(4, 1)).locked);
+        assert!(!evaluate(&quorum, &[2.0, 1.0], electorate(6, 3)).locked);
+    }
+
+    #[test]
+    fn polls_without_options_are_never_locked_while_open() {
+        let poll = Poll {
+            participants_only: true,
+            ..Poll::fixture()
+        };
+        let outcome = evaluate(&poll, &[], electorate(3, 0));
+        assert_eq!(outcome.status, OutcomeStatus::NoMajority);
+        assert!(!outcome.locked);
+    }
+}
@@ -0,0 +1,917 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, WebResult},
+        validate_session::{session_user, validate_session},
+    },
+    db::{
+        polls::{self, Poll, QuestionKind},
+        surveys::{self, QuestionCondition, Survey},
+    },
+    polls::{
+        manage_polls::{poll_data, PollData},
+        options::{option_write_error, PollOptionInput},
+        questions::{check_answer, record_answer, Question},
+        validation::{PollLimits, Validator},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+#[derive(Deserialize, ToSchema)]
+pub struct SurveyQuestionInput {
+    title: String,
+    #[serde(default)]
+    description: String,
+    /// What the question asks for, a choice among its options unless set
+    #[serde(default)]
+    question: Question,
+    /// Options to pick from; must be empty for questions other than `choice`
+    #[serde(default)]
+    options: Vec<PollOptionInput>,
+    /// A response has to answer this question whenever it is asked
+    #[serde(default)]
+    required: bool,
+    /// Ask this question only when an earlier answer matches
+    #[serde(default)]
+    show_if: Option<ShowIfInput>,
+}
+
+/**
+Refers to an earlier question by its index in `questions`. A choice question matches when
+one of `options` (indexes into its options) is picked, a scale or number question when
+the value is within `min`..=`max`, and a text question on any answer.
+*/
+#[derive(Deserialize, ToSchema)]
+pub struct ShowIfInput {
+    question: usize,
+    #[serde(default)]
+    options: Vec<usize>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl ShowIfInput {
+    fn validate(
+        &self,
+        v: &mut Validator,
+        field: &str,
+        index: usize,
+        questions: &[SurveyQuestionInput],
+    ) {
+        if self.question >= index {
+            v.add(
+                format!("{}.question", field),
+                "must refer to an earlier question",
+            );
+            return;
+        }
+        let source = &questions[self.question];
+        match source.question {
+            Question::Choice => {
+                if self.options.is_empty() {
+                    v.add(format!("{}.options", field), "must not be empty");
+                }
+                if let Some(option) = self.options.iter().find(|&&o| o >= source.options.len()) {
+                    v.add(
+                        format!("{}.options", field),
+                        format!(
+                            "refers to option {} of a question with {}",
+                            option,
+                            source.options.len()
+                        ),
+                    );
+                }
+                if self.min.is_some() || self.max.is_some() {
+                    v.add(
+                        field,
+                        "min and max only apply to scale and number questions",
+                    );
+                }
+            }
+            Question::Scale { .. } | Question::Number { .. } => {
+                if !self.options.is_empty() {
+                    v.add(
+                        format!("{}.options", field),
+                        "only apply to choice questions",
+                    );
+                }
+                match (self.min, self.max) {
+                    (None, None) => v.add(field, "must set min or max"),
+                    (Some(min), _) if !min.is_finite() => {
+                        v.add(format!("{}.min", field), "must be a number")
+                    }
+                    (_, Some(max)) if !max.is_finite() => {
+                        v.add(format!("{}.max", field), "must be a number")
+                    }
+                    (Some(min), Some(max)) if min > max => {
+                        v.add(format!("{}.max", field), "must be at least min")
+                    }
+                    _ => {}
+                }
+            }
+            Question::Text { .. } => {
+                if !self.options.is_empty() || self.min.is_some() || self.max.is_some() {
+                    v.add(
+                        field,
+                        "a text question matches any answer and takes no options, min or max",
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateSurveyRequest {
+    title: String,
+    #[serde(default)]
+    description: String,
+    /// Hide who answered what, for every question
+    #[serde(default)]
+    secret_ballot: bool,
+    /// Applies to every question
+    #[serde(default)]
+    results_visibility: polls::ResultsVisibility,
+    /// Asked in this order
+    questions: Vec<SurveyQuestionInput>,
+}
+
+impl CreateSurveyRequest {
+    fn validate(&self, limits: &PollLimits) -> Result<(), Error> {
+        let mut v = Validator::new();
+        v.text("title", &self.title, limits.max_title_len);
+        v.optional_text("description", &self.description, limits.max_description_len);
+        if self.questions.is_empty() {
+            v.add("questions", "must have at least 1 question");
+        } else if self.questions.len() > limits.max_questions {
+            v.add(
+                "questions",
+                format!("must have at most {} questions", limits.max_questions),
+            );
+        }
+        for (i, question) in self.questions.iter().enumerate() {
+            let prefix = format!("questions[{}].", i);
+            v.text(
+                &format!("{}title", prefix),
+                &question.title,
+                limits.max_title_len,
+            );
+            v.optional_text(
+                &format!("{}description", prefix),
+                &question.description,
+                limits.max_description_len,
+            );
+            question.question.validate_options(
+                &mut v,
+                &format!("{}options", prefix),
+                &question.options,
+                limits,
+            );
+            question
+                .question
+                .validate(&mut v, &format!("{}question", prefix), limits);
+            if let Some(show_if) = &question.show_if {
+                show_if.validate(&mut v, &format!("{}show_if", prefix), i, &self.questions);
+            }
+        }
+        v.finish()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateSurveyRequest {
+    title: Option<String>,
+    description: Option<String>,
+    /// Opens or closes the survey and all of its questions
+    is_active: Option<bool>,
+}
+
+/**
+One answer of a survey response. Choice questions take `option_id`, scale and number
+questions `value`, text questions `text`.
+*/
+#[derive(Deserialize, ToSchema)]
+pub struct SurveyAnswer {
+    question_id: Uuid,
+    option_id: Option<Uuid>,
+    value: Option<f64>,
+    text: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SurveyResponseRequest {
+    answers: Vec<SurveyAnswer>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SurveyQuestionData {
+    required: bool,
+    /// Asked only when this earlier answer matches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    show_if: Option<QuestionCondition>,
+    /// The poll holding the question, with its results as the caller may see them
+    #[serde(flatten)]
+    poll: PollData,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SurveyData {
+    id: Uuid,
+    user_id: Uuid,
+    title: String,
+    description: String,
+    is_active: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// Responses submitted so far
+    responses: i64,
+    questions: Vec<SurveyQuestionData>,
+}
+
+/**
+Loads the survey's questions with their results as `viewer` may see them.
+*/
+async fn survey_data(
+    pool: &PgPool,
+    survey: Survey,
+    viewer: Option<Uuid>,
+) -> Result<SurveyData, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let mut questions = Vec::new();
+    for question in surveys::get_questions(&mut *conn, survey.id).await? {
+        questions.push(SurveyQuestionData {
+            required: question.required,
+            show_if: question.condition(),
+            poll: poll_data(&mut conn, question.poll, viewer).await?,
+        });
+    }
+    let responses = surveys::count_responses(&mut *conn, survey.id).await?;
+    Ok(SurveyData {
+        id: survey.id,
+        user_id: survey.user_id,
+        title: survey.title,
+        description: survey.description,
+        is_active: survey.is_active,
+        created_at: survey.created_at,
+        responses,
+        questions,
+    })
+}
+
+async fn find_survey(pool: &PgPool, survey_id: Uuid) -> WebResult<Survey> {
+    surveys::get_survey(pool, survey_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::SurveyNotFound,
+            _ => Error::Database(e),
+        })
+}
+
+async fn survey_valid_owner_authorized(
+    pool: &PgPool,
+    survey_id: Uuid,
+    session: &Session,
+) -> WebResult<Survey> {
+    let user_id = validate_session(session)?;
+    let survey = find_survey(pool, survey_id).await?;
+    if survey.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    Ok(survey)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/surveys",
+    tag = "surveys",
+    request_body = CreateSurveyRequest,
+    responses(
+        (status = 201, description = "The created survey with its questions", body = SurveyData),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 422, description = "The survey or one of its questions failed validation", body = ErrorBody),
+    )
+)]
+pub async fn create_survey(
+    req: Json<CreateSurveyRequest>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    req.validate(&limits)?;
+
+    // The survey and every poll holding one of its questions are created together
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    let survey = surveys::create_survey(
+        &mut *tx,
+        Uuid::new_v4(),
+        user_id,
+        req.title.trim(),
+        req.description.trim(),
+    )
+    .await
+    .map_err(Error::Database)?;
+    // Poll and option ids of the questions created so far, for conditions to refer to
+    let mut created: Vec<(Uuid, Vec<Uuid>)> = Vec::new();
+    for (position, question) in req.questions.iter().enumerate() {
+        let options: Vec<polls::NewPollOption> = question
+            .options
+            .iter()
+            .cloned()
+            .map(PollOptionInput::into_new_option)
+            .collect();
+        let poll_id = Uuid::new_v4();
+        let (_, options) = polls::create_poll_with_options(
+            &mut *tx,
+            poll_id,
+            user_id,
+            question.title.trim(),
+            question.description.trim(),
+            &polls::PollSettings {
+                secret_ballot: req.secret_ballot,
+                results_visibility: req.results_visibility,
+                question: question.question.settings(),
+                ..Default::default()
+            },
+            &options,
+        )
+        .await
+        .map_err(|e| option_write_error(e, &format!("questions[{}].options", position)))?;
+        let condition = question.show_if.as_ref().map(|show_if| {
+            let (question_id, option_ids) = &created[show_if.question];
+            QuestionCondition {
+                question_id: *question_id,
+                option_ids: show_if.options.iter().map(|&i| option_ids[i]).collect(),
+                min: show_if.min,
+                max: show_if.max,
+            }
+        });
+        surveys::add_question(
+            &mut *tx,
+            survey.id,
+            poll_id,
+            position as i32,
+            question.required,
+            condition.as_ref(),
+        )
+        .await
+        .map_err(Error::Database)?;
+        created.push((
+            poll_id,
+            options.into_iter().map(|option| option.id).collect(),
+        ));
+    }
+    tx.commit().await.map_err(Error::Database)?;
+
+    let survey = survey_data(&pool, survey, Some(user_id))
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Created().json(survey))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/surveys",
+    tag = "surveys",
+    responses(
+        (status = 200, description = "The caller's surveys, newest first", body = [Survey]),
+        (status = 401, description = "No active session", body = ErrorBody),
+    )
+)]
+pub async fn list_surveys(session: Session, pool: Data<PgPool>) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let surveys = surveys::get_user_surveys(&pool, user_id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(surveys))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/surveys/{survey_id}",
+    tag = "surveys",
+    params(("survey_id" = Uuid, Path, description = "Survey id")),
+    responses(
+        (status = 200, description = "The survey with its questions; each question's tallies \
+            follow the survey's results_visibility", body = SurveyData),
+        (status = 404, description = "Survey not found", body = ErrorBody),
+    )
+)]
+pub async fn get_survey(
+    survey_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let survey = find_survey(&pool, survey_id.into_inner()).await?;
+    let survey = survey_data(&pool, survey, session_user(&session))
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(survey))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/surveys/{survey_id}",
+    tag = "surveys",
+    params(("survey_id" = Uuid, Path, description = "Survey id")),
+    request_body = UpdateSurveyRequest,
+    responses(
+        (status = 204, description = "Survey updated"),
+        (status = 401, description = "Caller does not own the survey", body = ErrorBody),
+        (status = 404, description = "Survey not found", body = ErrorBody),
+        (status = 422, description = "Title or description failed validation", body = ErrorBody),
+    )
+)]
+pub async fn update_survey(
+    survey_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<UpdateSurveyRequest>,
+) -> WebResult<HttpResponse> {
+    let survey = survey_valid_owner_authorized(&pool, survey_id.into_inner(), &session).await?;
+    let mut v = Validator::new();
+    if let Some(title) = &req.title {
+        v.text("title", title, limits.max_title_len);
+    }
+    if let Some(description) = &req.description {
+        v.optional_text("description", description, limits.max_description_len);
+    }
+    v.finish()?;
+
+    surveys::update_survey(
+        &pool,
+        survey.id,
+        req.title.as_deref().map(str::trim),
+        req.description.as_deref().map(str::trim),
+        req.is_active,
+    )
+    .await
+    .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/surveys/{survey_id}",
+    tag = "surveys",
+    params(("survey_id" = Uuid, Path, description = "Survey id")),
+    responses(
+        (status = 204, description = "Survey deleted along with its questions and answers"),
+        (status = 401, description = "Caller does not own the survey", body = ErrorBody),
+        (status = 404, description = "Survey not found", body = ErrorBody),
+    )
+)]
+pub async fn delete_survey(
+    survey_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let survey = survey_valid_owner_authorized(&pool, survey_id.into_inner(), &session).await?;
+    surveys::delete_survey(&pool, survey.id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/**
+A survey question with what is needed to check an answer to it
+*/
+struct AskedQuestion {
+    poll: Poll,
+    required: bool,
+    condition: Option<QuestionCondition>,
+    option_ids: Vec<Uuid>,
+}
+
+/**
+Whether `answer`, given to the condition's question, means the conditional question is asked
+*/
+fn matches(condition: &QuestionCondition, answer: &SurveyAnswer) -> bool {
+    if !condition.option_ids.is_empty() {
+        return answer
+            .option_id
+            .is_some_and(|option_id| condition.option_ids.contains(&option_id));
+    }
+    if condition.min.is_none() && condition.max.is_none() {
+        return true;
+    }
+    answer.value.is_some_and(|value| {
+        condition.min.is_none_or(|min| value >= min) && condition.max.is_none_or(|max| value <= max)
+    })
+}
+
+/**
+Checks every answer against its question and the questions the earlier answers lead to
+*/
+fn check_response(
+    asked: &[AskedQuestion],
+    answers: &[SurveyAnswer],
+    limits: &PollLimits,
+) -> Result<(), Error> {
+    let mut v = Validator::new();
+    // Index of the answer given to each question
+    let mut answered: HashMap<Uuid, usize> = HashMap::new();
+    for (i, answer) in answers.iter().enumerate() {
+        let prefix = format!("answers[{}].", i);
+        let Some(question) = asked.iter().find(|q| q.poll.id == answer.question_id) else {
+            v.add(
+                format!("{}question_id", prefix),
+                "is not a question of this survey",
+            );
+            continue;
+        };
+        match answered.get(&question.poll.id) {
+            Some(first) => v.add(
+                format!("{}question_id", prefix),
+                format!("duplicates answers[{}]", first),
+            ),
+            None => {
+                answered.insert(question.poll.id, i);
+            }
+        }
+    }
+    // Questions only depend on earlier ones, so one pass in order settles which are asked
+    let mut shown: HashSet<Uuid> = HashSet::new();
+    for question in asked {
+        let is_shown = match &question.condition {
+            None => true,
+            Some(condition) => {
+                shown.contains(&condition.question_id)
+                    && answered
+                        .get(&condition.question_id)
+                        .is_some_and(|&i| matches(condition, &answers[i]))
+            }
+        };
+        if is_shown {
+            shown.insert(question.poll.id);
+        }
+        let Some(&i) = answered.get(&question.poll.id) else {
+            // A question its owner closed can no longer be answered, so it cannot be required
+            if is_shown && question.required && question.poll.is_active {
+                v.add(
+                    "answers",
+                    format!("must answer the required question {}", question.poll.id),
+                );
+            }
+            continue;
+        };
+        let prefix = format!("answers[{}].", i);
+        if !question.poll.is_active {
+            v.add(format!("{}question_id", prefix), "is closed");
+            continue;
+        }
+        if !is_shown {
+            v.add(
+                format!("{}question_id", prefix),
+                "is not asked given the earlier answers",
+            );
+            continue;
+        }
+        let answer = &answers[i];
+        if question.poll.kind == QuestionKind::Choice {
+            match answer.option_id {
+                Some(option_id) if question.option_ids.contains(&option_id) => {}
+                Some(_) => v.add(
+                    format!("{}option_id", prefix),
+                    "does not belong to this question",
+                ),
+                None => v.add(format!("{}option_id", prefix), "is required"),
+            }
+        } else {
+            check_answer(
+                &mut v,
+                &prefix,
+                &question.poll,
+                answer.value,
+                answer.text.as_deref(),
+                limits,
+            );
+        }
+    }
+    v.finish()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/surveys/{survey_id}/responses",
+    tag = "surveys",
+    params(("survey_id" = Uuid, Path, description = "Survey id")),
+    request_body = SurveyResponseRequest,
+    responses(
+        (status = 204, description = "Every answer of the response was recorded"),
+        (status = 400, description = "Survey is closed or the user already responded", body = ErrorBody),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "Survey not found", body = ErrorBody),
+        (status = 422, description = "An answer does not fit its question or answers a closed \
+            one, or a required question is unanswered", body = ErrorBody),
+    )
+)]
+pub async fn submit_response(
+    survey_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<SurveyResponseRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let survey = find_survey(&pool, survey_id.into_inner()).await?;
+    if !survey.is_active {
+        return Err(Error::PollClosed);
+    }
+
+    let mut asked = Vec::new();
+    for question in surveys::get_questions(pool.get_ref(), survey.id)
+        .await
+        .map_err(Error::Database)?
+    {
+        asked.push(AskedQuestion {
+            option_ids: polls::get_poll_option_ids(&pool, question.poll.id)
+                .await
+                .map_err(Error::Database)?,
+            required: question.required,
+            condition: question.condition(),
+            poll: question.poll,
+        });
+    }
+
+    check_response(&asked, &req.answers, &limits)?;
+
+    // The response is recorded as a whole or not at all
+    let mut tx = pool.begin().await.map_err(Error::Database)?;
+    surveys::record_response(&mut *tx, survey.id, user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => Error::AlreadyVoted,
+            _ => Error::Database(e),
+        })?;
+    for answer in &req.answers {
+        let Some(question) = asked.iter().find(|q| q.poll.id == answer.question_id) else {
+            continue;
+        };
+        match answer.option_id {
+            Some(option_id) if question.poll.kind == QuestionKind::Choice => {
+                let weight = polls::vote(&mut *tx, option_id, user_id, Uuid::new_v4())
+                    .await
+                    .map_err(Error::Database)?;
+                polls::increase_vote_count(&mut *tx, option_id, weight)
+                    .await
+                    .map_err(Error::Database)?;
+            }
+            _ => record_answer(
+                &mut *tx,
+                &question.poll,
+                user_id,
+                answer.value,
+                answer.text.as_deref(),
+            )
+            .await
+            .map_err(Error::Database)?,
+        }
+    }
+    tx.commit().await.map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(question_id: Uuid) -> QuestionCondition {
+        QuestionCondition {
+            question_id,
+            option_ids: vec![],
+            min: None,
+            max: None,
+        }
+    }
+
+    fn answer(question_id: Uuid) -> SurveyAnswer {
+        SurveyAnswer {
+            question_id,
+            option_id: None,
+            value: None,
+            text: None,
+        }
+    }
+
+    fn picked(question: &AskedQuestion, option: usize) -> SurveyAnswer {
+        SurveyAnswer {
+            option_id: Some(question.option_ids[option]),
+            ..answer(question.poll.id)
+        }
+    }
+
+    fn choice(required: bool, condition: Option<QuestionCondition>) -> AskedQuestion {
+        AskedQuestion {
+            poll: Poll::fixture(),
+            required,
+            condition,
+            option_ids: vec![Uuid::new_v4(), Uuid::new_v4()],
+        }
+    }
+
+    /// A choice question asked when `option` of `earlier` is picked
+    fn follow_up(earlier: &AskedQuestion, option: usize, required: bool) -> AskedQuestion {
+        choice(
+            required,
+            Some(QuestionCondition {
+                option_ids: vec![earlier.option_ids[option]],
+                ..condition(earlier.poll.id)
+            }),
+        )
+    }
+
+    fn errors(asked: &[AskedQuestion], answers: &[SurveyAnswer]) -> Vec<(String, String)> {
+        match check_response(asked, answers, &PollLimits::default()) {
+            Ok(()) => vec![],
+            Err(Error::Validation(errors)) => {
+                errors.into_iter().map(|e| (e.field, e.message)).collect()
+            }
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn option_conditions_match_the_listed_options() {
+        let (yes, no) = (Uuid::new_v4(), Uuid::new_v4());
+        let question = Uuid::new_v4();
+        let condition = QuestionCondition {
+            option_ids: vec![yes],
+            ..condition(question)
+        };
+
+        let pick = |option_id| SurveyAnswer {
+            option_id: Some(option_id),
+            ..answer(question)
+        };
+        assert!(matches(&condition, &pick(yes)));
+        assert!(!matches(&condition, &pick(no)));
+        assert!(!matches(&condition, &answer(question)));
+    }
+
+    #[test]
+    fn range_conditions_include_their_bounds() {
+        let question = Uuid::new_v4();
+        let rated = |value| SurveyAnswer {
+            value: Some(value),
+            ..answer(question)
+        };
+        let between = QuestionCondition {
+            min: Some(2.0),
+            max: Some(4.0),
+            ..condition(question)
+        };
+        assert!(!matches(&between, &rated(1.0)));
+        assert!(matches(&between, &rated(2.0)));
+        assert!(matches(&between, &rated(4.0)));
+        assert!(!matches(&between, &rated(4.5)));
+        assert!(!matches(&between, &answer(question)));
+
+        let at_least = QuestionCondition {
+            min: Some(3.0),
+            ..condition(question)
+        };
+        assert!(matches(&at_least, &rated(100.0)));
+        assert!(!matches(&at_least, &rated(2.0)));
+        let at_most = QuestionCondition {
+            max: Some(3.0),
+            ..condition(question)
+        };
+        assert!(matches(&at_most, &rated(-1.0)));
+        assert!(!matches(&at_most, &rated(3.5)));
+    }
+
+    #[test]
+    fn text_conditions_match_any_answer() {
+        let question = Uuid::new_v4();
+        let written = SurveyAnswer {
+            text: Some("anything".to_string()),
+            ..answer(question)
+        };
+        assert!(matches(&condition(question), &written));
+    }
+
+    #[test]
+    fn required_questions_must_be_answered() {
+        let first = choice(true, None);
+        let second = choice(false, None);
+        let asked = [first, second];
+
+        assert_eq!(errors(&asked, &[picked(&asked[0], 0)]), vec![]);
+        assert_eq!(
+            errors(&asked, &[picked(&asked[1], 0)]),
+            vec![(
+                "answers".to_string(),
+                format!("must answer the required question {}", asked[0].poll.id)
+            )]
+        );
+    }
+
+    #[test]
+    fn required_questions_of_a_branch_not_taken_may_be_skipped() {
+        let first = choice(true, None);
+        let second = follow_up(&first, 0, true);
+        let asked = [first, second];
+
+        assert_eq!(errors(&asked, &[picked(&asked[0], 1)]), vec![]);
+        assert_eq!(
+            errors(&asked, &[picked(&asked[0], 0)]),
+            vec![(
+                "answers".to_string(),
+                format!("must answer the required question {}", asked[1].poll.id)
+            )]
+        );
+        assert_eq!(
+            errors(&asked, &[picked(&asked[0], 0), picked(&asked[1], 1)]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn questions_of_a_branch_not_taken_cannot_be_answered() {
+        let first = choice(true, None);
+        let second = follow_up(&first, 0, false);
+        let asked = [first, second];
+
+        assert_eq!(
+            errors(&asked, &[picked(&asked[0], 1), picked(&asked[1], 0)]),
+            vec![(
+                "answers[1].question_id".to_string(),
+                "is not asked given the earlier answers".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn branches_hide_everything_that_depends_on_them() {
+        let first = choice(true, None);
+        let second = follow_up(&first, 0, false);
+        let third = follow_up(&second, 0, true);
+        let asked = [first, second, third];
+
+        // The third question is only asked through the second, which is not
+        assert_eq!(errors(&asked, &[picked(&asked[0], 1)]), vec![]);
+    }
+
+    #[test]
+    fn answers_must_belong_to_the_survey_once_each() {
+        let asked = [choice(false, None)];
+        let stranger = choice(false, None);
+
+        assert_eq!(
+            errors(
+                &asked,
+                &[
+                    picked(&asked[0], 0),
+                    picked(&asked[0], 1),
+                    picked(&stranger, 0)
+                ]
+            ),
+            vec![
+                (
+                    "answers[1].question_id".to_string(),
+                    "duplicates answers[0]".to_string()
+                ),
+                (
+                    "answers[2].question_id".to_string(),
+                    "is not a question of this survey".to_string()
+                ),
+            ]
+        );
+        let foreign = SurveyAnswer {
+            option_id: stranger.option_ids.first().copied(),
+            ..answer(asked[0].poll.id)
+        };
+        assert_eq!(
+            errors(&asked, &[foreign]),
+            vec![(
+                "answers[0].option_id".to_string(),
+                "does not belong to this question".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn closed_questions_cannot_be_answered_and_are_not_required() {
+        let mut closed = choice(true, None);
+        closed.poll.is_active = false;
+        let asked = [closed, choice(false, None)];
+
+        assert_eq!(errors(&asked, &[picked(&asked[1], 0)]), vec![]);
+        assert_eq!(
+            errors(&asked, &[picked(&asked[0], 0)]),
+            vec![(
+                "answers[0].question_id".to_string(),
+                "is closed".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, FieldError, WebResult},
+        validate_session::validate_session,
+    },
+    db::templates::{self, PollTemplate},
+    polls::{
+        definition::PollDefinition,
+        manage_polls::{insert_poll, PollData},
+        validation::{PollLimits, Validator},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+#[derive(Serialize, ToSchema)]
+pub struct TemplateResponse {
+    pub id: Uuid,
+    pub name: String,
+    #[schema(value_type = PollDefinition)]
+    pub definition: serde_json::Value,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<PollTemplate> for TemplateResponse {
+    fn from(template: PollTemplate) -> Self {
+        TemplateResponse {
+            id: template.id,
+            name: template.name,
+            definition: template.definition,
+            created_at: template.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTemplateRequest {
+    name: String,
+    definition: PollDefinition,
+}
+
+fn template_error(e: sqlx::Error) -> Error {
+    match e {
+        sqlx::Error::RowNotFound => Error::TemplateNotFound,
+        _ => Error::Database(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/templates",
+    tag = "templates",
+    responses(
+        (status = 200, description = "The caller's templates, by name", body = [TemplateResponse]),
+        (status = 401, description = "No active session", body = ErrorBody),
+    )
+)]
+pub async fn list_templates(session: Session, pool: Data<PgPool>) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let templates: Vec<TemplateResponse> = templates::get_user_templates(&pool, user_id)
+        .await
+        .map_err(Error::Database)?
+        .into_iter()
+        .map(TemplateResponse::from)
+        .collect();
+    Ok(HttpResponse::Ok().json(templates))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/templates",
+    tag = "templates",
+    request_body = CreateTemplateRequest,
+    responses(
+        (status = 201, description = "Template saved", body = TemplateResponse),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 422, description = "Name or definition failed validation", body = ErrorBody),
+    )
+)]
+pub async fn create_template(
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<CreateTemplateRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let mut v = Validator::new();
+    v.text("name", &req.name, limits.max_title_len);
+    v.finish()?;
+    req.definition.check_version()?;
+    // Templates only hold definitions that would create a valid poll today
+    req.definition.poll.validate(&limits)?;
+
+    let definition =
+        serde_json::to_value(&req.definition).map_err(|e| Error::Export(e.to_string()))?;
+    let template =
+        templates::create_template(&pool, Uuid::new_v4(), user_id, req.name.trim(), &definition)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(db) if db.is_unique_violation() => {
+                    Error::Validation(vec![FieldError::new(
+                        "name",
+                        "is already used by another of your templates",
+                    )])
+                }
+                _ => Error::Database(e),
+            })?;
+    Ok(HttpResponse::Created().json(TemplateResponse::from(template)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/templates/{template_id}",
+    tag = "templates",
+    params(("template_id" = Uuid, Path, description = "Template id")),
+    responses(
+        (status = 204, description = "Template deleted"),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "The caller has no such template", body = ErrorBody),
+    )
+)]
+pub async fn delete_template(
+    template_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    templates::delete_template(&pool, template_id.into_inner(), user_id)
+        .await
+        .map_err(template_error)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/templates/{template_id}/polls",
+    tag = "templates",
+    params(("template_id" = Uuid, Path, description = "Template id")),
+    responses(
+        (status = 201, description = "Poll created from the template", body = PollData),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 404, description = "The caller has no such template", body = ErrorBody),
+        (status = 422, description = "The template no longer passes validation", body = ErrorBody),
+    )
+)]
+pub async fn create_poll_from_template(
+    template_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let template = templates::get_template(&pool, template_id.into_inner(), user_id)
+        .await
+        .map_err(template_error)?;
+    let definition: PollDefinition = serde_json::from_value(template.definition)
+        .map_err(|e| Error::InvalidRequest(format!("Stored template is unreadable: {}", e)))?;
+    let poll = insert_poll(pool.get_ref(), user_id, &definition.poll, &limits).await?;
+    Ok(HttpResponse::Created().json(poll))
+}
@@ -0,0 +1,371 @@
+use crate::{
+    auth::error::{Error, FieldError},
+    db::polls::{OutcomeRules, PassThreshold, QuestionKind},
+    polls::recurrence::Recurrence,
+};
+use std::{collections::HashMap, env};
+
+/// What two option texts are compared by when looking for duplicates
+fn option_key(text: &str) -> String {
+    text.trim().to_lowercase()
+}
+
+/**
+Limits applied to poll input. Loaded once at startup; every value can be
+overridden through the environment.
+*/
+#[derive(Debug, Clone)]
+pub struct PollLimits {
+    pub max_title_len: usize,
+    pub max_description_len: usize,
+    pub max_option_len: usize,
+    pub max_option_description_len: usize,
+    pub max_url_len: usize,
+    pub min_options: usize,
+    pub max_options: usize,
+    /// Longest free-text answer a text question may allow
+    pub max_answer_len: usize,
+    /// Highest top value of a rating scale
+    pub max_scale: usize,
+    /// Most questions a survey may ask, and most polls a live session may present
+    pub max_questions: usize,
+    /// Longest comment on a poll
+    pub max_comment_len: usize,
+    /// Longest question the audience of a live session may ask
+    pub max_audience_question_len: usize,
+}
+
+impl Default for PollLimits {
+    fn default() -> Self {
+        PollLimits {
+            max_title_len: 200,
+            max_description_len: 2000,
+            max_option_len: 200,
+            max_option_description_len: 1000,
+            max_url_len: 2048,
+            min_options: 2,
+            max_options: 20,
+            max_answer_len: 1000,
+            max_scale: 10,
+            max_questions: 50,
+            max_comment_len: 2000,
+            max_audience_question_len: 500,
+        }
+    }
+}
+
+fn env_or(key: &str, default: usize) -> usize {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a number", key)),
+        Err(_) => default,
+    }
+}
+
+impl PollLimits {
+    pub fn from_env() -> Self {
+        let defaults = PollLimits::default();
+        PollLimits {
+            max_title_len: env_or("POLL_MAX_TITLE_LEN", defaults.max_title_len),
+            max_description_len: env_or("POLL_MAX_DESCRIPTION_LEN", defaults.max_description_len),
+            max_option_len: env_or("POLL_MAX_OPTION_LEN", defaults.max_option_len),
+            max_option_description_len: env_or(
+                "POLL_MAX_OPTION_DESCRIPTION_LEN",
+                defaults.max_option_description_len,
+            ),
+            max_url_len: env_or("POLL_MAX_URL_LEN", defaults.max_url_len),
+            min_options: env_or("POLL_MIN_OPTIONS", defaults.min_options),
+            max_options: env_or("POLL_MAX_OPTIONS", defaults.max_options),
+            max_answer_len: env_or("POLL_MAX_ANSWER_LEN", defaults.max_answer_len),
+            max_scale: env_or("POLL_MAX_SCALE", defaults.max_scale),
+            max_questions: env_or("POLL_MAX_QUESTIONS", defaults.max_questions),
+            max_comment_len: env_or("POLL_MAX_COMMENT_LEN", defaults.max_comment_len),
+            max_audience_question_len: env_or(
+                "POLL_MAX_AUDIENCE_QUESTION_LEN",
+                defaults.max_audience_question_len,
+            ),
+        }
+    }
+}
+
+/**
+Collects field errors so a request reports every problem at once.
+*/
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Validator::default()
+    }
+
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError::new(field, message));
+    }
+
+    /// Rejects blank text and text longer than `max` characters.
+    pub fn text(&mut self, field: &str, value: &str, max: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "must not be blank");
+        } else if value.chars().count() > max {
+            self.add(field, format!("must be at most {} characters", max));
+        }
+    }
+
+    /// Like [Validator::text] but allows an empty value.
+    pub fn optional_text(&mut self, field: &str, value: &str, max: usize) {
+        if value.chars().count() > max {
+            self.add(field, format!("must be at most {} characters", max));
+        }
+    }
+
+    /// Checks the option count, each option's text and duplicates (ignoring case and
+    /// surrounding whitespace).
+    pub fn options(&mut self, field: &str, options: &[&str], limits: &PollLimits) {
+        if options.len() < limits.min_options {
+            self.add(
+                field,
+                format!("must have at least {} options", limits.min_options),
+            );
+        } else if options.len() > limits.max_options {
+            self.add(
+                field,
+                format!("must have at most {} options", limits.max_options),
+            );
+        }
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for (i, option) in options.iter().enumerate() {
+            let option_field = format!("{}[{}]", field, i);
+            self.text(&option_field, option, limits.max_option_len);
+            let key = option_key(option);
+            if key.is_empty() {
+                continue;
+            }
+            match seen.get(&key) {
+                Some(first) => self.add(option_field, format!("duplicates {}[{}]", field, first)),
+                None => {
+                    seen.insert(key, i);
+                }
+            }
+        }
+    }
+
+    /// Checks a new text for an existing option against the texts of the poll's other
+    /// options, the way [Validator::options] checks a whole list.
+    pub fn renamed_option(&mut self, field: &str, text: &str, others: &[&str], max: usize) {
+        self.text(field, text, max);
+        let key = option_key(text);
+        if !key.is_empty() && others.iter().any(|other| option_key(other) == key) {
+            self.add(field, "duplicates another option of the poll");
+        }
+    }
+
+    /// Accepts absolute `http(s)` URLs only.
+    pub fn url(&mut self, field: &str, value: &str, max: usize) {
+        if !(value.starts_with("https://") || value.starts_with("http://")) {
+            self.add(field, "must be an http or https URL");
+        } else if value.chars().count() > max {
+            self.add(field, format!("must be at most {} characters", max));
+        }
+    }
+
+    /// Accepts `#rgb` and `#rrggbb` hex colors.
+    pub fn color(&mut self, field: &str, value: &str) {
+        let valid = value
+            .strip_prefix('#')
+            .map(|hex| {
+                (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+            })
+            .unwrap_or(false);
+        if !valid {
+            self.add(field, "must be a hex color like #1e90ff");
+        }
+    }
+
+    /// Accepts anything [Recurrence] can parse.
+    pub fn recurrence(&mut self, field: &str, value: &str) {
+        if let Err(e) = value.parse::<Recurrence>() {
+            self.add(
+                field,
+                format!("must be daily, weekly or a cron expression ({})", e),
+            );
+        }
+    }
+
+    /// Accepts finite, non-negative voting weights.
+    pub fn weight(&mut self, field: &str, value: f64) {
+        if !value.is_finite() || value < 0.0 {
+            self.add(field, "must be a non-negative number");
+        }
+    }
+
+    /// Checks quorum bounds; a share of the electorate needs one to refer to. A pass
+    /// threshold and `auto_close` need options to pass, so only choice polls set them.
+    pub fn outcome_rules(&mut self, rules: &OutcomeRules, kind: QuestionKind) {
+        if rules.quorum_votes.is_some_and(|votes| votes < 1) {
+            self.add("quorum_votes", "must be at least 1");
+        }
+        if let Some(percent) = rules.quorum_percent {
+            if !(percent > 0.0 && percent <= 100.0) {
+                self.add("quorum_percent", "must be more than 0 and at most 100");
+            } else if !rules.participants_only {
+                self.add("quorum_percent", "requires participants_only");
+            }
+        }
+        if kind != QuestionKind::Choice {
+            if rules.pass_threshold != PassThreshold::default() {
+                self.add("pass_threshold", "only applies to choice questions");
+            }
+            if rules.auto_close {
+                self.add("auto_close", "only applies to choice questions");
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Error> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(self.errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_fields(v: Validator) -> Vec<String> {
+        errors(v).into_iter().map(|(field, _)| field).collect()
+    }
+
+    fn errors(v: Validator) -> Vec<(String, String)> {
+        match v.finish() {
+            Ok(()) => vec![],
+            Err(Error::Validation(errors)) => {
+                errors.into_iter().map(|e| (e.field, e.message)).collect()
+            }
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    fn option_errors(options: &[&str]) -> Vec<(String, String)> {
+        let mut v = Validator::new();
+        v.options("poll_options", options, &PollLimits::default());
+        errors(v)
+    }
+
+    fn error(field: &str, message: &str) -> (String, String) {
+        (field.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn distinct_options_pass() {
+        assert!(option_errors(&["Pizza", "Pasta", "Salad"]).is_empty());
+    }
+
+    #[test]
+    fn the_option_count_is_limited() {
+        assert_eq!(
+            option_errors(&["Pizza"]),
+            [error("poll_options", "must have at least 2 options")]
+        );
+        let many: Vec<String> = (0..21).map(|i| format!("Option {}", i)).collect();
+        let many: Vec<&str> = many.iter().map(String::as_str).collect();
+        assert_eq!(
+            option_errors(&many),
+            [error("poll_options", "must have at most 20 options")]
+        );
+    }
+
+    #[test]
+    fn blank_and_long_options_are_reported_by_index() {
+        let long = "x".repeat(201);
+        assert_eq!(
+            option_errors(&["Pizza", "  ", &long]),
+            [
+                error("poll_options[1]", "must not be blank"),
+                error("poll_options[2]", "must be at most 200 characters"),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_ignore_case_and_surrounding_whitespace() {
+        assert_eq!(
+            option_errors(&["Pizza", "Pasta", " pizza ", "PASTA", "PIZZA"]),
+            [
+                error("poll_options[2]", "duplicates poll_options[0]"),
+                error("poll_options[3]", "duplicates poll_options[1]"),
+                error("poll_options[4]", "duplicates poll_options[0]"),
+            ]
+        );
+        // Blank options are only reported as blank, not as duplicates of each other
+        assert_eq!(
+            option_errors(&["", " "]),
+            [
+                error("poll_options[0]", "must not be blank"),
+                error("poll_options[1]", "must not be blank"),
+            ]
+        );
+    }
+
+    fn rename_errors(text: &str, others: &[&str]) -> Vec<String> {
+        let mut v = Validator::new();
+        v.renamed_option("option_text", text, others, 100);
+        failed_fields(v)
+    }
+
+    #[test]
+    fn renamed_options_cannot_duplicate_others() {
+        let others = ["Pizza", "Pasta"];
+        assert!(rename_errors("Salad", &others).is_empty());
+        assert_eq!(rename_errors("  pizza ", &others), ["option_text"]);
+        assert_eq!(rename_errors("PASTA", &others), ["option_text"]);
+        assert_eq!(rename_errors(" ", &others), ["option_text"]);
+    }
+
+    fn rule_errors(rules: &OutcomeRules, kind: QuestionKind) -> Vec<String> {
+        let mut v = Validator::new();
+        v.outcome_rules(rules, kind);
+        failed_fields(v)
+    }
+
+    #[test]
+    fn only_choices_set_a_threshold_or_auto_close() {
+        let rules = OutcomeRules {
+            pass_threshold: PassThreshold::Majority,
+            auto_close: true,
+            ..Default::default()
+        };
+        assert!(rule_errors(&rules, QuestionKind::Choice).is_empty());
+        for kind in [
+            QuestionKind::Scale,
+            QuestionKind::Number,
+            QuestionKind::Text,
+        ] {
+            assert_eq!(rule_errors(&rules, kind), ["pass_threshold", "auto_close"]);
+            assert!(rule_errors(&OutcomeRules::default(), kind).is_empty());
+        }
+    }
+
+    #[test]
+    fn a_quorum_share_needs_participants() {
+        let rules = OutcomeRules {
+            quorum_percent: Some(50.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            rule_errors(&rules, QuestionKind::Choice),
+            ["quorum_percent"]
+        );
+        let rules = OutcomeRules {
+            participants_only: true,
+            ..rules
+        };
+        assert!(rule_errors(&rules, QuestionKind::Choice).is_empty());
+    }
+}
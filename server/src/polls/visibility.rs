@@ -0,0 +1,126 @@
+use crate::db::polls::{self, Poll, ResultsVisibility};
+use sqlx::PgExecutor;
+use webauthn_rs::prelude::Uuid;
+
+/**
+Whether `viewer` (`None` for anonymous visitors) may see the tallies of `poll`.
+Every route that reveals counts goes through here.
+*/
+pub async fn can_view_results<'e, E>(
+    executor: E,
+    poll: &Poll,
+    viewer: Option<Uuid>,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    if viewer == Some(poll.user_id) {
+        return Ok(true);
+    }
+    match poll.results_visibility {
+        ResultsVisibility::Always => Ok(true),
+        ResultsVisibility::OwnerOnly => Ok(false),
+        ResultsVisibility::AfterClose => Ok(!poll.is_active),
+        ResultsVisibility::AfterVote => match viewer {
+            _ if !poll.is_active => Ok(true),
+            Some(user_id) => polls::has_user_voted(executor, user_id, poll.id).await,
+            None => Ok(false),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{connect, insert_poll, insert_user};
+    use sqlx::{Acquire, PgPool};
+
+    /// A pool that never connects; any query through it fails the test
+    fn no_database() -> PgPool {
+        PgPool::connect_lazy("postgres://nobody@127.0.0.1:1/none").unwrap()
+    }
+
+    fn poll(visibility: ResultsVisibility, is_active: bool) -> Poll {
+        Poll {
+            results_visibility: visibility,
+            is_active,
+            ..Poll::fixture()
+        }
+    }
+
+    async fn can_view(poll: &Poll, viewer: Option<Uuid>) -> bool {
+        can_view_results(&no_database(), poll, viewer)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_owner_always_sees_results() {
+        for visibility in [
+            ResultsVisibility::Always,
+            ResultsVisibility::OwnerOnly,
+            ResultsVisibility::AfterClose,
+            ResultsVisibility::AfterVote,
+        ] {
+            let poll = poll(visibility, true);
+            assert!(can_view(&poll, Some(poll.user_id)).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn others_see_results_as_the_setting_allows() {
+        let someone = Some(Uuid::new_v4());
+        for (visibility, open, closed) in [
+            (ResultsVisibility::Always, true, true),
+            (ResultsVisibility::OwnerOnly, false, false),
+            (ResultsVisibility::AfterClose, false, true),
+        ] {
+            for viewer in [someone, None] {
+                assert_eq!(can_view(&poll(visibility, true), viewer).await, open);
+                assert_eq!(can_view(&poll(visibility, false), viewer).await, closed);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn after_vote_opens_to_everyone_once_closed() {
+        let poll = poll(ResultsVisibility::AfterVote, false);
+        assert!(can_view(&poll, Some(Uuid::new_v4())).await);
+        assert!(can_view(&poll, None).await);
+    }
+
+    #[tokio::test]
+    async fn anonymous_visitors_cannot_have_voted() {
+        assert!(!can_view(&poll(ResultsVisibility::AfterVote, true), None).await);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the database of DATABASE_URL"]
+    async fn after_vote_shows_results_to_voters_only() {
+        let mut conn = connect().await;
+        let mut tx = conn.begin().await.unwrap();
+        let owner = insert_user(&mut tx, "owner").await;
+        let voter = insert_user(&mut tx, "voter").await;
+        let bystander = insert_user(&mut tx, "bystander").await;
+        let (poll_id, options) = insert_poll(&mut tx, owner, &["Yes", "No"]).await;
+        sqlx::query("INSERT INTO votes (id, user_id, poll_option_id) VALUES ($1, $2, $3)")
+            .bind(Uuid::new_v4())
+            .bind(voter)
+            .bind(options[0])
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        let poll = Poll {
+            id: poll_id,
+            user_id: owner,
+            ..poll(ResultsVisibility::AfterVote, true)
+        };
+
+        assert!(can_view_results(&mut *tx, &poll, Some(voter))
+            .await
+            .unwrap());
+        assert!(!can_view_results(&mut *tx, &poll, Some(bystander))
+            .await
+            .unwrap());
+    }
+}
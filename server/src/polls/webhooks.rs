@@ -0,0 +1,783 @@
+use crate::{
+    auth::{
+        error::{Error, ErrorBody, FieldError, WebResult},
+        validate_session::validate_session,
+    },
+    db::{
+        polls,
+        webhooks::{self, Delivery, DueDelivery, Webhook, WebhookEvent},
+    },
+    polls::{
+        manage_polls::{poll_data, PollData},
+        validation::{PollLimits, Validator},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json, Path, Query},
+    HttpResponse,
+};
+use futures_util::future::join_all;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{PgConnection, PgPool};
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use utoipa::{IntoParams, ToSchema};
+use webauthn_rs::prelude::Uuid;
+
+/// Deliveries the dispatcher claims at once
+const BATCH_SIZE: i64 = 20;
+/// Longest error text kept in the delivery log
+const MAX_ERROR_LEN: usize = 500;
+
+/**
+How the dispatcher sends deliveries and retries failed ones. Loaded once at startup from
+the environment.
+*/
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// How often the dispatcher looks for due deliveries
+    pub check_interval: Duration,
+    /// How long an endpoint gets to answer
+    pub timeout: Duration,
+    /// Attempts before a delivery is given up
+    pub max_attempts: i32,
+    /// Wait before the first retry; it doubles with every further attempt
+    pub retry_base: chrono::Duration,
+    /// Longest wait between two attempts
+    pub retry_max: chrono::Duration,
+}
+
+fn secs_env(name: &str, default: u64) -> u64 {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a number", name)),
+        Err(_) => default,
+    }
+}
+
+impl WebhookConfig {
+    pub fn from_env() -> Self {
+        WebhookConfig {
+            check_interval: Duration::from_secs(secs_env("WEBHOOK_CHECK_SECS", 5)),
+            timeout: Duration::from_secs(secs_env("WEBHOOK_TIMEOUT_SECS", 10)),
+            max_attempts: secs_env("WEBHOOK_MAX_ATTEMPTS", 8) as i32,
+            retry_base: chrono::Duration::seconds(secs_env("WEBHOOK_RETRY_BASE_SECS", 30) as i64),
+            retry_max: chrono::Duration::seconds(
+                secs_env("WEBHOOK_RETRY_MAX_SECS", 6 * 60 * 60) as i64
+            ),
+        }
+    }
+
+    /**
+    How long to wait after the `attempt`th attempt failed, or `None` once every attempt
+    is used up.
+    */
+    fn retry_delay(&self, attempt: i32) -> Option<chrono::Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let factor = 1i32.checked_shl((attempt - 1).clamp(0, 30) as u32).unwrap();
+        Some((self.retry_base * factor).min(self.retry_max))
+    }
+
+    /// When to try again after the `attempt`th attempt failed, see [Self::retry_delay]
+    fn retry_at(&self, attempt: i32) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.retry_delay(attempt)
+            .map(|delay| chrono::Utc::now() + delay)
+    }
+}
+
+/**
+The vote a `poll_voted` or `poll_vote_removed` event reports
+*/
+#[derive(Serialize, ToSchema)]
+pub struct VoteInfo {
+    /// Absent for answers to scale, number and text polls
+    pub option_id: Option<Uuid>,
+    /// Absent on secret ballots
+    pub user_id: Option<Uuid>,
+}
+
+/**
+Body of every delivery
+*/
+#[derive(Serialize, ToSchema)]
+pub struct WebhookPayload {
+    event: WebhookEvent,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+    /// The poll as its owner sees it after the event; the final results once it is closed
+    poll: PollData,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vote: Option<VoteInfo>,
+}
+
+/**
+Queues `event` for every webhook that covers the poll and subscribes to it. Called on the
+transaction that makes the change it reports, so the event is queued if and only if the
+change is committed.
+*/
+pub async fn notify(
+    conn: &mut PgConnection,
+    poll_id: Uuid,
+    event: WebhookEvent,
+    vote: Option<VoteInfo>,
+) -> Result<(), sqlx::Error> {
+    if !webhooks::has_subscribers(&mut *conn, poll_id, event).await? {
+        return Ok(());
+    }
+    let poll = polls::get_poll(&mut *conn, poll_id).await?;
+    let owner = poll.user_id;
+    let payload = WebhookPayload {
+        event,
+        occurred_at: chrono::Utc::now(),
+        poll: poll_data(&mut *conn, poll, Some(owner)).await?,
+        vote,
+    };
+    let payload = serde_json::to_value(&payload).unwrap();
+    webhooks::enqueue_event(&mut *conn, poll_id, event, &payload).await?;
+    Ok(())
+}
+
+/**
+Hex HMAC-SHA256 of `{timestamp}.{body}` under the webhook's secret, as sent in
+`X-Webhook-Signature`
+*/
+fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_ERROR_LEN).collect()
+}
+
+const PRIVATE_TARGET: &str = "must not point to a loopback, private or link-local address";
+
+/**
+Whether webhooks may be sent to `ip`. Only addresses reachable on the public internet
+are, so a webhook cannot be aimed at the server itself, its network or a cloud metadata
+endpoint.
+*/
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space of carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking
+                || (a == 198 && (18..20).contains(&b))
+                // Reserved, including broadcast
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            // IPv4-mapped, IPv4-compatible and NAT64 addresses reach the IPv4 address inside
+            if let Some(ip) = ip.to_ipv4() {
+                return is_public(IpAddr::V4(ip));
+            }
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., hi, lo] = segments;
+                return is_public(IpAddr::V4((u32::from(hi) << 16 | u32::from(lo)).into()));
+            }
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local, fc00::/7
+                || (segments[0] & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10
+                || (segments[0] & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/**
+Resolves `host` and fails unless every address it has is public
+*/
+async fn public_addrs(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("host could not be resolved: {}", e))?
+        .collect();
+    if addrs.is_empty() {
+        Err("host has no addresses".to_string())
+    } else if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        Err(PRIVATE_TARGET.to_string())
+    } else {
+        Ok(addrs)
+    }
+}
+
+/**
+Checks that `url` leads to public addresses only. Webhook URLs are checked when they are
+saved and again before every delivery, since what a name resolves to can change.
+*/
+async fn check_target(url: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(url).map_err(|_| "must be a valid URL".to_string())?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| "must have a host".to_string())?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) if is_public(ip) => Ok(()),
+        Ok(_) => Err(PRIVATE_TARGET.to_string()),
+        Err(_) => public_addrs(host, url.port_or_known_default().unwrap_or(80))
+            .await
+            .map(|_| ()),
+    }
+}
+
+/**
+Resolver of the dispatcher's HTTP client. It refuses names with a non-public address, so
+a name changed to a private address between [check_target] and the connection is not
+reached either.
+*/
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = public_addrs(&host, 0).await?;
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/**
+Rejects webhook URLs that do not lead to public addresses
+*/
+async fn validate_target(url: &str) -> WebResult<()> {
+    check_target(url)
+        .await
+        .map_err(|reason| Error::Validation(vec![FieldError::new("url", reason)]))
+}
+
+/**
+POSTs a delivery and returns whether it was delivered, the status the endpoint answered
+with and why it failed. Only the status of a failed answer is kept, never its body.
+*/
+async fn send(
+    client: &reqwest::Client,
+    delivery: &DueDelivery,
+) -> (bool, Option<i32>, Option<String>) {
+    let body = delivery.payload.to_string();
+    let timestamp = chrono::Utc::now().timestamp();
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "livepool-webhooks")
+        .header("X-Webhook-Id", delivery.webhook_id.to_string())
+        .header("X-Webhook-Delivery", delivery.id.to_string())
+        .header("X-Webhook-Event", delivery.event.as_str())
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header(
+            "X-Webhook-Signature",
+            format!("sha256={}", sign(&delivery.secret, timestamp, &body)),
+        )
+        .body(body)
+        .send()
+        .await;
+    match response {
+        Ok(response) => {
+            let status = response.status();
+            let failure = (!status.is_success()).then(|| format!("HTTP {}", status));
+            (status.is_success(), Some(status.as_u16() as i32), failure)
+        }
+        Err(e) => (false, None, Some(truncate(&e.to_string()))),
+    }
+}
+
+/**
+Sends one delivery and records the outcome. Anything but a 2xx answer counts as a
+failure and is retried with exponential backoff, as does a URL that no longer leads to a
+public address.
+*/
+async fn deliver(
+    pool: &PgPool,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    delivery: DueDelivery,
+) {
+    let (delivered, status, failure) = match check_target(&delivery.url).await {
+        Ok(()) => send(client, &delivery).await,
+        Err(reason) => (false, None, Some(format!("URL refused: {}", reason))),
+    };
+    let attempt = delivery.attempts + 1;
+    let retry_at = if delivered {
+        None
+    } else {
+        config.retry_at(attempt)
+    };
+    match (&failure, retry_at) {
+        (None, _) => {}
+        (Some(failure), Some(retry_at)) => warn!(
+            "webhook delivery {} failed (attempt {}), retrying at {}: {}",
+            delivery.id, attempt, retry_at, failure
+        ),
+        (Some(failure), None) => warn!(
+            "webhook delivery {} failed (attempt {}), giving up: {}",
+            delivery.id, attempt, failure
+        ),
+    }
+    if let Err(e) = webhooks::record_attempt(
+        pool,
+        delivery.id,
+        delivered,
+        status,
+        failure.as_deref(),
+        retry_at,
+    )
+    .await
+    {
+        error!("recording webhook delivery {} failed: {:?}", delivery.id, e);
+    }
+}
+
+/**
+Background task that sends due deliveries from the outbox. Deliveries survive restarts:
+one that was claimed but never recorded is picked up again once its lease runs out.
+*/
+pub async fn run_dispatcher(pool: PgPool, config: WebhookConfig) {
+    // Redirects are not followed, as they could lead anywhere
+    let client = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .expect("webhook HTTP client should build");
+    // Long enough for every request of a batch to time out before it is claimed again
+    let lease_seconds = 2 * config.timeout.as_secs() as i64 + 30;
+    let mut interval = tokio::time::interval(config.check_interval);
+    loop {
+        interval.tick().await;
+        loop {
+            let due = match webhooks::claim_due_deliveries(&pool, BATCH_SIZE, lease_seconds).await {
+                Ok(due) => due,
+                Err(e) => {
+                    error!("claiming webhook deliveries failed: {:?}", e);
+                    break;
+                }
+            };
+            if due.is_empty() {
+                break;
+            }
+            info!("sending {} webhook deliveries", due.len());
+            join_all(
+                due.into_iter()
+                    .map(|delivery| deliver(&pool, &client, &config, delivery)),
+            )
+            .await;
+        }
+    }
+}
+
+/**
+A webhook as its owner sees it
+*/
+#[derive(Serialize, ToSchema)]
+pub struct WebhookData {
+    id: Uuid,
+    /// The only poll whose events are delivered; every poll of the owner when absent
+    poll_id: Option<Uuid>,
+    url: String,
+    /// Events delivered; every event when empty
+    events: Vec<WebhookEvent>,
+    active: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// Key of the `X-Webhook-Signature` HMAC, only returned when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+}
+
+impl From<Webhook> for WebhookData {
+    fn from(webhook: Webhook) -> Self {
+        WebhookData {
+            id: webhook.id,
+            poll_id: webhook.poll_id,
+            url: webhook.url,
+            events: webhook.events,
+            active: webhook.active,
+            created_at: webhook.created_at,
+            secret: None,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    /// `http(s)` endpoint the events are POSTed to
+    url: String,
+    /// Limit the webhook to one of the caller's polls
+    poll_id: Option<Uuid>,
+    /// Events to deliver; every event when left out or empty
+    #[serde(default)]
+    events: Vec<WebhookEvent>,
+}
+
+/**
+Fields left out are unchanged.
+*/
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateWebhookRequest {
+    url: Option<String>,
+    events: Option<Vec<WebhookEvent>>,
+    /// Inactive webhooks queue nothing, and their pending deliveries wait until reactivated
+    active: Option<bool>,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DeliveryPageQuery {
+    /// Page number, starting at 1
+    #[serde(default = "default_page")]
+    page: i64,
+    /// Deliveries per page, at most 100
+    #[serde(default = "default_per_page")]
+    per_page: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeliveryPage {
+    pub page: i64,
+    pub per_page: i64,
+    /// Deliveries across all pages
+    pub total: i64,
+    pub deliveries: Vec<Delivery>,
+}
+
+fn generate_secret() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("whsec_{}", hex)
+}
+
+/**
+A webhook of the caller
+*/
+async fn own_webhook(pool: &PgPool, webhook_id: Uuid, session: &Session) -> WebResult<Webhook> {
+    let user_id = validate_session(session)?;
+    let webhook = webhooks::get_webhook(pool, webhook_id)
+        .await
+        .map_err(Error::Database)?
+        .ok_or(Error::WebhookNotFound)?;
+    if webhook.user_id != user_id {
+        return Err(Error::Unauthorized);
+    }
+    Ok(webhook)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, description = "The new webhook with its signing secret. Every delivery is a \
+            POST of a WebhookPayload carrying `X-Webhook-Event`, `X-Webhook-Delivery` (the same on \
+            retries), `X-Webhook-Timestamp` and `X-Webhook-Signature: sha256=<hex>`, the \
+            HMAC-SHA256 of `<timestamp>.<body>` under the secret. Failed deliveries are retried \
+            with exponential backoff", body = WebhookData),
+        (status = 401, description = "No active session", body = ErrorBody),
+        (status = 422, description = "The URL is not valid, does not lead to a public address or the poll is not the caller's", body = ErrorBody),
+    )
+)]
+pub async fn create_webhook(
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<CreateWebhookRequest>,
+) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let url = req.url.trim();
+    let mut v = Validator::new();
+    v.url("url", url, limits.max_url_len);
+    if let Some(poll_id) = req.poll_id {
+        match polls::does_poll_exist(&pool, poll_id).await {
+            Ok(owner) if owner == user_id => {}
+            Ok(_) | Err(sqlx::Error::RowNotFound) => v.add("poll_id", "is not a poll of yours"),
+            Err(e) => return Err(Error::Database(e)),
+        }
+    }
+    v.finish()?;
+    validate_target(url).await?;
+
+    let webhook = webhooks::create_webhook(
+        &pool,
+        Uuid::new_v4(),
+        user_id,
+        req.poll_id,
+        url,
+        &generate_secret(),
+        &req.events,
+    )
+    .await
+    .map_err(Error::Database)?;
+    let secret = webhook.secret.clone();
+    let mut data = WebhookData::from(webhook);
+    data.secret = Some(secret);
+    Ok(HttpResponse::Created().json(data))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "The caller's webhooks, oldest first", body = [WebhookData]),
+        (status = 401, description = "No active session", body = ErrorBody),
+    )
+)]
+pub async fn list_webhooks(session: Session, pool: Data<PgPool>) -> WebResult<HttpResponse> {
+    let user_id = validate_session(&session)?;
+    let webhooks: Vec<WebhookData> = webhooks::get_user_webhooks(&pool, user_id)
+        .await
+        .map_err(Error::Database)?
+        .into_iter()
+        .map(WebhookData::from)
+        .collect();
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/webhooks/{webhook_id}",
+    tag = "webhooks",
+    params(("webhook_id" = Uuid, Path, description = "Webhook id")),
+    request_body = UpdateWebhookRequest,
+    responses(
+        (status = 200, description = "The updated webhook", body = WebhookData),
+        (status = 401, description = "Caller does not own the webhook", body = ErrorBody),
+        (status = 404, description = "Webhook not found", body = ErrorBody),
+        (status = 422, description = "The URL is not valid or does not lead to a public address", body = ErrorBody),
+    )
+)]
+pub async fn update_webhook(
+    webhook_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<UpdateWebhookRequest>,
+) -> WebResult<HttpResponse> {
+    let webhook = own_webhook(&pool, webhook_id.into_inner(), &session).await?;
+    let url = req.url.as_deref().map(str::trim);
+    if let Some(url) = url {
+        let mut v = Validator::new();
+        v.url("url", url, limits.max_url_len);
+        v.finish()?;
+        validate_target(url).await?;
+    }
+    let webhook =
+        webhooks::update_webhook(&pool, webhook.id, url, req.events.as_deref(), req.active)
+            .await
+            .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(WebhookData::from(webhook)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/{webhook_id}",
+    tag = "webhooks",
+    params(("webhook_id" = Uuid, Path, description = "Webhook id")),
+    responses(
+        (status = 204, description = "Webhook deleted along with its pending deliveries and log"),
+        (status = 401, description = "Caller does not own the webhook", body = ErrorBody),
+        (status = 404, description = "Webhook not found", body = ErrorBody),
+    )
+)]
+pub async fn delete_webhook(
+    webhook_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let webhook = own_webhook(&pool, webhook_id.into_inner(), &session).await?;
+    webhooks::delete_webhook(&pool, webhook.id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/{webhook_id}/deliveries",
+    tag = "webhooks",
+    params(("webhook_id" = Uuid, Path, description = "Webhook id"), DeliveryPageQuery),
+    responses(
+        (status = 200, description = "The webhook's deliveries, newest first, with the outcome \
+            of their latest attempt", body = DeliveryPage),
+        (status = 401, description = "Caller does not own the webhook", body = ErrorBody),
+        (status = 404, description = "Webhook not found", body = ErrorBody),
+        (status = 422, description = "The page is out of range", body = ErrorBody),
+    )
+)]
+pub async fn list_deliveries(
+    webhook_id: Path<Uuid>,
+    query: Query<DeliveryPageQuery>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let webhook = own_webhook(&pool, webhook_id.into_inner(), &session).await?;
+    let mut v = Validator::new();
+    if query.page < 1 {
+        v.add("page", "must be at least 1");
+    }
+    if !(1..=100).contains(&query.per_page) {
+        v.add("per_page", "must be between 1 and 100");
+    }
+    v.finish()?;
+
+    let total = webhooks::count_deliveries(&pool, webhook.id)
+        .await
+        .map_err(Error::Database)?;
+    let deliveries = webhooks::get_deliveries(
+        &pool,
+        webhook.id,
+        query.per_page,
+        (query.page - 1) * query.per_page,
+    )
+    .await
+    .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(DeliveryPage {
+        page: query.page,
+        per_page: query.per_page,
+        total,
+        deliveries,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> WebhookConfig {
+        WebhookConfig {
+            check_interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+            max_attempts: 8,
+            retry_base: chrono::Duration::seconds(30),
+            retry_max: chrono::Duration::seconds(600),
+        }
+    }
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_the_maximum() {
+        let config = config();
+        let delays: Vec<i64> = (1..8)
+            .map(|attempt| config.retry_delay(attempt).unwrap().num_seconds())
+            .collect();
+        assert_eq!(delays, [30, 60, 120, 240, 480, 600, 600]);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let config = config();
+        assert!(config.retry_delay(8).is_none());
+        assert!(config.retry_at(9).is_none());
+    }
+
+    #[test]
+    fn retry_at_is_the_delay_from_now() {
+        let before = chrono::Utc::now();
+        let retry_at = config().retry_at(2).unwrap();
+        assert!(retry_at >= before + chrono::Duration::seconds(60));
+        assert!(retry_at <= chrono::Utc::now() + chrono::Duration::seconds(60));
+    }
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        let signature = sign("whsec_test", 1_700_000_000, r#"{"event":"poll_voted"}"#);
+        assert_eq!(
+            signature,
+            "d5038a779ff35160db6509fb7791418abfe340db9c3e0779cebf0c8d58f88222"
+        );
+    }
+
+    #[test]
+    fn signature_covers_every_part() {
+        let body = r#"{"event":"poll_voted"}"#;
+        let signature = sign("whsec_test", 1_700_000_000, body);
+        assert_ne!(signature, sign("whsec_other", 1_700_000_000, body));
+        assert_ne!(signature, sign("whsec_test", 1_700_000_001, body));
+        assert_ne!(signature, sign("whsec_test", 1_700_000_000, "{}"));
+    }
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn refuses_addresses_that_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "224.0.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!public(ip), "{} should be refused", ip);
+        }
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        for ip in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "172.32.0.1",
+            "100.128.0.1",
+            "2606:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(public(ip), "{} should be accepted", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_url_hosts() {
+        assert!(check_target("http://127.0.0.1:8080/hook").await.is_err());
+        assert!(check_target("http://[::1]/hook").await.is_err());
+        assert!(check_target("http://169.254.169.254/latest/meta-data")
+            .await
+            .is_err());
+        assert!(check_target("http://localhost/hook").await.is_err());
+        assert!(check_target("https://1.1.1.1/hook").await.is_ok());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{
+    auth::error::{Error, ErrorBody, FieldError, WebResult},
+    db::weights::{self, Participant, PollRole},
+    notifications::digest,
+    polls::{
+        manage_polls::poll_valid_owner_authorized,
+        validation::{PollLimits, Validator},
+    },
+};
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpResponse,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use webauthn_rs::prelude::Uuid;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateRoleRequest {
+    /// Name of the group, e.g. `shareholders`; unique within the poll
+    name: String,
+    weight: f64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SetParticipantRequest {
+    /// Role of the poll the user belongs to
+    role_id: Option<Uuid>,
+    /// Personal weight, overriding the role's
+    weight: Option<f64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/roles",
+    tag = "voters",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 200, description = "The poll's voter roles, by name", body = [PollRole]),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn list_roles(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    let roles = weights::get_poll_roles(&pool, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(roles))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/polls/{poll_id}/roles",
+    tag = "voters",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    request_body = CreateRoleRequest,
+    responses(
+        (status = 201, description = "Role created", body = PollRole),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+        (status = 422, description = "Name or weight failed validation", body = ErrorBody),
+    )
+)]
+pub async fn create_role(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+    limits: Data<PollLimits>,
+    req: Json<CreateRoleRequest>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    let mut v = Validator::new();
+    v.text("name", &req.name, limits.max_option_len);
+    v.weight("weight", req.weight);
+    v.finish()?;
+
+    let role = weights::create_role(&pool, Uuid::new_v4(), poll_id, req.name.trim(), req.weight)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                Error::Validation(vec![FieldError::new(
+                    "name",
+                    "is already used by another role of this poll",
+                )])
+            }
+            _ => Error::Database(e),
+        })?;
+    Ok(HttpResponse::Created().json(role))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}/roles/{role_id}",
+    tag = "voters",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("role_id" = Uuid, Path, description = "Role id"),
+    ),
+    responses(
+        (status = 204, description = "Role deleted; its members fall back to the default weight"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll or role not found", body = ErrorBody),
+    )
+)]
+pub async fn delete_role(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, role_id) = path.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    weights::delete_role(&pool, role_id, poll_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::RoleNotFound,
+            _ => Error::Database(e),
+        })?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/polls/{poll_id}/participants",
+    tag = "voters",
+    params(("poll_id" = Uuid, Path, description = "Poll id")),
+    responses(
+        (status = 200, description = "Users with a role or personal weight, by username", body = [Participant]),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found", body = ErrorBody),
+    )
+)]
+pub async fn list_participants(
+    poll_id: Path<Uuid>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let poll_id = poll_id.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    let participants = weights::get_participants(&pool, poll_id)
+        .await
+        .map_err(Error::Database)?;
+    Ok(HttpResponse::Ok().json(participants))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/polls/{poll_id}/participants/{user_id}",
+    tag = "voters",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("user_id" = Uuid, Path, description = "User to assign"),
+    ),
+    request_body = SetParticipantRequest,
+    responses(
+        (status = 204, description = "Assignment saved; applies to votes cast from now on"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll, user or role not found", body = ErrorBody),
+        (status = 422, description = "Weight failed validation", body = ErrorBody),
+    )
+)]
+pub async fn set_participant(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+    req: Json<SetParticipantRequest>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, user_id) = path.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    if let Some(weight) = req.weight {
+        let mut v = Validator::new();
+        v.weight("weight", weight);
+        v.finish()?;
+    }
+
+    let invited = weights::set_participant(&pool, poll_id, user_id, req.role_id, req.weight)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::RoleNotFound,
+            sqlx::Error::Database(db) if db.is_foreign_key_violation() => Error::UserNotFound,
+            _ => Error::Database(e),
+        })?;
+    if invited {
+        digest::notify_invite(&pool, poll_id, user_id).await;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/polls/{poll_id}/participants/{user_id}",
+    tag = "voters",
+    params(
+        ("poll_id" = Uuid, Path, description = "Poll id"),
+        ("user_id" = Uuid, Path, description = "User to unassign"),
+    ),
+    responses(
+        (status = 204, description = "The user is back to the poll's default weight"),
+        (status = 401, description = "Caller does not own the poll", body = ErrorBody),
+        (status = 404, description = "Poll not found or the user has no assignment", body = ErrorBody),
+    )
+)]
+pub async fn delete_participant(
+    path: Path<(Uuid, Uuid)>,
+    session: Session,
+    pool: Data<PgPool>,
+) -> WebResult<HttpResponse> {
+    let (poll_id, user_id) = path.into_inner();
+    poll_valid_owner_authorized(poll_id, session, &pool).await?;
+    weights::delete_participant(&pool, poll_id, user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => Error::ParticipantNotFound,
+            _ => Error::Database(e),
+        })?;
+    Ok(HttpResponse::NoContent().finish())
+}